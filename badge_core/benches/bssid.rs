@@ -0,0 +1,58 @@
+//! Times looking up a BSSID among 10,000 already counted ones, for the sorted binary set the
+//! badge uses and the list of formatted strings it used to keep.
+//!
+//! `cargo bench -p badge_core --target <host triple>`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use badge_core::bssid::{format_bssid, Bssid, SeenBssids};
+use heapless::{String, Vec};
+
+const ENTRIES: usize = 10_000;
+const LOOKUPS: u32 = 20_000;
+
+/// Spreads BSSIDs out so they are not inserted in sorted order
+fn bssid(i: u32) -> Bssid {
+    let [a, b, c, d] = i.wrapping_mul(2_654_435_761).to_be_bytes();
+    [0x02, 0x00, a, b, c, d]
+}
+
+/// Average time of `lookup` over [`LOOKUPS`] BSSIDs, `pick` says which BSSID the `i`th one is
+fn time_lookups(pick: impl Fn(u32) -> u32, mut lookup: impl FnMut(Bssid) -> bool) -> Duration {
+    let began = Instant::now();
+    for i in 0..LOOKUPS {
+        black_box(lookup(black_box(bssid(pick(i)))));
+    }
+    began.elapsed() / LOOKUPS
+}
+
+fn main() {
+    let mut set = Box::new(SeenBssids::<ENTRIES>::new());
+    let mut strings = Box::new(Vec::<String<17>, ENTRIES>::new());
+    for i in 0..ENTRIES as u32 {
+        set.see(bssid(i));
+        strings.push(format_bssid(bssid(i))).unwrap();
+    }
+
+    //Hits look up BSSIDs already counted, going round them again if there are more lookups,
+    //misses ones that are not
+    let hit = |i| i % ENTRIES as u32;
+    let miss = |i| ENTRIES as u32 + i;
+    println!("lookups among {} BSSIDs, average per lookup", ENTRIES);
+    println!(
+        "binary set:   hit {:>10.2?}  miss {:>10.2?}",
+        time_lookups(hit, |bssid| set.contains(&bssid)),
+        time_lookups(miss, |bssid| set.contains(&bssid)),
+    );
+    println!(
+        "string list:  hit {:>10.2?}  miss {:>10.2?}",
+        time_lookups(hit, |bssid| strings.contains(&format_bssid(bssid))),
+        time_lookups(miss, |bssid| strings.contains(&format_bssid(bssid))),
+    );
+    println!(
+        "memory:       binary set {} bytes, string list {} bytes",
+        core::mem::size_of::<SeenBssids<ENTRIES>>(),
+        core::mem::size_of::<Vec<String<17>, ENTRIES>>(),
+    );
+}
@@ -0,0 +1,112 @@
+//! The badge itself: name and details, an image, the time and the sensor readings in the top bar.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use super::compositor::Compositor;
+use super::page::{Page, Redraw};
+use super::widget::{BadgeImage, NameAndDetails, StatusLine, TimeBox, TopBar};
+use super::{badge_top_bar_text, DisplayState};
+
+/// Everything on it is redrawn when it changes, the clock sends the time when it ticks over
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BadgePage;
+
+impl Page for BadgePage {
+    fn draw<D>(
+        &mut self,
+        display: &mut D,
+        state: &DisplayState,
+        redraw: &Redraw,
+        compositor: &mut Compositor,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        if redraw.full {
+            compositor.draw(display, &NameAndDetails(state.name_and_details))?;
+        }
+        if redraw.full || redraw.readings_changed {
+            let top_text = badge_top_bar_text(state.temp, state.humidity, state.wifi_count);
+            compositor.draw(display, &TopBar(&top_text))?;
+        }
+        if redraw.full || redraw.time_changed {
+            let time = TimeBox {
+                time: state.time,
+                len: state.time_len,
+            };
+            compositor.draw(display, &time)?;
+        }
+        //Only when a boot stage fails or recovers
+        if redraw.full || redraw.status_changed {
+            compositor.draw(display, &StatusLine(state.status))?;
+        }
+        //Drawn last as the name box can run under it
+        if redraw.full || redraw.image_changed {
+            compositor.draw(display, &BadgeImage(state.image))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::badge_display::display_image::DisplayImage;
+    use crate::badge_display::framebuffer::Framebuffer;
+    use crate::badge_display::tests::Sample;
+    use crate::badge_display::wifi_list::WifiListView;
+    use crate::badge_display::{HEIGHT, STATUS_LINE_BOUNDS, WIDTH};
+    use embedded_graphics::primitives::Rectangle;
+
+    #[test]
+    fn refreshes_what_changed() {
+        let sample = Sample::new();
+        let mut state = sample.state(WifiListView::new());
+        let mut display = Framebuffer::new();
+        let mut page = BadgePage;
+        let mut region = |state: &DisplayState, redraw| {
+            let mut compositor = Compositor::new();
+            page.draw(&mut display, state, &redraw, &mut compositor)
+                .unwrap();
+            compositor.region()
+        };
+
+        let screen = Rectangle::new(Point::zero(), Size::new(WIDTH, HEIGHT));
+        assert_eq!(region(&state, Redraw::full()), Some(screen));
+        assert_eq!(region(&state, Redraw::default()), None);
+        let tick = Redraw {
+            time_changed: true,
+            ..Redraw::default()
+        };
+        assert_eq!(
+            region(&state, tick),
+            Some(Rectangle::new(Point::new(0, 96), Size::new(88, 24)))
+        );
+        let status = Redraw {
+            status_changed: true,
+            ..Redraw::default()
+        };
+        assert_eq!(region(&state, status), Some(STATUS_LINE_BOUNDS));
+        //The top bar and time are refreshed together, with the status line between them
+        let both = Redraw {
+            readings_changed: true,
+            ..tick
+        };
+        assert_eq!(
+            region(&state, both),
+            Some(Rectangle::new(Point::zero(), Size::new(WIDTH, 120)))
+        );
+        //Images are refreshed over all of where any image can be, down to the bottom of the screen
+        let repo = DisplayImage::bundled().nth(1).unwrap();
+        state.image = Some(&repo);
+        let image = Redraw {
+            image_changed: true,
+            ..Redraw::default()
+        };
+        assert_eq!(
+            region(&state, image),
+            Some(Rectangle::new(Point::new(150, 24), Size::new(146, 104)))
+        );
+    }
+}
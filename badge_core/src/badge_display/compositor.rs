@@ -0,0 +1,108 @@
+//! Collects everything a page draws in a display cycle into one area to refresh.
+//!
+//! A partial refresh of the UC8151 takes about as long whatever its size, so a refresh of
+//! everything drawn at once beats one per widget. The panel is mounted on its side, so its
+//! controller takes the badge's rows 8 at a time and refreshed areas have to start and end on a
+//! multiple of 8 pixels from the top.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+
+use super::widget::Widget;
+use super::{union, HEIGHT, WIDTH};
+
+/// Refreshed areas start and end on multiples of this many pixels from the top
+pub const REFRESH_ALIGN: u32 = 8;
+
+/// Grows `area` out to whole multiples of [`REFRESH_ALIGN`] rows and cuts it to the screen.
+/// `None` if none of it is on screen
+pub fn align(area: Rectangle) -> Option<Rectangle> {
+    let screen = Rectangle::new(Point::zero(), Size::new(WIDTH, HEIGHT));
+    let area = area.intersection(&screen);
+    if area.is_zero_sized() {
+        return None;
+    }
+    //On screen so never negative
+    let top = area.top_left.y as u32 / REFRESH_ALIGN * REFRESH_ALIGN;
+    let bottom = (area.top_left.y as u32 + area.size.height).next_multiple_of(REFRESH_ALIGN);
+    Some(Rectangle::new(
+        Point::new(area.top_left.x, top as i32),
+        Size::new(area.size.width, bottom - top),
+    ))
+}
+
+/// What was drawn this display cycle
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Compositor {
+    dirty: Option<Rectangle>,
+}
+
+impl Compositor {
+    pub const fn new() -> Self {
+        Self { dirty: None }
+    }
+
+    /// Draws `widget` and marks its bounds for refreshing
+    pub fn draw<D, W>(&mut self, display: &mut D, widget: &W) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+        W: Widget,
+    {
+        widget.draw(display)?;
+        self.invalidate(widget.bounds());
+        Ok(())
+    }
+
+    /// Marks `area` for refreshing
+    pub fn invalidate(&mut self, area: Rectangle) {
+        let Some(area) = align(area) else {
+            return;
+        };
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => union(&dirty, &area),
+            None => area,
+        });
+    }
+
+    /// The one area to refresh, covering everything drawn. `None` if nothing was
+    pub fn region(&self) -> Option<Rectangle> {
+        self.dirty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rectangle(x: i32, y: i32, width: u32, height: u32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(width, height))
+    }
+
+    #[test]
+    fn areas_grow_to_whole_rows_of_eight() {
+        assert_eq!(
+            align(rectangle(0, 96, 88, 24)),
+            Some(rectangle(0, 96, 88, 24))
+        );
+        //The image starts 2 pixels under the top bar and runs to the bottom
+        assert_eq!(
+            align(rectangle(150, 26, 157, 101)),
+            Some(rectangle(150, 24, 146, 104))
+        );
+        assert_eq!(
+            align(rectangle(0, 41, 10, 1)),
+            Some(rectangle(0, 40, 10, 8))
+        );
+        assert_eq!(align(rectangle(0, HEIGHT as i32, 10, 8)), None);
+    }
+
+    #[test]
+    fn one_region_covers_everything_drawn() {
+        let mut compositor = Compositor::new();
+        assert_eq!(compositor.region(), None);
+        compositor.invalidate(rectangle(0, 24, 150, 16));
+        compositor.invalidate(rectangle(0, 96, 88, 24));
+        assert_eq!(compositor.region(), Some(rectangle(0, 24, 150, 96)));
+        compositor.invalidate(rectangle(200, 3, 10, 2));
+        assert_eq!(compositor.region(), Some(rectangle(0, 0, 210, 120)));
+    }
+}
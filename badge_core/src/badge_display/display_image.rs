@@ -0,0 +1,275 @@
+//! Images shown on the right of the badge screen.
+//!
+//! Images are kept in flash by the [`ImageStore`](crate::image_store::ImageStore) so new ones can
+//! be uploaded without a rebuild. The BMPs bundled with the firmware are only what an empty store
+//! starts out with, the same way the `.env` seeds the config.
+
+use core::fmt;
+
+use embedded_graphics::{
+    image::{Image, ImageRaw},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+use heapless::{String, Vec};
+use tinybmp::Bmp;
+
+use super::IMAGE_BOUNDS;
+
+/// Longest image name
+pub const IMAGE_NAME_LEN: usize = 16;
+/// Bytes in each row of pixels of the widest image
+pub const MAX_ROW_LEN: usize = row_len(IMAGE_BOUNDS.size.width);
+/// Bytes of pixels in the biggest image, one that fills [`IMAGE_BOUNDS`]
+pub const MAX_IMAGE_LEN: usize = MAX_ROW_LEN * IMAGE_BOUNDS.size.height as usize;
+
+/// Name, BMP and position of the images an empty store is seeded with
+const BUNDLED: [(&str, &[u8], Point); 2] = [
+    (
+        "ferris",
+        include_bytes!("../../../images/ferris_w_a_knife.bmp"),
+        Point::new(150, 26),
+    ),
+    (
+        "repo",
+        include_bytes!("../../../images/repo.bmp"),
+        Point::new(190, 26),
+    ),
+];
+
+/// Bytes in each row of an image `width` pixels wide, every row starts on a whole byte
+pub const fn row_len(width: u32) -> usize {
+    width.div_ceil(8) as usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ImageError {
+    /// Empty or bigger than [`IMAGE_BOUNDS`]
+    BadSize,
+    NameTooLong,
+    /// Not as many bytes of pixels as the size needs
+    BadPixels,
+    /// An uploaded row is not hex, not as wide as the image or past its bottom
+    BadRow,
+    /// Not a BMP that can be read
+    BadBmp,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadSize => write!(
+                f,
+                "images have to be 1x1 to {}x{}",
+                IMAGE_BOUNDS.size.width, IMAGE_BOUNDS.size.height
+            ),
+            Self::NameTooLong => write!(f, "names are up to {} bytes", IMAGE_NAME_LEN),
+            Self::BadPixels => f.write_str("pixels do not match the size"),
+            Self::BadRow => f.write_str("rows are 2 hex digits per 8 pixels of width"),
+            Self::BadBmp => f.write_str("not a BMP"),
+        }
+    }
+}
+
+/// A 1 bit image and where it goes. Rows of pixels start on a whole byte with the leftmost pixel
+/// in the top bit, 1 is white
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayImage {
+    name: String<IMAGE_NAME_LEN>,
+    size: Size,
+    /// Always keeps the whole image inside [`IMAGE_BOUNDS`]
+    position: Point,
+    pixels: Vec<u8, MAX_IMAGE_LEN>,
+}
+
+impl DisplayImage {
+    /// An image of `pixels`, at `position` or as close to it as stays inside [`IMAGE_BOUNDS`]
+    pub fn new(name: &str, size: Size, position: Point, pixels: &[u8]) -> Result<Self, ImageError> {
+        let mut image = Self::blank(name, size, position)?;
+        if pixels.len() != image.pixels.len() {
+            return Err(ImageError::BadPixels);
+        }
+        image.pixels.copy_from_slice(pixels);
+        Ok(image)
+    }
+
+    /// A white image to be filled in, see [`DisplayImage::new`]
+    pub fn blank(name: &str, size: Size, position: Point) -> Result<Self, ImageError> {
+        let max = IMAGE_BOUNDS.size;
+        if size.width == 0 || size.height == 0 || size.width > max.width || size.height > max.height
+        {
+            return Err(ImageError::BadSize);
+        }
+        let name = String::try_from(name).map_err(|_| ImageError::NameTooLong)?;
+        let furthest = IMAGE_BOUNDS.top_left + (max - size);
+        let mut pixels = Vec::new();
+        //Fits as the size was checked
+        let _ = pixels.resize(row_len(size.width) * size.height as usize, 0xFF);
+        Ok(Self {
+            name,
+            size,
+            position: position
+                .component_max(IMAGE_BOUNDS.top_left)
+                .component_min(furthest),
+            pixels,
+        })
+    }
+
+    /// Converts a BMP to 1 bit, cutting off what does not fit in [`IMAGE_BOUNDS`] from
+    /// `position`
+    pub fn from_bmp(name: &str, bmp: &[u8], position: Point) -> Result<Self, ImageError> {
+        let bmp: Bmp<BinaryColor> = Bmp::from_slice(bmp).map_err(|_| ImageError::BadBmp)?;
+        let room = Rectangle::new(position, bmp.size()).intersection(&IMAGE_BOUNDS);
+        let mut image = Self::blank(name, room.size, position)?;
+        //Drawn rather than read pixel by pixel, so colors convert the same as drawing the BMP did
+        let top_left = position - image.position;
+        let _ = Image::new(&bmp, top_left).draw(&mut Canvas(&mut image));
+        Ok(image)
+    }
+
+    /// The images bundled with the firmware
+    pub fn bundled() -> impl Iterator<Item = Self> {
+        BUNDLED
+            .iter()
+            .map(|(name, bmp, position)| Self::from_bmp(name, bmp, *position).unwrap())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Top left corner on the screen
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    pub fn bounds(&self) -> Rectangle {
+        Rectangle::new(self.position, self.size)
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Replaces row `row` of the pixels, `None` if it is past the bottom or `bytes` is not a row
+    pub fn set_row(&mut self, row: u32, bytes: &[u8]) -> Option<()> {
+        let len = row_len(self.size.width);
+        if row >= self.size.height || bytes.len() != len {
+            return None;
+        }
+        let start = row as usize * len;
+        self.pixels[start..start + len].copy_from_slice(bytes);
+        Some(())
+    }
+
+    /// Sets the pixel at `point` from the image's top left corner, points outside it are ignored
+    fn set_pixel(&mut self, point: Point, color: BinaryColor) {
+        if !Rectangle::new(Point::zero(), self.size).contains(point) {
+            return;
+        }
+        let index = point.y as usize * row_len(self.size.width) + point.x as usize / 8;
+        let bit = 0x80 >> (point.x % 8);
+        match color {
+            BinaryColor::On => self.pixels[index] |= bit,
+            BinaryColor::Off => self.pixels[index] &= !bit,
+        }
+    }
+
+    /// The pixels to draw with `embedded_graphics`
+    pub fn raw(&self) -> ImageRaw<'_, BinaryColor> {
+        ImageRaw::new(&self.pixels, self.size.width)
+    }
+}
+
+/// Draws onto an image's pixels, from its top left corner
+struct Canvas<'a>(&'a mut DisplayImage);
+
+impl OriginDimensions for Canvas<'_> {
+    fn size(&self) -> Size {
+        self.0.size
+    }
+}
+
+impl DrawTarget for Canvas<'_> {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            self.0.set_pixel(point, color);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::badge_display::framebuffer::Framebuffer;
+
+    #[test]
+    fn bundled_images_draw_like_the_bmps() {
+        for (image, (_, bmp, position)) in DisplayImage::bundled().zip(BUNDLED) {
+            let bmp: Bmp<BinaryColor> = Bmp::from_slice(bmp).unwrap();
+            let mut expected = Framebuffer::new();
+            Image::new(&bmp, position).draw(&mut expected).unwrap();
+            let mut drawn = Framebuffer::new();
+            Image::new(&image.raw(), image.position())
+                .draw(&mut drawn)
+                .unwrap();
+            assert!(expected
+                .bounding_box()
+                .points()
+                .all(|p| expected.pixel(p) == drawn.pixel(p)));
+            assert!(IMAGE_BOUNDS.contains(image.bounds().bottom_right().unwrap()));
+        }
+    }
+
+    #[test]
+    fn images_are_kept_inside_the_bounds() {
+        let image = DisplayImage::blank("qr", Size::new(40, 40), Point::new(400, 0)).unwrap();
+        assert_eq!(
+            image.bounds(),
+            Rectangle::new(
+                Point::new(
+                    IMAGE_BOUNDS.top_left.x + IMAGE_BOUNDS.size.width as i32 - 40,
+                    26
+                ),
+                Size::new(40, 40)
+            )
+        );
+        assert_eq!(image.pixels().len(), 5 * 40);
+        assert_eq!(
+            DisplayImage::blank("wide", Size::new(200, 10), Point::zero()),
+            Err(ImageError::BadSize)
+        );
+        assert_eq!(
+            DisplayImage::blank("a name far too long", Size::new(8, 8), Point::zero()),
+            Err(ImageError::NameTooLong)
+        );
+        assert_eq!(
+            DisplayImage::new("dot", Size::new(9, 2), Point::zero(), &[0; 3]),
+            Err(ImageError::BadPixels)
+        );
+    }
+
+    #[test]
+    fn rows_are_whole_bytes() {
+        let mut image = DisplayImage::blank("dot", Size::new(9, 2), Point::zero()).unwrap();
+        assert_eq!(image.set_row(1, &[0x7F, 0x00]), Some(()));
+        assert_eq!(image.set_row(2, &[0x7F, 0x00]), None);
+        assert_eq!(image.set_row(0, &[0x7F]), None);
+        assert_eq!(image.pixels(), &[0xFF, 0xFF, 0x7F, 0x00]);
+        image.set_pixel(Point::new(8, 0), BinaryColor::Off);
+        assert_eq!(image.pixels()[..2], [0xFF, 0x7F]);
+    }
+}
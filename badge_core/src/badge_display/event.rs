@@ -0,0 +1,153 @@
+//! What other tasks tell the display, and what it has to redraw for it.
+//!
+//! The display sleeps until it is sent a [`DisplayEvent`] or a page's timed refresh is due, so
+//! every reason to redraw is one of these instead of a flag it polls.
+
+use heapless::String;
+
+use super::page::Redraw;
+use crate::time::format::CLOCK_STRING_LEN;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisplayEvent {
+    /// Another page is shown, the screen is cleared for it
+    ScreenChanged,
+    /// The wifi list's cursor moved, the page redraws what that changed
+    CursorMoved,
+    /// A scan asked for with a button finished, everything is redrawn with the new history
+    Scanned,
+    WifiCount(u32),
+    /// In Fahrenheit and percent
+    Sensors {
+        temp: u8,
+        humidity: u8,
+    },
+    /// The time as the clock format shows it, sent when it changes
+    Time(String<CLOCK_STRING_LEN>),
+    /// Another image was picked, by its index in the image store
+    Image(usize),
+    /// Images were uploaded or deleted, the shown one is read from flash again
+    ImagesChanged,
+    /// A boot stage failed or recovered, see `BootStatus`
+    StatusChanged,
+}
+
+impl DisplayEvent {
+    /// Sent because a button was pressed
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            Self::ScreenChanged
+                | Self::CursorMoved
+                | Self::Scanned
+                | Self::Image(_)
+                | Self::ImagesChanged
+        )
+    }
+}
+
+/// What the display shows that it is sent in events
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Readings {
+    pub temp: u8,
+    pub humidity: u8,
+    pub wifi_count: u32,
+    pub time: String<CLOCK_STRING_LEN>,
+    /// Index of the image shown in the image store
+    pub image: usize,
+}
+
+impl Readings {
+    /// Takes in `event`, marking what it changed in `redraw`. Events that change nothing mark
+    /// nothing, so they do not cost a refresh
+    pub fn apply(&mut self, event: DisplayEvent, redraw: &mut Redraw) {
+        redraw.input |= event.is_input();
+        match event {
+            DisplayEvent::ScreenChanged => {
+                redraw.clear = true;
+                redraw.full = true;
+            }
+            //The page compares the cursor to where it was drawn
+            DisplayEvent::CursorMoved => {}
+            DisplayEvent::Scanned => redraw.full = true,
+            DisplayEvent::WifiCount(count) => {
+                redraw.readings_changed |= count != self.wifi_count;
+                self.wifi_count = count;
+            }
+            DisplayEvent::Sensors { temp, humidity } => {
+                redraw.readings_changed |= (temp, humidity) != (self.temp, self.humidity);
+                self.temp = temp;
+                self.humidity = humidity;
+            }
+            DisplayEvent::Time(time) => {
+                redraw.time_changed |= time != self.time;
+                self.time = time;
+            }
+            DisplayEvent::Image(image) => {
+                redraw.image_changed |= image != self.image;
+                self.image = image;
+            }
+            DisplayEvent::ImagesChanged => redraw.image_changed = true,
+            DisplayEvent::StatusChanged => redraw.status_changed = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_mark_what_they_change() {
+        let mut readings = Readings::default();
+        let mut redraw = Redraw::default();
+        readings.apply(DisplayEvent::WifiCount(0), &mut redraw);
+        assert_eq!(redraw, Redraw::default());
+        //Only counts as a button press, the page draws the cursor
+        readings.apply(DisplayEvent::CursorMoved, &mut redraw);
+        assert_eq!(
+            redraw,
+            Redraw {
+                input: true,
+                ..Redraw::default()
+            }
+        );
+
+        readings.apply(DisplayEvent::WifiCount(3), &mut redraw);
+        readings.apply(
+            DisplayEvent::Time(String::try_from("09:41 AM").unwrap()),
+            &mut redraw,
+        );
+        assert!(redraw.readings_changed && redraw.time_changed);
+        assert!(!redraw.full && !redraw.clear);
+        assert_eq!(readings.wifi_count, 3);
+        assert_eq!(readings.time, "09:41 AM");
+
+        let mut redraw = Redraw::default();
+        readings.apply(
+            DisplayEvent::Sensors {
+                temp: 72,
+                humidity: 40,
+            },
+            &mut redraw,
+        );
+        readings.apply(DisplayEvent::Image(1), &mut redraw);
+        readings.apply(DisplayEvent::ScreenChanged, &mut redraw);
+        assert!(redraw.readings_changed && redraw.image_changed);
+        assert!(redraw.full && redraw.clear);
+    }
+
+    #[test]
+    fn events_sent_while_busy_add_up() {
+        let mut readings = Readings::default();
+        let mut redraw = Redraw::default();
+        //The count went up and back down before the display got to it
+        readings.apply(DisplayEvent::WifiCount(5), &mut redraw);
+        readings.apply(DisplayEvent::WifiCount(0), &mut redraw);
+        readings.apply(DisplayEvent::StatusChanged, &mut redraw);
+        assert!(redraw.readings_changed && redraw.status_changed);
+        assert!(!redraw.input);
+        assert_eq!(readings.wifi_count, 0);
+    }
+}
@@ -0,0 +1,73 @@
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use super::{HEIGHT, WIDTH};
+
+const BUFFER_LEN: usize = (WIDTH * HEIGHT) as usize / 8;
+
+/// An in memory 1 bit display the same size as the badge's. Used to render screens off the badge
+/// for the simulator and tests
+#[derive(Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    buffer: [u8; BUFFER_LEN],
+}
+
+impl Framebuffer {
+    /// A framebuffer with every pixel set to `On`, which is white on the badge
+    pub fn new() -> Self {
+        Self {
+            buffer: [0xFF; BUFFER_LEN],
+        }
+    }
+
+    pub fn pixel(&self, point: Point) -> Option<BinaryColor> {
+        let index = Self::index(point)?;
+        let on = self.buffer[index / 8] & (0x80 >> (index % 8)) != 0;
+        Some(BinaryColor::from(on))
+    }
+
+    /// Iterates every row of pixels top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = impl Iterator<Item = BinaryColor> + '_> + '_ {
+        (0..HEIGHT as i32)
+            .map(move |y| (0..WIDTH as i32).map(move |x| self.pixel(Point::new(x, y)).unwrap()))
+    }
+
+    fn index(point: Point) -> Option<usize> {
+        if point.x < 0 || point.y < 0 || point.x >= WIDTH as i32 || point.y >= HEIGHT as i32 {
+            return None;
+        }
+        Some(point.y as usize * WIDTH as usize + point.x as usize)
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(WIDTH, HEIGHT)
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(index) = Self::index(point) {
+                let mask = 0x80 >> (index % 8);
+                match color {
+                    BinaryColor::On => self.buffer[index / 8] |= mask,
+                    BinaryColor::Off => self.buffer[index / 8] &= !mask,
+                }
+            }
+        }
+        Ok(())
+    }
+}
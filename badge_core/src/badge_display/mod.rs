@@ -0,0 +1,771 @@
+pub mod badge;
+pub mod compositor;
+pub mod display_image;
+pub mod event;
+pub mod framebuffer;
+pub mod page;
+pub mod refresh;
+pub mod widget;
+pub mod wifi_detail;
+pub mod wifi_list;
+pub mod wifi_stats;
+
+use compositor::Compositor;
+use display_image::DisplayImage;
+use embedded_graphics::{
+    image::Image,
+    mono_font::{ascii::*, MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Baseline, Text},
+};
+use embedded_text::{
+    alignment::HorizontalAlignment,
+    style::{HeightMode, TextBoxStyleBuilder},
+    TextBox,
+};
+use heapless::String;
+use page::{Pages, Redraw};
+use wifi_list::{WifiListView, WIFI_LIST_ROWS};
+
+use crate::bssid::format_bssid;
+use crate::helpers::easy_format;
+use crate::scan::{ScanHistory, ScanRecord, SCAN_HISTORY_LEN};
+use crate::stats::{signal_bars, WifiStats, CHANNELS};
+use crate::time::datetime_from_unix;
+use crate::time::tz::TimeZone;
+
+/// Width of the Badger 2040 W's UC8151 display
+pub const WIDTH: u32 = 296;
+/// Height of the Badger 2040 W's UC8151 display
+pub const HEIGHT: u32 = 128;
+/// Height of the top bar and each row of the wifi list
+const ROW_HEIGHT: u32 = 24;
+/// The bar across the top of every screen
+pub const TOP_BAR_BOUNDS: Rectangle = Rectangle::new(Point::zero(), Size::new(WIDTH, ROW_HEIGHT));
+/// Everything under the top bar, where the wifi screens draw
+pub const BODY_BOUNDS: Rectangle = Rectangle::new(
+    Point::new(0, ROW_HEIGHT as i32),
+    Size::new(WIDTH, HEIGHT - ROW_HEIGHT),
+);
+/// Name and details go under the status line, the box grows down to fit the text
+const NAME_AND_DETAILS_TOP_LEFT: Point = Point::new(0, 40);
+/// The time box is in the bottom left corner of the badge screen
+const TIME_BOX_TOP_LEFT: Point = Point::new(0, 96);
+/// Right of the time box and under the top bar, images are kept inside this
+pub const IMAGE_BOUNDS: Rectangle = Rectangle::new(Point::new(150, 26), Size::new(146, 102));
+/// The time box has to stay left of the images
+const TIME_BOX_MAX_WIDTH: u32 = IMAGE_BOUNDS.top_left.x as u32;
+/// Space between the time box's border and the text
+const TIME_BOX_PADDING: u32 = 8;
+/// Fonts tried for the time, biggest first, until the longest time for the format fits
+const TIME_FONTS: [&MonoFont; 4] = [&FONT_9X18_BOLD, &FONT_7X13_BOLD, &FONT_6X13_BOLD, &FONT_5X8];
+/// The status line sits between the top bar and the name, left of the images
+pub const STATUS_LINE_BOUNDS: Rectangle = Rectangle::new(Point::new(0, 24), Size::new(150, 16));
+/// Channel histogram on the left of the statistics screen, labels go under it
+const CHANNEL_BARS_BOUNDS: Rectangle = Rectangle::new(Point::new(5, 30), Size::new(140, 56));
+/// Open and secured counts under the channel histogram
+const SECURITY_LINE_BOUNDS: Rectangle = Rectangle::new(Point::new(0, 106), Size::new(150, 16));
+/// Strongest networks on the right of the statistics screen, one row each
+const STRONGEST_BOUNDS: Rectangle = Rectangle::new(Point::new(152, 28), Size::new(144, 42));
+/// New networks per hour sparkline under the strongest networks
+const PER_HOUR_BOUNDS: Rectangle = Rectangle::new(Point::new(152, 84), Size::new(144, 40));
+/// Height of each line of the wifi detail screen
+const DETAIL_LINE_HEIGHT: i32 = 18;
+
+/// Which page is shown, see [`page::Navigator`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Screen {
+    Badge,
+    WifiList,
+    /// Everything known about the network selected on the wifi list
+    WifiDetail,
+    WifiStats,
+}
+
+/// Everything the screens show. The firmware fills this from its shared state, the simulator
+/// from sample data
+pub struct DisplayState<'a> {
+    /// Name and details separated by a new line
+    pub name_and_details: &'a str,
+    pub temp: u8,
+    pub humidity: u8,
+    pub wifi_count: u32,
+    pub time: &'a str,
+    /// Longest `time` can be, the time box is sized to fit it. See `ClockFormat::text_len`
+    pub time_len: usize,
+    /// `None` when there are no images in flash
+    pub image: Option<&'a DisplayImage>,
+    /// What is not working, see `BootStatus::status_line`. Empty hides the status line
+    pub status: &'a str,
+    /// Listed newest first on the wifi list
+    pub history: &'a ScanHistory<SCAN_HISTORY_LEN>,
+    pub list: WifiListView,
+    /// For the times on the wifi detail screen
+    pub time_zone: &'a TimeZone,
+    pub stats: &'a WifiStats,
+}
+
+// Note we're setting the Text color to `Off`. The driver is set up to treat Off as Black so that BMPs work as expected.
+pub fn character_style() -> MonoTextStyle<'static, BinaryColor> {
+    MonoTextStyle::new(&FONT_9X18_BOLD, BinaryColor::Off)
+}
+
+/// White box with a black border used for the top bar, time and wifi rows
+fn boxed_style() -> PrimitiveStyle<BinaryColor> {
+    PrimitiveStyleBuilder::default()
+        .stroke_color(BinaryColor::Off)
+        .fill_color(BinaryColor::On)
+        .stroke_width(1)
+        .build()
+}
+
+pub fn badge_top_bar_text(temp: u8, humidity: u8, wifi_count: u32) -> String<64> {
+    easy_format::<64>(format_args!(
+        "{}F {}% Wifi found: {}",
+        temp, humidity, wifi_count
+    ))
+}
+
+/// `page` of `pages` is shown after the count, see [`WifiListView::page`]
+pub fn wifi_list_top_bar_text(wifi_count: u32, (page, pages): (usize, usize)) -> String<64> {
+    easy_format::<64>(format_args!(
+        "Wifi found: {} {}/{}",
+        wifi_count, page, pages
+    ))
+}
+
+pub fn wifi_stats_top_bar_text(wifi_count: u32, today: u32) -> String<64> {
+    easy_format::<64>(format_args!("Wifi found: {} Today: {}", wifi_count, today))
+}
+
+/// Clears the whole display to white
+pub fn clear_screen<D>(display: &mut D) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    Rectangle::new(Point::new(0, 0), Size::new(WIDTH, HEIGHT))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)
+}
+
+/// The name and details text box, as tall as the text needs
+fn name_and_details_box(text: &str) -> TextBox<'_, MonoTextStyle<'static, BinaryColor>> {
+    let textbox_style = TextBoxStyleBuilder::new()
+        .height_mode(HeightMode::FitToText)
+        .alignment(HorizontalAlignment::Left)
+        .paragraph_spacing(6)
+        .build();
+    let bounds = Rectangle::new(NAME_AND_DETAILS_TOP_LEFT, Size::new(WIDTH - 75, 0));
+    TextBox::with_textbox_style(text, bounds, character_style(), textbox_style)
+}
+
+/// Where [`draw_name_and_details`] draws `text`
+pub fn name_and_details_bounds(text: &str) -> Rectangle {
+    name_and_details_box(text).bounding_box()
+}
+
+/// Draws the name and details text on the left of the badge screen. Returns the area drawn
+pub fn draw_name_and_details<D>(display: &mut D, text: &str) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let name_and_detail_box = name_and_details_box(text);
+    // Fill the bounds with the opposite color so we can read the text.
+    let bounds = name_and_detail_box.bounding_box();
+    bounds
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    name_and_detail_box.draw(display)?;
+    Ok(bounds)
+}
+
+/// Draws the bar across the top of the screen. Returns the area drawn
+pub fn draw_top_bar<D>(display: &mut D, text: &str) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    TOP_BAR_BOUNDS.into_styled(boxed_style()).draw(display)?;
+    Text::new(text, Point::new(8, 16), character_style()).draw(display)?;
+    Ok(TOP_BAR_BOUNDS)
+}
+
+/// Biggest font and box width that fit `text_len` characters of time left of the images
+fn time_box_layout(text_len: usize) -> (&'static MonoFont<'static>, u32) {
+    let width_for = |font: &MonoFont| {
+        let character_width = font.character_size.width + font.character_spacing;
+        text_len as u32 * character_width + 2 * TIME_BOX_PADDING
+    };
+    let font = TIME_FONTS
+        .iter()
+        .find(|font| width_for(font) <= TIME_BOX_MAX_WIDTH)
+        .unwrap_or(&TIME_FONTS[TIME_FONTS.len() - 1]);
+    (font, width_for(font).min(TIME_BOX_MAX_WIDTH))
+}
+
+/// Where [`draw_time`] draws a time of up to `time_len` characters
+pub fn time_bounds(time_len: usize) -> Rectangle {
+    let (_, width) = time_box_layout(time_len);
+    Rectangle::new(TIME_BOX_TOP_LEFT, Size::new(width, ROW_HEIGHT))
+}
+
+/// Draws the time box in the bottom left of the badge screen, sized for `time_len` characters so
+/// it stays the same size as the time changes. Returns the area drawn
+pub fn draw_time<D>(display: &mut D, time: &str, time_len: usize) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let time_len = time_len.max(time.len());
+    let (font, _) = time_box_layout(time_len);
+    //The bounds of the box for time and refresh area
+    let time_bounds = time_bounds(time_len);
+    time_bounds.into_styled(boxed_style()).draw(display)?;
+
+    Text::with_baseline(
+        time,
+        TIME_BOX_TOP_LEFT + Point::new(TIME_BOX_PADDING as i32, ROW_HEIGHT as i32 / 2),
+        MonoTextStyle::new(font, BinaryColor::Off),
+        Baseline::Middle,
+    )
+    .draw(display)?;
+    Ok(time_bounds)
+}
+
+/// Draws the status line under the top bar of the badge screen, an empty `status` clears it.
+/// Returns the area drawn
+pub fn draw_status_line<D>(display: &mut D, status: &str) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    STATUS_LINE_BOUNDS
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    Text::with_baseline(
+        status,
+        Point::new(4, STATUS_LINE_BOUNDS.center().y),
+        MonoTextStyle::new(&FONT_6X10, BinaryColor::Off),
+        Baseline::Middle,
+    )
+    .draw(display)?;
+    Ok(STATUS_LINE_BOUNDS)
+}
+
+/// Draws the image on the right of the badge screen, clearing wherever the previous one was.
+/// Returns the area drawn, all of [`IMAGE_BOUNDS`]
+pub fn draw_image<D>(display: &mut D, image: Option<&DisplayImage>) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    IMAGE_BOUNDS
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    if let Some(image) = image {
+        Image::new(&image.raw(), image.position()).draw(display)?;
+    }
+    Ok(IMAGE_BOUNDS)
+}
+
+/// Smallest rectangle covering both rectangles
+pub(crate) fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let top_left = a.top_left.component_min(b.top_left);
+    let bottom_right = (a.top_left + a.size).component_max(b.top_left + b.size);
+    Rectangle::with_corners(top_left, bottom_right - Point::new(1, 1))
+}
+
+/// What a network is called on screen, hidden networks do not send their SSID
+pub fn network_name(record: &ScanRecord) -> &str {
+    match record.ssid.trim() {
+        "" => "(hidden)",
+        ssid => ssid,
+    }
+}
+
+/// The first `chars` characters of `text`
+fn truncate(text: &str, chars: usize) -> &str {
+    text.char_indices()
+        .nth(chars)
+        .map_or(text, |(end, _)| &text[..end])
+}
+
+/// Where [`draw_wifi_row`] draws `row`
+pub fn wifi_row_bounds(row: usize) -> Rectangle {
+    let y_offset = ((row as u32 + 1) * ROW_HEIGHT) as i32;
+    Rectangle::new(Point::new(0, y_offset), Size::new(WIDTH, ROW_HEIGHT))
+}
+
+/// Draws one network name of the wifi list, row 0 is right under the top bar. The `selected` row
+/// is drawn white on black as the cursor. Returns the area drawn
+pub fn draw_wifi_row<D>(
+    display: &mut D,
+    row: usize,
+    ssid: &str,
+    selected: bool,
+) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let wifi_bounds = wifi_row_bounds(row);
+    let y_offset = wifi_bounds.top_left.y;
+    let text_style = if selected {
+        wifi_bounds
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+            .draw(display)?;
+        MonoTextStyle::new(&FONT_9X18_BOLD, BinaryColor::On)
+    } else {
+        wifi_bounds.into_styled(boxed_style()).draw(display)?;
+        character_style()
+    };
+    Text::new(ssid.trim(), Point::new(8, y_offset + 16), text_style).draw(display)?;
+    Ok(wifi_bounds)
+}
+
+/// Draws the page of the wifi list the cursor is on, clearing rows past the end of the history.
+/// Returns the area drawn
+pub fn draw_wifi_list<D, const N: usize>(
+    display: &mut D,
+    history: &ScanHistory<N>,
+    list: WifiListView,
+) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let bounds = BODY_BOUNDS;
+    bounds
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    if history.is_empty() {
+        draw_wifi_row(display, 0, "Press B to scan", false)?;
+    }
+    let page = history.iter().skip(list.page_start()).take(WIFI_LIST_ROWS);
+    for (row, record) in page.enumerate() {
+        draw_wifi_row(
+            display,
+            row,
+            network_name(record),
+            row == list.selected_row(),
+        )?;
+    }
+    Ok(bounds)
+}
+
+/// When a network was seen in local time, unknown if it was before the clock was set
+fn seen_text(time: Option<u32>, time_zone: &TimeZone) -> String<32> {
+    match time {
+        Some(time) => {
+            let local = time_zone.to_local(&datetime_from_unix(time as i64));
+            easy_format::<32>(format_args!(
+                "{}-{:02}-{:02} {:02}:{:02}",
+                local.year, local.month, local.day, local.hour, local.minute
+            ))
+        }
+        None => String::try_from("unknown").unwrap(),
+    }
+}
+
+/// Draws the wifi detail screen under the top bar: the BSSID, signal, channel, security and when
+/// `record` was first and last seen. Returns the area drawn
+pub fn draw_wifi_detail<D>(
+    display: &mut D,
+    record: &ScanRecord,
+    time_zone: &TimeZone,
+) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let bounds = BODY_BOUNDS;
+    bounds
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    let text_style = MonoTextStyle::new(&FONT_7X13, BinaryColor::Off);
+    let lines: [(&str, String<32>); 5] = [
+        (
+            "BSSID",
+            easy_format::<32>(format_args!("{}", format_bssid(record.bssid))),
+        ),
+        (
+            "Signal",
+            easy_format::<32>(format_args!("{} dBm", record.rssi)),
+        ),
+        (
+            "Channel",
+            easy_format::<32>(format_args!("{}, {}", record.channel, record.security)),
+        ),
+        ("First seen", seen_text(record.first_seen, time_zone)),
+        ("Last seen", seen_text(record.last_seen, time_zone)),
+    ];
+    for (index, (label, value)) in lines.iter().enumerate() {
+        let y = bounds.top_left.y + 4 + index as i32 * DETAIL_LINE_HEIGHT;
+        let line = easy_format::<48>(format_args!("{:<11}{}", label, value));
+        let end = Text::with_baseline(&line, Point::new(8, y), text_style, Baseline::Top)
+            .draw(display)?;
+        if *label == "Signal" {
+            draw_signal_bars(display, end + Point::new(8, 10), signal_bars(record.rssi))?;
+        }
+    }
+    Ok(bounds)
+}
+
+/// Draws `values` as bars along the bottom of `bounds`, scaled so the biggest fills its height.
+/// Anything above zero gets at least one pixel so it does not look the same as nothing
+fn draw_bars<D>(
+    display: &mut D,
+    bounds: Rectangle,
+    values: &[u16],
+    bar_width: u32,
+    gap: u32,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let max = values.iter().copied().max().unwrap_or_default().max(1) as u32;
+    let bottom = bounds.top_left.y + bounds.size.height as i32;
+    for (index, value) in values.iter().enumerate() {
+        let height = (*value as u32 * bounds.size.height).div_ceil(max);
+        let x = bounds.top_left.x + (index as u32 * (bar_width + gap)) as i32;
+        Rectangle::new(
+            Point::new(x, bottom - height as i32),
+            Size::new(bar_width, height),
+        )
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+        .draw(display)?;
+    }
+    Ok(())
+}
+
+/// Draws 0 to 4 signal bars of rising height with their bottom left corner at `bottom_left`,
+/// missing bars are outlined
+fn draw_signal_bars<D>(display: &mut D, bottom_left: Point, bars: u8) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    for bar in 0..4u8 {
+        let height = 3 * (bar as u32 + 1);
+        let style = if bar < bars {
+            PrimitiveStyle::with_fill(BinaryColor::Off)
+        } else {
+            PrimitiveStyle::with_stroke(BinaryColor::Off, 1)
+        };
+        Rectangle::new(
+            bottom_left + Point::new(5 * bar as i32, 1 - height as i32),
+            Size::new(4, height),
+        )
+        .into_styled(style)
+        .draw(display)?;
+    }
+    Ok(())
+}
+
+/// Draws the statistics screen under the top bar: a histogram of access points per channel and
+/// how many are open or secured on the left, the strongest networks and new networks per hour on
+/// the right. Returns the area drawn
+pub fn draw_wifi_stats<D>(display: &mut D, stats: &WifiStats) -> Result<Rectangle, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let bounds = BODY_BOUNDS;
+    bounds
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    let small_text = MonoTextStyle::new(&FONT_6X10, BinaryColor::Off);
+
+    draw_bars(display, CHANNEL_BARS_BOUNDS, &stats.channels, 8, 2)?;
+    let label_y = CHANNEL_BARS_BOUNDS.top_left.y + CHANNEL_BARS_BOUNDS.size.height as i32 + 2;
+    //Only the channels that do not overlap each other, the rest would not fit
+    for channel in [1, 6, 11, CHANNELS] {
+        let x = CHANNEL_BARS_BOUNDS.top_left.x + 10 * (channel as i32 - 1);
+        let label = easy_format::<4>(format_args!("{}", channel));
+        Text::with_baseline(&label, Point::new(x, label_y), small_text, Baseline::Top)
+            .draw(display)?;
+    }
+    let security = easy_format::<32>(format_args!(
+        "Open {} Secured {}",
+        stats.open, stats.secured
+    ));
+    Text::with_baseline(
+        &security,
+        Point::new(4, SECURITY_LINE_BOUNDS.center().y),
+        small_text,
+        Baseline::Middle,
+    )
+    .draw(display)?;
+
+    let row_height = STRONGEST_BOUNDS.size.height as i32 / 3;
+    for (row, network) in stats.strongest.iter().enumerate() {
+        let top = STRONGEST_BOUNDS.top_left + Point::new(0, row as i32 * row_height);
+        //Cut to what fits left of the signal bars
+        let ssid = truncate(&network.ssid, 18);
+        Text::with_baseline(ssid, top, small_text, Baseline::Top).draw(display)?;
+        draw_signal_bars(
+            display,
+            top + Point::new(STRONGEST_BOUNDS.size.width as i32 - 22, row_height - 3),
+            signal_bars(network.rssi),
+        )?;
+    }
+
+    Text::with_baseline(
+        "New per hour",
+        PER_HOUR_BOUNDS.top_left - Point::new(0, 2),
+        small_text,
+        Baseline::Bottom,
+    )
+    .draw(display)?;
+    draw_bars(display, PER_HOUR_BOUNDS, &stats.per_hour, 5, 1)?;
+    Ok(bounds)
+}
+
+/// Draws a whole screen from scratch
+pub fn draw_screen<D>(display: &mut D, screen: Screen, state: &DisplayState) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    clear_screen(display)?;
+    Pages::default().draw(
+        screen,
+        display,
+        state,
+        &Redraw::full(),
+        &mut Compositor::new(),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::framebuffer::Framebuffer;
+    use super::*;
+
+    /// What a [`DisplayState`] borrows
+    pub(crate) struct Sample {
+        history: ScanHistory<SCAN_HISTORY_LEN>,
+        time_zone: TimeZone,
+        stats: WifiStats,
+        image: DisplayImage,
+    }
+
+    impl Sample {
+        pub(crate) fn new() -> Self {
+            Self {
+                history: ScanHistory::new(),
+                time_zone: TimeZone::utc(),
+                stats: WifiStats::default(),
+                image: DisplayImage::bundled().next().unwrap(),
+            }
+        }
+
+        /// A history of `networks` named `net 0` (the oldest) and up
+        pub(crate) fn with_networks(networks: u8) -> Self {
+            let mut sample = Self::new();
+            for i in 0..networks {
+                sample.history.record(ScanRecord::new(
+                    easy_format::<8>(format_args!("net {}", i)).as_bytes(),
+                    [0x3c, 0x84, 0x6a, 0, 0, i],
+                    -61,
+                    6,
+                    crate::scan::SecurityFlags(0x11),
+                    Some(1_723_801_260 + i as u32 * 60),
+                ));
+            }
+            sample
+        }
+
+        pub(crate) fn state(&self, list: WifiListView) -> DisplayState<'_> {
+            DisplayState {
+                name_and_details: "Ferris\nRustacean",
+                temp: 72,
+                humidity: 40,
+                wifi_count: 12,
+                time: "09:05 AM",
+                time_len: 8,
+                image: Some(&self.image),
+                status: "",
+                history: &self.history,
+                list,
+                time_zone: &self.time_zone,
+                stats: &self.stats,
+            }
+        }
+    }
+
+    #[test]
+    fn widgets_report_their_bounds() {
+        let mut display = Framebuffer::new();
+        let top = draw_top_bar(&mut display, "top").unwrap();
+        assert_eq!(top, Rectangle::new(Point::zero(), Size::new(WIDTH, 24)));
+        let time = draw_time(&mut display, "09:05 AM", 8).unwrap();
+        assert_eq!(time, Rectangle::new(Point::new(0, 96), Size::new(88, 24)));
+        let row = draw_wifi_row(&mut display, 1, "venue", false).unwrap();
+        assert_eq!(row, Rectangle::new(Point::new(0, 48), Size::new(WIDTH, 24)));
+    }
+
+    #[test]
+    fn time_box_fits_the_clock_format() {
+        let mut display = Framebuffer::new();
+        //Sized for the format, not for what is shown right now
+        let no_wifi = draw_time(&mut display, "No Wifi", 8).unwrap();
+        assert_eq!(no_wifi.size, Size::new(88, 24));
+        let seconds = draw_time(&mut display, "18:30:05", 8).unwrap();
+        assert_eq!(seconds.size, Size::new(88, 24));
+        let with_seconds = draw_time(&mut display, "06:30:05 PM", 11).unwrap();
+        assert_eq!(with_seconds.size, Size::new(115, 24));
+        //Too long for the big font so a smaller one is used to stay left of the images
+        let everything = draw_time(&mut display, "Fri 2024-08-16 06:30:05 PM", 26).unwrap();
+        assert_eq!(everything.size, Size::new(146, 24));
+        for len in 0..=crate::time::format::CLOCK_STRING_LEN {
+            assert!(time_box_layout(len).1 <= TIME_BOX_MAX_WIDTH);
+        }
+    }
+
+    #[test]
+    fn badge_screen_draws_boxes_and_image() {
+        let mut display = Framebuffer::new();
+        let sample = Sample::new();
+        draw_screen(
+            &mut display,
+            Screen::Badge,
+            &sample.state(WifiListView::new()),
+        )
+        .unwrap();
+        //Top bar and time box borders are black
+        assert_eq!(display.pixel(Point::new(0, 0)), Some(BinaryColor::Off));
+        assert_eq!(display.pixel(Point::new(87, 119)), Some(BinaryColor::Off));
+        //Inside the time box but away from the text is white
+        assert_eq!(display.pixel(Point::new(2, 98)), Some(BinaryColor::On));
+        assert!(display
+            .bounding_box()
+            .intersection(&IMAGE_BOUNDS)
+            .points()
+            .any(|p| display.pixel(p) == Some(BinaryColor::Off)));
+    }
+
+    #[test]
+    fn wifi_list_draws_a_row_per_network() {
+        let sample = Sample::with_networks(2);
+        let mut display = Framebuffer::new();
+        draw_screen(
+            &mut display,
+            Screen::WifiList,
+            &sample.state(WifiListView::new()),
+        )
+        .unwrap();
+        //The first row is the cursor, the second has a bottom border
+        assert_eq!(display.pixel(Point::new(2, 26)), Some(BinaryColor::Off));
+        assert_eq!(display.pixel(Point::new(2, 50)), Some(BinaryColor::On));
+        assert_eq!(display.pixel(Point::new(10, 71)), Some(BinaryColor::Off));
+        //Nothing below the second row
+        assert_eq!(display.pixel(Point::new(10, 100)), Some(BinaryColor::On));
+    }
+
+    #[test]
+    fn wifi_list_shows_the_cursors_page() {
+        let sample = Sample::with_networks(10);
+        let mut list = WifiListView::new();
+        for _ in 0..9 {
+            list.down(sample.history.len());
+        }
+        let mut display = Framebuffer::new();
+        draw_screen(&mut display, Screen::WifiList, &sample.state(list)).unwrap();
+        //The last page has two networks and the cursor is on the second
+        assert_eq!(display.pixel(Point::new(2, 26)), Some(BinaryColor::On));
+        assert_eq!(display.pixel(Point::new(2, 50)), Some(BinaryColor::Off));
+        assert_eq!(display.pixel(Point::new(10, 100)), Some(BinaryColor::On));
+        assert_eq!(
+            wifi_list_top_bar_text(12, list.page(10)),
+            "Wifi found: 12 3/3"
+        );
+    }
+
+    #[test]
+    fn wifi_detail_shows_the_selected_network() {
+        let sample = Sample::with_networks(3);
+        let mut list = WifiListView::new();
+        list.down(3);
+        let record = sample.history.get(list.selected()).unwrap();
+        assert_eq!(network_name(record), "net 1");
+        assert_eq!(
+            seen_text(record.first_seen, &sample.time_zone),
+            "2024-08-16 09:42"
+        );
+        assert_eq!(seen_text(None, &sample.time_zone), "unknown");
+
+        let mut display = Framebuffer::new();
+        draw_screen(&mut display, Screen::WifiDetail, &sample.state(list)).unwrap();
+        //Three of the four signal bars for -61 dBm right of the signal line
+        let black = |x, y| display.pixel(Point::new(x, y)) == Some(BinaryColor::Off);
+        let signal_end = 8 + 7 * "Signal     -61 dBm".len() as i32 + 8;
+        assert!(black(signal_end + 11, 52) && !black(signal_end + 17, 52));
+    }
+
+    #[test]
+    fn hidden_networks_have_a_name() {
+        let record = ScanRecord::new(b"\0\0", [0; 6], -70, 1, Default::default(), None);
+        assert_eq!(network_name(&record), "(hidden)");
+    }
+
+    #[test]
+    fn stats_screen_scales_the_bars() {
+        let mut sample = Sample::new();
+        let stats = &mut sample.stats;
+        *stats = WifiStats {
+            all_time: 40,
+            today: 3,
+            open: 1,
+            secured: 4,
+            ..WifiStats::default()
+        };
+        stats.channels[0] = 1;
+        stats.channels[5] = 4;
+        stats.per_hour[23] = 2;
+        stats
+            .strongest
+            .push(crate::stats::TopNetwork {
+                ssid: String::try_from("venue").unwrap(),
+                rssi: -60,
+            })
+            .unwrap();
+        let mut display = Framebuffer::new();
+        draw_screen(
+            &mut display,
+            Screen::WifiStats,
+            &sample.state(WifiListView::new()),
+        )
+        .unwrap();
+
+        let black = |x, y| display.pixel(Point::new(x, y)) == Some(BinaryColor::Off);
+        //Channel 6 fills the histogram's height, channel 1 a quarter of it, channel 2 nothing
+        let bottom = CHANNEL_BARS_BOUNDS.top_left.y + CHANNEL_BARS_BOUNDS.size.height as i32 - 1;
+        assert!(black(55, CHANNEL_BARS_BOUNDS.top_left.y));
+        assert!(black(5, bottom - 13) && !black(5, bottom - 14));
+        assert!(!black(15, bottom));
+        //The current hour is the last bar of the sparkline, the full height
+        assert!(black(294, PER_HOUR_BOUNDS.top_left.y));
+        assert!(!black(288, PER_HOUR_BOUNDS.top_left.y + 39));
+        //Three of the four signal bars for -60 are filled, the tallest is only outlined
+        assert!(black(286, 32) && black(292, 32) && !black(290, 32));
+    }
+
+    #[test]
+    fn status_line_fits_left_of_the_images() {
+        let mut display = Framebuffer::new();
+        let longest = "Wifi: could not join +3";
+        assert!(longest.len() <= crate::boot::STATUS_LINE_LEN);
+        let bounds = draw_status_line(&mut display, longest).unwrap();
+        let image_left = IMAGE_BOUNDS.top_left.x;
+        assert!(bounds.top_left.x + bounds.size.width as i32 <= image_left);
+        let text_right = display
+            .bounding_box()
+            .points()
+            .filter(|p| display.pixel(*p) == Some(BinaryColor::Off))
+            .map(|p| p.x)
+            .max()
+            .unwrap();
+        assert!(text_right < image_left);
+
+        //Clearing it leaves nothing behind
+        draw_status_line(&mut display, "").unwrap();
+        assert!(bounds
+            .points()
+            .all(|p| display.pixel(p) == Some(BinaryColor::On)));
+    }
+}
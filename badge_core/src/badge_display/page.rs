@@ -0,0 +1,291 @@
+//! Pages are the screens the badge can show, each one its own module that draws itself from a
+//! [`DisplayState`] and handles the buttons it has a use for.
+//!
+//! [`Pages`] holds every page and draws the one for a [`Screen`], [`Navigator`] tracks which
+//! screen is shown and moves between them with the buttons. What a page does not handle goes
+//! where [`ROUTES`] says, so the order of the screens is in one table instead of spread over the
+//! button handling.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use super::badge::BadgePage;
+use super::compositor::Compositor;
+use super::wifi_detail::WifiDetailPage;
+use super::wifi_list::{WifiListPage, WifiListView};
+use super::wifi_stats::WifiStatsPage;
+use super::{DisplayState, Screen};
+
+/// Buttons that move between and around the pages, B and C do the same thing on every page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Button {
+    Up,
+    Down,
+    A,
+}
+
+/// What a page did with a button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Input {
+    /// The page used it, e.g. to move a cursor
+    Handled,
+    /// The page has no use for it, it goes where [`ROUTES`] says
+    Route,
+    /// Nothing happens, not even the route. E.g. A on an empty list
+    Blocked,
+}
+
+/// What a button press did, see [`Navigator::press`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Navigation {
+    /// Another screen is shown, the display has to be cleared for it
+    Moved(Screen),
+    /// The page used it and redraws what changed itself
+    Handled,
+    /// Nothing uses the button here
+    Ignored,
+}
+
+/// When a page is redrawn without an event asking for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Refresh {
+    /// Only when the screen is cleared or what it shows changes
+    OnChange,
+    /// Every this many seconds as well
+    Every(u32),
+}
+
+/// What a page has to go on when working out what to draw, built up from
+/// [`DisplayEvent`](super::event::DisplayEvent)s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Redraw {
+    /// The screen has to be cleared first, another page is shown
+    pub clear: bool,
+    /// Everything, the screen was cleared or what every page shows changed
+    pub full: bool,
+    /// The page's timed refresh is due, see [`Page::refresh`]
+    pub timed: bool,
+    /// Temperature, humidity or the wifi count changed
+    pub readings_changed: bool,
+    pub time_changed: bool,
+    /// The boot status line changed
+    pub status_changed: bool,
+    /// Another image was picked
+    pub image_changed: bool,
+    /// A button was pressed, see [`RefreshPolicy::quiet`](super::refresh::RefreshPolicy::quiet)
+    pub input: bool,
+}
+
+impl Redraw {
+    /// Everything, like after the screen was cleared
+    pub fn full() -> Self {
+        Self {
+            full: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// One of the screens
+pub trait Page {
+    /// When the page wants redrawing on a timer
+    fn refresh(&self) -> Refresh {
+        Refresh::OnChange
+    }
+
+    /// Draws what `redraw` says is stale and whatever changed in `state` since the last draw,
+    /// through `compositor` so it knows what to refresh
+    fn draw<D>(
+        &mut self,
+        display: &mut D,
+        state: &DisplayState,
+        redraw: &Redraw,
+        compositor: &mut Compositor,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>;
+
+    /// What the page does with `button` before [`ROUTES`] are tried. `list` is the wifi list's
+    /// cursor over `networks` in the scan history. Pages are owned by the display, so this only
+    /// gets the state shared with the buttons
+    fn handle_input(button: Button, list: &mut WifiListView, networks: usize) -> Input {
+        let _ = (button, list, networks);
+        Input::Route
+    }
+}
+
+/// Where each button goes from each screen when the page does not handle it
+pub const ROUTES: [(Screen, Button, Screen); 6] = [
+    (Screen::Badge, Button::Down, Screen::WifiList),
+    (Screen::WifiList, Button::Up, Screen::Badge),
+    (Screen::WifiList, Button::Down, Screen::WifiStats),
+    (Screen::WifiList, Button::A, Screen::WifiDetail),
+    (Screen::WifiDetail, Button::A, Screen::WifiList),
+    (Screen::WifiStats, Button::Up, Screen::WifiList),
+];
+
+/// Which screen is shown and where the wifi list's cursor is, moved by the buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Navigator {
+    screen: Screen,
+    list: WifiListView,
+}
+
+impl Navigator {
+    pub const fn new() -> Self {
+        Self {
+            screen: Screen::Badge,
+            list: WifiListView::new(),
+        }
+    }
+
+    pub fn screen(&self) -> Screen {
+        self.screen
+    }
+
+    pub fn list(&self) -> WifiListView {
+        self.list
+    }
+
+    /// Puts the wifi list's cursor back on the newest network, scans reorder the history
+    pub fn reset_list(&mut self) {
+        self.list.reset();
+    }
+
+    /// Handles `button` on the current screen, `networks` is how many are in the scan history
+    pub fn press(&mut self, button: Button, networks: usize) -> Navigation {
+        let list = &mut self.list;
+        let input = match self.screen {
+            Screen::Badge => BadgePage::handle_input(button, list, networks),
+            Screen::WifiList => WifiListPage::handle_input(button, list, networks),
+            Screen::WifiDetail => WifiDetailPage::handle_input(button, list, networks),
+            Screen::WifiStats => WifiStatsPage::handle_input(button, list, networks),
+        };
+        match input {
+            Input::Handled => Navigation::Handled,
+            Input::Blocked => Navigation::Ignored,
+            Input::Route => {
+                let route = ROUTES
+                    .iter()
+                    .find(|(from, pressed, _)| *from == self.screen && *pressed == button);
+                match route {
+                    Some((_, _, to)) => {
+                        self.screen = *to;
+                        Navigation::Moved(*to)
+                    }
+                    None => Navigation::Ignored,
+                }
+            }
+        }
+    }
+}
+
+impl Default for Navigator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Every page, remembering what each last drew
+#[derive(Debug, Clone, Default)]
+pub struct Pages {
+    pub badge: BadgePage,
+    pub wifi_list: WifiListPage,
+    pub wifi_detail: WifiDetailPage,
+    pub wifi_stats: WifiStatsPage,
+}
+
+impl Pages {
+    pub fn refresh(&self, screen: Screen) -> Refresh {
+        match screen {
+            Screen::Badge => self.badge.refresh(),
+            Screen::WifiList => self.wifi_list.refresh(),
+            Screen::WifiDetail => self.wifi_detail.refresh(),
+            Screen::WifiStats => self.wifi_stats.refresh(),
+        }
+    }
+
+    /// Draws `screen`'s page, see [`Page::draw`]
+    pub fn draw<D>(
+        &mut self,
+        screen: Screen,
+        display: &mut D,
+        state: &DisplayState,
+        redraw: &Redraw,
+        compositor: &mut Compositor,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        match screen {
+            Screen::Badge => self.badge.draw(display, state, redraw, compositor),
+            Screen::WifiList => self.wifi_list.draw(display, state, redraw, compositor),
+            Screen::WifiDetail => self.wifi_detail.draw(display, state, redraw, compositor),
+            Screen::WifiStats => self.wifi_stats.draw(display, state, redraw, compositor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buttons_move_between_screens_and_the_list() {
+        let mut navigator = Navigator::new();
+        assert_eq!(
+            navigator.press(Button::Down, 2),
+            Navigation::Moved(Screen::WifiList)
+        );
+        assert_eq!(navigator.press(Button::Down, 2), Navigation::Handled);
+        assert_eq!(navigator.list().selected(), 1);
+        assert_eq!(
+            navigator.press(Button::A, 2),
+            Navigation::Moved(Screen::WifiDetail)
+        );
+        //Up and down on the detail screen move to the next network without leaving
+        assert_eq!(navigator.press(Button::Down, 2), Navigation::Handled);
+        assert_eq!(navigator.press(Button::Up, 2), Navigation::Handled);
+        assert_eq!(navigator.list().selected(), 0);
+        assert_eq!(
+            navigator.press(Button::A, 2),
+            Navigation::Moved(Screen::WifiList)
+        );
+        //Past either end of the list leaves it
+        assert_eq!(
+            navigator.press(Button::Up, 2),
+            Navigation::Moved(Screen::Badge)
+        );
+        assert_eq!(navigator.press(Button::Up, 2), Navigation::Ignored);
+        navigator.press(Button::Down, 2);
+        navigator.press(Button::Down, 2);
+        assert_eq!(
+            navigator.press(Button::Down, 2),
+            Navigation::Moved(Screen::WifiStats)
+        );
+        assert_eq!(navigator.press(Button::Down, 2), Navigation::Ignored);
+        assert_eq!(
+            navigator.press(Button::Up, 2),
+            Navigation::Moved(Screen::WifiList)
+        );
+        //Nothing to show details of
+        assert_eq!(navigator.press(Button::A, 0), Navigation::Ignored);
+        assert_eq!(navigator.screen(), Screen::WifiList);
+    }
+
+    #[test]
+    fn every_route_starts_and_ends_on_a_page() {
+        for (index, (from, button, to)) in ROUTES.iter().enumerate() {
+            assert_ne!(from, to);
+            //One route per button on each screen
+            assert!(!ROUTES[..index]
+                .iter()
+                .any(|(other, pressed, _)| other == from && pressed == button));
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! When the e-ink gets a full refresh and which waveform each update is driven with.
+//!
+//! A partial refresh only drives the pixels that changed, so after enough clock ticks the old
+//! digits show through around the new ones. [`RefreshPolicy`] counts how much each band of rows
+//! has been partially refreshed and swaps the next update for a full one once any band passes
+//! `FULL_REFRESH_AFTER`, or earlier once nobody has pressed a button for a while so the flash of
+//! a full refresh is not in the way.
+//!
+//! Faster waveforms ghost more, so each update gets the fastest one that still looks right for
+//! what was drawn, see [`UpdateKind`].
+
+use embedded_graphics::primitives::Rectangle;
+
+use super::compositor::{align, REFRESH_ALIGN};
+use super::page::Redraw;
+use super::{Screen, HEIGHT};
+
+/// Bands of [`REFRESH_ALIGN`] rows the ghosting is counted over, the smallest area the
+/// controller refreshes on its own
+pub const BANDS: usize = (HEIGHT / REFRESH_ALIGN) as usize;
+
+/// The UC8151 waveforms the badge uses, slowest and cleanest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Lut {
+    Medium,
+    Fast,
+    Ultrafast,
+}
+
+impl Lut {
+    /// How much ghosting a partial refresh with this waveform counts for
+    pub fn ghosting(self) -> u16 {
+        match self {
+            Self::Medium | Self::Fast => 1,
+            Self::Ultrafast => 2,
+        }
+    }
+}
+
+/// What an update is for, which picks its waveform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UpdateKind {
+    /// The badge screen people look at, drawn in the best quality
+    Face,
+    /// The wifi screens, they change with every button press so they have to be quick
+    Menu,
+    /// Only the time or the top bar of the badge screen ticked over
+    Tick,
+}
+
+impl UpdateKind {
+    /// What drawing `redraw` on `screen` is
+    pub fn of(screen: Screen, redraw: &Redraw) -> Self {
+        if screen != Screen::Badge {
+            return Self::Menu;
+        }
+        let ticked_only =
+            !(redraw.clear || redraw.full || redraw.status_changed || redraw.image_changed);
+        if ticked_only {
+            Self::Tick
+        } else {
+            Self::Face
+        }
+    }
+
+    pub fn lut(self) -> Lut {
+        match self {
+            Self::Face => Lut::Medium,
+            Self::Menu => Lut::Fast,
+            Self::Tick => Lut::Ultrafast,
+        }
+    }
+}
+
+/// How the display is refreshed after a display cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Update {
+    /// Just `region`, see [`Compositor::region`](super::compositor::Compositor::region)
+    Partial { region: Rectangle, lut: Lut },
+    /// The whole screen, clearing any ghosting
+    Full,
+}
+
+impl Update {
+    /// Full refreshes always use the cleanest waveform
+    pub fn lut(&self) -> Lut {
+        match self {
+            Self::Partial { lut, .. } => *lut,
+            Self::Full => Lut::Medium,
+        }
+    }
+}
+
+/// Counts the partial refreshes since the last full one, see the module docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RefreshPolicy {
+    /// See `Config::full_refresh_after`
+    full_after: u16,
+    ghosting: [u16; BANDS],
+    /// Nobody pressed anything for a while and there is enough ghosting to clean up
+    clean_due: bool,
+}
+
+impl RefreshPolicy {
+    pub const fn new(full_after: u16) -> Self {
+        Self {
+            full_after,
+            ghosting: [0; BANDS],
+            clean_due: false,
+        }
+    }
+
+    /// Picks how to refresh `region`, what was drawn as `kind`. `cleared` is when the screen was
+    /// cleared for another page, which always gets a full refresh. `None` if nothing needs one
+    pub fn plan(
+        &mut self,
+        region: Option<Rectangle>,
+        kind: UpdateKind,
+        cleared: bool,
+    ) -> Option<Update> {
+        if cleared || self.clean_due {
+            self.reset();
+            return Some(Update::Full);
+        }
+        let region = align(region?)?;
+        let lut = kind.lut();
+        let top = region.top_left.y as usize / REFRESH_ALIGN as usize;
+        let bands = top..top + (region.size.height / REFRESH_ALIGN) as usize;
+        if self.ghosting[bands.clone()]
+            .iter()
+            .any(|&ghosting| ghosting.saturating_add(lut.ghosting()) > self.full_after)
+        {
+            self.reset();
+            return Some(Update::Full);
+        }
+        for ghosting in &mut self.ghosting[bands] {
+            *ghosting += lut.ghosting();
+        }
+        Some(Update::Partial { region, lut })
+    }
+
+    /// Nobody pressed a button for a while, so the next [`plan`](Self::plan) cleans the screen if
+    /// any band is at least a quarter of the way to needing it
+    pub fn quiet(&mut self) {
+        self.clean_due = self.ghosting() >= self.full_after.div_ceil(4).max(1);
+    }
+
+    /// The most ghosting of any band since the last full refresh
+    pub fn ghosting(&self) -> u16 {
+        self.ghosting.iter().copied().max().unwrap_or(0)
+    }
+
+    fn reset(&mut self) {
+        self.ghosting = [0; BANDS];
+        self.clean_due = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::prelude::*;
+
+    fn rectangle(x: i32, y: i32, width: u32, height: u32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(width, height))
+    }
+
+    #[test]
+    fn updates_get_the_waveform_for_what_was_drawn() {
+        let tick = Redraw {
+            time_changed: true,
+            readings_changed: true,
+            ..Redraw::default()
+        };
+        assert_eq!(UpdateKind::of(Screen::Badge, &tick), UpdateKind::Tick);
+        assert_eq!(
+            UpdateKind::of(Screen::Badge, &Redraw::full()),
+            UpdateKind::Face
+        );
+        let image = Redraw {
+            image_changed: true,
+            ..tick
+        };
+        assert_eq!(UpdateKind::of(Screen::Badge, &image), UpdateKind::Face);
+        assert_eq!(UpdateKind::of(Screen::WifiList, &tick), UpdateKind::Menu);
+        assert_eq!(UpdateKind::Face.lut(), Lut::Medium);
+        assert_eq!(UpdateKind::Menu.lut(), Lut::Fast);
+        assert_eq!(UpdateKind::Tick.lut(), Lut::Ultrafast);
+    }
+
+    #[test]
+    fn ghosting_is_counted_per_band() {
+        let mut policy = RefreshPolicy::new(4);
+        let time = rectangle(0, 96, 88, 24);
+        let top_bar = rectangle(0, 0, 296, 24);
+        assert_eq!(policy.plan(None, UpdateKind::Tick, false), None);
+        for _ in 0..2 {
+            assert_eq!(
+                policy.plan(Some(time), UpdateKind::Tick, false),
+                Some(Update::Partial {
+                    region: time,
+                    lut: Lut::Ultrafast
+                })
+            );
+        }
+        assert_eq!(policy.ghosting(), 4);
+        //The top bar has its own bands, so it has a way to go
+        for _ in 0..4 {
+            assert!(matches!(
+                policy.plan(Some(top_bar), UpdateKind::Menu, false),
+                Some(Update::Partial { .. })
+            ));
+        }
+        //One more tick of the time would push it over
+        assert_eq!(
+            policy.plan(Some(time), UpdateKind::Menu, false),
+            Some(Update::Full)
+        );
+        assert_eq!(policy.ghosting(), 0);
+        assert_eq!(Update::Full.lut(), Lut::Medium);
+    }
+
+    #[test]
+    fn another_page_and_quiet_times_refresh_everything() {
+        let mut policy = RefreshPolicy::new(8);
+        let time = rectangle(0, 96, 88, 24);
+        assert_eq!(
+            policy.plan(Some(time), UpdateKind::Face, true),
+            Some(Update::Full)
+        );
+
+        policy.plan(Some(time), UpdateKind::Face, false);
+        //Not enough ghosting to be worth the flash
+        policy.quiet();
+        assert!(matches!(
+            policy.plan(Some(time), UpdateKind::Face, false),
+            Some(Update::Partial { .. })
+        ));
+        policy.quiet();
+        assert_eq!(
+            policy.plan(None, UpdateKind::Tick, false),
+            Some(Update::Full)
+        );
+        assert_eq!(policy.plan(None, UpdateKind::Tick, false), None);
+    }
+}
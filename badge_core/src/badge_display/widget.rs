@@ -0,0 +1,225 @@
+//! The parts pages are built from. Each widget knows where it draws before it is drawn, so the
+//! [`Compositor`](super::compositor::Compositor) can refresh just those areas.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+
+use super::display_image::DisplayImage;
+use super::wifi_list::WifiListView;
+use super::{
+    draw_image, draw_name_and_details, draw_status_line, draw_time, draw_top_bar, draw_wifi_detail,
+    draw_wifi_list, draw_wifi_row, draw_wifi_stats, name_and_details_bounds, time_bounds,
+    wifi_row_bounds, BODY_BOUNDS, IMAGE_BOUNDS, STATUS_LINE_BOUNDS, TOP_BAR_BOUNDS,
+};
+use crate::scan::ScanHistory;
+use crate::scan::ScanRecord;
+use crate::stats::WifiStats;
+use crate::time::tz::TimeZone;
+
+/// Something drawn on its own in a fixed area of the screen
+pub trait Widget {
+    /// Where the widget draws, it does not draw outside this
+    fn bounds(&self) -> Rectangle;
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>;
+}
+
+/// The bar across the top of every screen
+pub struct TopBar<'a>(pub &'a str);
+
+impl Widget for TopBar<'_> {
+    fn bounds(&self) -> Rectangle {
+        TOP_BAR_BOUNDS
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_top_bar(display, self.0).map(drop)
+    }
+}
+
+/// Name and details on the badge screen
+pub struct NameAndDetails<'a>(pub &'a str);
+
+impl Widget for NameAndDetails<'_> {
+    fn bounds(&self) -> Rectangle {
+        name_and_details_bounds(self.0)
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_name_and_details(display, self.0).map(drop)
+    }
+}
+
+/// The time box, sized for the longest time the clock format shows
+pub struct TimeBox<'a> {
+    pub time: &'a str,
+    /// See `ClockFormat::text_len`
+    pub len: usize,
+}
+
+impl Widget for TimeBox<'_> {
+    fn bounds(&self) -> Rectangle {
+        time_bounds(self.len.max(self.time.len()))
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_time(display, self.time, self.len).map(drop)
+    }
+}
+
+/// What is not working, see `BootStatus::status_line`
+pub struct StatusLine<'a>(pub &'a str);
+
+impl Widget for StatusLine<'_> {
+    fn bounds(&self) -> Rectangle {
+        STATUS_LINE_BOUNDS
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_status_line(display, self.0).map(drop)
+    }
+}
+
+/// The image on the right of the badge screen, covering where the previous image was. `None`
+/// when there are no images
+pub struct BadgeImage<'a>(pub Option<&'a DisplayImage>);
+
+impl Widget for BadgeImage<'_> {
+    fn bounds(&self) -> Rectangle {
+        IMAGE_BOUNDS
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_image(display, self.0).map(drop)
+    }
+}
+
+/// One network on the wifi list
+pub struct WifiRow<'a> {
+    pub row: usize,
+    pub ssid: &'a str,
+    pub selected: bool,
+}
+
+impl Widget for WifiRow<'_> {
+    fn bounds(&self) -> Rectangle {
+        wifi_row_bounds(self.row)
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_wifi_row(display, self.row, self.ssid, self.selected).map(drop)
+    }
+}
+
+/// The page of the wifi list the cursor is on
+pub struct WifiList<'a, const N: usize> {
+    pub history: &'a ScanHistory<N>,
+    pub list: WifiListView,
+}
+
+impl<const N: usize> Widget for WifiList<'_, N> {
+    fn bounds(&self) -> Rectangle {
+        BODY_BOUNDS
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_wifi_list(display, self.history, self.list).map(drop)
+    }
+}
+
+/// Everything known about one network
+pub struct WifiDetail<'a> {
+    pub record: &'a ScanRecord,
+    pub time_zone: &'a TimeZone,
+}
+
+impl Widget for WifiDetail<'_> {
+    fn bounds(&self) -> Rectangle {
+        BODY_BOUNDS
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_wifi_detail(display, self.record, self.time_zone).map(drop)
+    }
+}
+
+/// Everything under the top bar of the statistics screen
+pub struct WifiStatsBody<'a>(pub &'a WifiStats);
+
+impl Widget for WifiStatsBody<'_> {
+    fn bounds(&self) -> Rectangle {
+        BODY_BOUNDS
+    }
+
+    fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        draw_wifi_stats(display, self.0).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::badge_display::framebuffer::Framebuffer;
+
+    /// Draws `widget` on a white screen and checks nothing changed outside its bounds
+    fn draws_inside<W: Widget>(widget: W) {
+        let mut display = Framebuffer::new();
+        display.clear(BinaryColor::On).unwrap();
+        widget.draw(&mut display).unwrap();
+        let bounds = widget.bounds();
+        assert!(display
+            .bounding_box()
+            .points()
+            .filter(|p| !bounds.contains(*p))
+            .all(|p| display.pixel(p) == Some(BinaryColor::On)));
+    }
+
+    #[test]
+    fn widgets_stay_inside_their_bounds() {
+        draws_inside(TopBar("Wifi found: 12"));
+        draws_inside(NameAndDetails("Ferris\nRustacean"));
+        draws_inside(TimeBox {
+            time: "Fri 2024-08-16 06:30:05 PM",
+            len: 26,
+        });
+        draws_inside(StatusLine("Wifi: could not join +3"));
+        for image in DisplayImage::bundled() {
+            draws_inside(BadgeImage(Some(&image)));
+        }
+        draws_inside(BadgeImage(None));
+        draws_inside(WifiRow {
+            row: 3,
+            ssid: "venue",
+            selected: true,
+        });
+        draws_inside(WifiStatsBody(&WifiStats::default()));
+    }
+}
@@ -0,0 +1,61 @@
+//! Everything known about the network selected on the wifi list.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use super::compositor::Compositor;
+use super::page::{Button, Input, Page, Redraw};
+use super::widget::{TopBar, WifiDetail};
+use super::wifi_list::WifiListView;
+use super::{network_name, truncate, DisplayState};
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WifiDetailPage {
+    /// The cursor when last drawn, up and down move it to the next network
+    drawn: Option<WifiListView>,
+}
+
+impl Page for WifiDetailPage {
+    fn draw<D>(
+        &mut self,
+        display: &mut D,
+        state: &DisplayState,
+        redraw: &Redraw,
+        compositor: &mut Compositor,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        if redraw.full || self.drawn != Some(state.list) {
+            match state.history.get(state.list.selected()) {
+                Some(record) => {
+                    compositor.draw(display, &TopBar(truncate(network_name(record), 30)))?;
+                    let detail = WifiDetail {
+                        record,
+                        time_zone: state.time_zone,
+                    };
+                    compositor.draw(display, &detail)?;
+                }
+                //The history was cleared under the cursor
+                None => compositor.draw(display, &TopBar("Network gone"))?,
+            }
+        }
+        self.drawn = Some(state.list);
+        Ok(())
+    }
+
+    /// Up and down move to the next network without going back to the list
+    fn handle_input(button: Button, list: &mut WifiListView, networks: usize) -> Input {
+        match button {
+            Button::Up => {
+                list.up();
+                Input::Handled
+            }
+            Button::Down => {
+                list.down(networks);
+                Input::Handled
+            }
+            Button::A => Input::Route,
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Which network is selected on the wifi list and which page of the list that puts on screen.
+//!
+//! The list is the scan history, newest first. It is paged rather than scrolled a row at a time so
+//! moving the cursor only redraws two rows of the e-ink display until it crosses onto a new page.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use super::compositor::Compositor;
+use super::page::{Button, Input, Page, Redraw};
+use super::widget::{TopBar, WifiList, WifiRow};
+use super::{network_name, wifi_list_top_bar_text, DisplayState};
+
+/// Rows of networks that fit under the top bar
+pub const WIFI_LIST_ROWS: usize = 4;
+
+/// Cursor on the wifi list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WifiListView {
+    selected: usize,
+}
+
+impl WifiListView {
+    pub const fn new() -> Self {
+        Self { selected: 0 }
+    }
+
+    /// Index of the selected network in the scan history
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Index of the network in the first row of the selected one's page
+    pub fn page_start(&self) -> usize {
+        self.selected / WIFI_LIST_ROWS * WIFI_LIST_ROWS
+    }
+
+    /// Row on screen of the selected network
+    pub fn selected_row(&self) -> usize {
+        self.selected % WIFI_LIST_ROWS
+    }
+
+    /// Page of the selected network counting from 1, and the number of pages for `networks`
+    pub fn page(&self, networks: usize) -> (usize, usize) {
+        (
+            self.selected / WIFI_LIST_ROWS + 1,
+            networks.div_ceil(WIFI_LIST_ROWS).max(1),
+        )
+    }
+
+    /// Moves the cursor up, false if it was already on the first network
+    pub fn up(&mut self) -> bool {
+        match self.selected.checked_sub(1) {
+            Some(selected) => {
+                self.selected = selected;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the cursor down, false if it was already on the last of `networks`
+    pub fn down(&mut self, networks: usize) -> bool {
+        if self.selected + 1 < networks {
+            self.selected += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Back to the newest network
+    pub fn reset(&mut self) {
+        self.selected = 0;
+    }
+}
+
+/// The scan history, a page at a time with a cursor
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WifiListPage {
+    /// Where the cursor was when last drawn, so moving it only redraws what changed
+    drawn: Option<WifiListView>,
+}
+
+impl Page for WifiListPage {
+    fn draw<D>(
+        &mut self,
+        display: &mut D,
+        state: &DisplayState,
+        redraw: &Redraw,
+        compositor: &mut Compositor,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let list = state.list;
+        let new_page = self
+            .drawn
+            .is_none_or(|drawn| drawn.page_start() != list.page_start());
+        if redraw.full || new_page {
+            let top_text = wifi_list_top_bar_text(state.wifi_count, list.page(state.history.len()));
+            compositor.draw(display, &TopBar(&top_text))?;
+            let rows = WifiList {
+                history: state.history,
+                list,
+            };
+            compositor.draw(display, &rows)?;
+        } else if let Some(last) = self.drawn.filter(|last| *last != list) {
+            //Only the row the cursor left and the one it moved to
+            for (index, row) in [
+                (last.selected(), last.selected_row()),
+                (list.selected(), list.selected_row()),
+            ] {
+                if let Some(record) = state.history.get(index) {
+                    let row = WifiRow {
+                        row,
+                        ssid: network_name(record),
+                        selected: index == list.selected(),
+                    };
+                    compositor.draw(display, &row)?;
+                }
+            }
+        }
+        self.drawn = Some(list);
+        Ok(())
+    }
+
+    /// Up and down move the cursor and leave the list past its ends, A needs a network to open
+    fn handle_input(button: Button, list: &mut WifiListView, networks: usize) -> Input {
+        let moved = match button {
+            Button::Up => list.up(),
+            Button::Down => list.down(networks),
+            Button::A if networks == 0 => return Input::Blocked,
+            Button::A => false,
+        };
+        if moved {
+            Input::Handled
+        } else {
+            Input::Route
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::badge_display::framebuffer::Framebuffer;
+    use crate::badge_display::tests::Sample;
+    use crate::badge_display::{HEIGHT, WIDTH};
+    use embedded_graphics::primitives::Rectangle;
+
+    #[test]
+    fn pages_follow_the_cursor() {
+        let mut view = WifiListView::new();
+        assert_eq!(view.page(0), (1, 1));
+        assert_eq!(view.page(9), (1, 3));
+        for _ in 0..5 {
+            assert!(view.down(9));
+        }
+        assert_eq!(view.selected(), 5);
+        assert_eq!(view.page_start(), 4);
+        assert_eq!(view.selected_row(), 1);
+        assert_eq!(view.page(9), (2, 3));
+    }
+
+    #[test]
+    fn stops_at_the_ends() {
+        let mut view = WifiListView::new();
+        assert!(!view.up());
+        assert!(!view.down(0));
+        assert!(!view.down(1));
+        assert!(view.down(2));
+        assert!(!view.down(2));
+        assert!(view.up());
+        assert_eq!(view.selected(), 0);
+    }
+
+    #[test]
+    fn moving_the_cursor_refreshes_two_rows() {
+        let sample = Sample::with_networks(6);
+        let mut display = Framebuffer::new();
+        let mut page = WifiListPage::default();
+        let mut region = |list| {
+            let mut compositor = Compositor::new();
+            page.draw(
+                &mut display,
+                &sample.state(list),
+                &Redraw::default(),
+                &mut compositor,
+            )
+            .unwrap();
+            compositor.region()
+        };
+        let mut list = WifiListView::new();
+        let screen = Rectangle::new(Point::zero(), Size::new(WIDTH, HEIGHT));
+        assert_eq!(region(list), Some(screen));
+        assert_eq!(region(list), None);
+
+        list.down(6);
+        assert_eq!(
+            region(list),
+            Some(Rectangle::new(Point::new(0, 24), Size::new(WIDTH, 48)))
+        );
+        //Onto the next page redraws the list
+        for _ in 0..3 {
+            list.down(6);
+        }
+        assert_eq!(region(list), Some(screen));
+    }
+}
@@ -0,0 +1,38 @@
+//! Channels, security and the strongest networks in the scan history, and how many new networks
+//! were counted over the last day.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+use super::compositor::Compositor;
+use super::page::{Page, Redraw, Refresh};
+use super::widget::{TopBar, WifiStatsBody};
+use super::{wifi_stats_top_bar_text, DisplayState};
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WifiStatsPage;
+
+impl Page for WifiStatsPage {
+    /// Scans change the numbers slowly
+    fn refresh(&self) -> Refresh {
+        Refresh::Every(60)
+    }
+
+    fn draw<D>(
+        &mut self,
+        display: &mut D,
+        state: &DisplayState,
+        redraw: &Redraw,
+        compositor: &mut Compositor,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        if redraw.full || redraw.timed {
+            let top_text = wifi_stats_top_bar_text(state.wifi_count, state.stats.today);
+            compositor.draw(display, &TopBar(&top_text))?;
+            compositor.draw(display, &WifiStatsBody(state.stats))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Remembering networks that no longer fit in the exact list, for
+//! [`WifiCounting::FilterWhenFull`](crate::bssid::WifiCounting::FilterWhenFull).
+//!
+//! [`BloomFilter`] sets [`HASHES`] of its [`BITS`] bits for every BSSID added, and a BSSID whose
+//! bits are all set may have been added. One that was added is always found, so a forgotten
+//! network is never counted again. One that was not can find its bits set by others and is then
+//! not counted either. With `n` BSSIDs added that happens to (1 - e^(-4n / 16384))^4 of new ones,
+//! 1 in 450 after 1000, 1 in 45 after 2000 and 1 in 7 after 4000.
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::bssid::Bssid;
+use crate::sketch::hash_bssid;
+
+/// Bytes of bits the filter keeps
+pub const FILTER_LEN: usize = 2048;
+/// Number of bits, each BSSID sets [`HASHES`] of them
+pub const BITS: usize = FILTER_LEN * 8;
+/// Bits set for each BSSID
+pub const HASHES: u32 = 4;
+
+/// A Bloom filter of the BSSIDs forgotten from the exact list
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    /// Always [`FILTER_LEN`] long, a `Vec` as serde can not do arrays this big
+    bits: Vec<u8, FILTER_LEN>,
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BloomFilter {
+    pub fn new() -> Self {
+        let mut bits = Vec::new();
+        let _ = bits.resize(FILTER_LEN, 0);
+        Self { bits }
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+
+    pub fn add(&mut self, bssid: &Bssid) {
+        for bit in bits_of(bssid) {
+            //Only short if a corrupted save was loaded
+            if let Some(byte) = self.bits.get_mut(bit / 8) {
+                *byte |= 1 << (bit % 8);
+            }
+        }
+    }
+
+    /// True if `bssid` was added, or for a few that were not, see the module docs
+    pub fn contains(&self, bssid: &Bssid) -> bool {
+        bits_of(bssid).all(|bit| {
+            self.bits
+                .get(bit / 8)
+                .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+        })
+    }
+}
+
+/// The bits for `bssid`, two halves of one hash combined as h1 + i * h2
+fn bits_of(bssid: &Bssid) -> impl Iterator<Item = usize> {
+    let hash = hash_bssid(bssid);
+    let first = hash as u32;
+    //Odd so the bits never all land on the same one
+    let step = (hash >> 32) as u32 | 1;
+    (0..HASHES).map(move |i| first.wrapping_add(i.wrapping_mul(step)) as usize % BITS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bssid(i: u32) -> Bssid {
+        let [a, b, c, d] = i.to_be_bytes();
+        [0, 0, a, b, c, d]
+    }
+
+    #[test]
+    fn added_bssids_are_always_found() {
+        let mut filter = BloomFilter::new();
+        assert!(!filter.contains(&bssid(1)));
+        for i in 0..5_000 {
+            filter.add(&bssid(i));
+        }
+        assert!((0..5_000).all(|i| filter.contains(&bssid(i))));
+
+        filter.clear();
+        assert_eq!(filter, BloomFilter::new());
+    }
+
+    #[test]
+    fn false_matches_are_as_rare_as_documented() {
+        let mut filter = BloomFilter::new();
+        for i in 0..2_000 {
+            filter.add(&bssid(i));
+        }
+        let false_matches = (1_000_000..1_020_000)
+            .filter(|i| filter.contains(&bssid(*i)))
+            .count();
+        //1 in 45 is about 444 of 20,000
+        assert!((300..600).contains(&false_matches), "{}", false_matches);
+    }
+}
@@ -0,0 +1,230 @@
+//! What went wrong while starting up, shown on the badge screen's status line.
+//!
+//! Boot is split into stages that can each fail without stopping the rest of the badge. A failed
+//! stage is reported to [`BootStatus`], and cleared again when a later retry of it works, so the
+//! status line only shows what is still broken.
+
+use core::fmt;
+
+use heapless::String;
+
+use crate::helpers::easy_format;
+use crate::time::TimeApiError;
+
+/// Longest status line, fits the space left of the images in the status line font
+pub const STATUS_LINE_LEN: usize = 24;
+
+/// Parts of the badge that can fail on their own, in the order they start up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootStage {
+    Config,
+    Wifi,
+    Save,
+    Clock,
+}
+
+impl BootStage {
+    const COUNT: usize = 4;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BootError {
+    /// The config in flash could not be read, the `.env` defaults are used
+    ConfigLoad,
+    ConfigSave,
+    /// None of the saved wifi networks could be joined
+    WifiJoin,
+    /// Joined a network but never got an address
+    Dhcp,
+    /// The saved wifi counts could not be read, counting starts over
+    SaveLoad,
+    /// The save was read but is damaged or from newer firmware, counting starts over
+    SaveCorrupt,
+    SaveWrite,
+    /// Wifi is down so the clock can not sync
+    TimeNoNetwork,
+    TimeHttpRequest,
+    TimeHttpSend,
+    TimeHttpBody,
+    TimeApi(TimeApiError),
+    Sntp,
+    /// The time was fetched but the RTC would not take it
+    RtcSet,
+}
+
+impl BootError {
+    pub fn stage(&self) -> BootStage {
+        match self {
+            Self::ConfigLoad | Self::ConfigSave => BootStage::Config,
+            Self::WifiJoin | Self::Dhcp => BootStage::Wifi,
+            Self::SaveLoad | Self::SaveCorrupt | Self::SaveWrite => BootStage::Save,
+            Self::TimeNoNetwork
+            | Self::TimeHttpRequest
+            | Self::TimeHttpSend
+            | Self::TimeHttpBody
+            | Self::TimeApi(_)
+            | Self::Sntp
+            | Self::RtcSet => BootStage::Clock,
+        }
+    }
+}
+
+impl fmt::Display for BootError {
+    /// Short enough to leave room for a count of other errors on the status line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ConfigLoad => "Config: using .env",
+            Self::ConfigSave => "Config: save failed",
+            Self::WifiJoin => "Wifi: could not join",
+            Self::Dhcp => "Wifi: no address",
+            Self::SaveLoad => "Counts: load failed",
+            Self::SaveCorrupt => "Counts: save corrupt",
+            Self::SaveWrite => "Counts: save failed",
+            Self::TimeNoNetwork => "Clock: no wifi",
+            Self::TimeHttpRequest => "Clock: bad TIME_API",
+            Self::TimeHttpSend => "Clock: request failed",
+            Self::TimeHttpBody => "Clock: no reply",
+            Self::TimeApi(_) => "Clock: bad reply",
+            Self::Sntp => "Clock: SNTP failed",
+            Self::RtcSet => "Clock: RTC not set",
+        })
+    }
+}
+
+/// The latest error of each stage that has not recovered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BootStatus {
+    errors: [Option<BootError>; BootStage::COUNT],
+}
+
+impl BootStatus {
+    pub const fn new() -> Self {
+        Self {
+            errors: [None; BootStage::COUNT],
+        }
+    }
+
+    pub fn failed(&mut self, error: BootError) {
+        self.errors[error.stage() as usize] = Some(error);
+    }
+
+    pub fn succeeded(&mut self, stage: BootStage) {
+        self.errors[stage as usize] = None;
+    }
+
+    /// Clears `error` if it is still what its stage shows. For stages where one part working does
+    /// not undo another failing, like a save working after the old save was lost
+    pub fn clear(&mut self, error: BootError) {
+        let stage = &mut self.errors[error.stage() as usize];
+        if *stage == Some(error) {
+            *stage = None;
+        }
+    }
+
+    /// Records either outcome of a stage
+    pub fn report(&mut self, stage: BootStage, result: Result<(), BootError>) {
+        match result {
+            Ok(()) => self.succeeded(stage),
+            Err(error) => self.failed(error),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors.iter().all(Option::is_none)
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = BootError> + '_ {
+        self.errors.iter().flatten().copied()
+    }
+
+    /// The first stage's error and how many others there are, empty when everything works
+    pub fn status_line(&self) -> String<STATUS_LINE_LEN> {
+        let mut errors = self.errors();
+        match (errors.next(), errors.count()) {
+            (None, _) => String::new(),
+            (Some(error), 0) => easy_format(format_args!("{}", error)),
+            (Some(error), others) => easy_format(format_args!("{} +{}", error, others)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_ERRORS: [BootError; 14] = [
+        BootError::ConfigLoad,
+        BootError::ConfigSave,
+        BootError::WifiJoin,
+        BootError::Dhcp,
+        BootError::SaveLoad,
+        BootError::SaveCorrupt,
+        BootError::SaveWrite,
+        BootError::TimeNoNetwork,
+        BootError::TimeHttpRequest,
+        BootError::TimeHttpSend,
+        BootError::TimeHttpBody,
+        BootError::TimeApi(TimeApiError::InvalidResponse),
+        BootError::Sntp,
+        BootError::RtcSet,
+    ];
+
+    #[test]
+    fn nothing_to_show_when_everything_works() {
+        let status = BootStatus::new();
+        assert!(status.is_ok());
+        assert_eq!(status.status_line(), "");
+    }
+
+    #[test]
+    fn shows_the_earliest_stage_and_counts_the_rest() {
+        let mut status = BootStatus::new();
+        status.failed(BootError::TimeHttpSend);
+        assert_eq!(status.status_line(), "Clock: request failed");
+        status.failed(BootError::WifiJoin);
+        status.failed(BootError::SaveLoad);
+        assert_eq!(status.status_line(), "Wifi: could not join +2");
+        assert!(!status.is_ok());
+    }
+
+    #[test]
+    fn recovering_clears_the_stage() {
+        let mut status = BootStatus::new();
+        status.failed(BootError::WifiJoin);
+        status.failed(BootError::TimeNoNetwork);
+        status.report(BootStage::Wifi, Ok(()));
+        assert_eq!(status.status_line(), "Clock: no wifi");
+        //A newer error replaces the old one for the same stage
+        status.report(
+            BootStage::Clock,
+            Err(BootError::TimeApi(TimeApiError::InvalidResponse)),
+        );
+        assert_eq!(status.status_line(), "Clock: bad reply");
+        status.succeeded(BootStage::Clock);
+        assert!(status.is_ok());
+    }
+
+    #[test]
+    fn clearing_leaves_other_errors() {
+        let mut status = BootStatus::new();
+        status.failed(BootError::SaveCorrupt);
+        status.clear(BootError::SaveWrite);
+        assert_eq!(status.status_line(), "Counts: save corrupt");
+        status.failed(BootError::SaveWrite);
+        status.clear(BootError::SaveWrite);
+        assert!(status.is_ok());
+    }
+
+    #[test]
+    fn every_line_fits_with_a_count() {
+        let mut status = BootStatus::new();
+        for error in ALL_ERRORS {
+            status.failed(error);
+            let line = easy_format::<64>(format_args!("{} +{}", error, BootStage::COUNT - 1));
+            assert!(line.len() <= STATUS_LINE_LEN, "{}", line);
+        }
+        assert_eq!(status.errors().count(), BootStage::COUNT);
+    }
+}
@@ -0,0 +1,396 @@
+//! Remembering which access points have been counted so each is only counted once.
+//!
+//! BSSIDs are kept as their raw 6 bytes sorted for binary search, which takes half the RAM of
+//! the formatted strings they used to be kept as and finds one in about 10 comparisons instead of
+//! a pass over the whole list. `cargo bench -p badge_core` compares the two.
+
+use core::fmt::{self, Write};
+
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+use crate::bloom::BloomFilter;
+use crate::sketch::HyperLogLog;
+
+/// Max number of unique bssids remembered between scans
+pub const BSSID_LEN: usize = 1_000;
+
+/// The MAC address of an access point
+pub type Bssid = [u8; 6];
+
+/// The set saved with the badge's counts
+pub type BssidSet = SeenBssids<BSSID_LEN>;
+
+/// How unique networks are counted, none counts a network twice
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiCounting {
+    /// Once [`BSSID_LEN`] networks have been counted new ones are not, the count is exact but
+    /// stops growing
+    #[default]
+    StopWhenFull,
+    /// The count is estimated by a [`HyperLogLog`] sketch, which has no limit but is only
+    /// accurate to a few percent. The count never goes down, so it can lag the estimate for a
+    /// while after switching from an exact count
+    Estimate,
+    /// Once [`BSSID_LEN`] networks are in the list each new one takes the place of one next to
+    /// it, which goes into a [`BloomFilter`] that is checked before counting. The count has no
+    /// limit and never counts a network twice, but the filter mistakes a few new networks for
+    /// forgotten ones and does not count them, more the more it holds
+    FilterWhenFull,
+}
+
+impl WifiCounting {
+    /// Parses the `WIFI_COUNTING` setting, `stop_when_full`, `estimate` or `filter_when_full`
+    pub fn from_config(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("stop_when_full") {
+            Some(Self::StopWhenFull)
+        } else if value.eq_ignore_ascii_case("estimate") {
+            Some(Self::Estimate)
+        } else if value.eq_ignore_ascii_case("filter_when_full") {
+            Some(Self::FilterWhenFull)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for WifiCounting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::StopWhenFull => "stop_when_full",
+            Self::Estimate => "estimate",
+            Self::FilterWhenFull => "filter_when_full",
+        })
+    }
+}
+
+/// Up to `N` BSSIDs that have been counted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SeenBssids<const N: usize> {
+    /// Sorted
+    bssids: Vec<Bssid, N>,
+}
+
+impl<const N: usize> Default for SeenBssids<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> SeenBssids<N> {
+    pub const fn new() -> Self {
+        Self { bssids: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bssids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bssids.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.bssids.is_full()
+    }
+
+    pub fn contains(&self, bssid: &Bssid) -> bool {
+        self.bssids.binary_search(bssid).is_ok()
+    }
+
+    /// Remembered BSSIDs in sorted order
+    pub fn iter(&self) -> impl Iterator<Item = &Bssid> + '_ {
+        self.bssids.iter()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Records a sighting of `bssid`. Returns true if it has not been seen before and should be
+    /// counted, false if it was already counted or the set is full
+    pub fn see(&mut self, bssid: Bssid) -> bool {
+        match self.bssids.binary_search(&bssid) {
+            Ok(_) => false,
+            Err(index) => self.bssids.insert(index, bssid).is_ok(),
+        }
+    }
+
+    /// Like [`SeenBssids::see`], but a full set makes room for a new `bssid` by forgetting the
+    /// BSSID in the place it goes. Returns whether to count it and the BSSID forgotten
+    pub fn see_replacing(&mut self, bssid: Bssid) -> (bool, Option<Bssid>) {
+        match self.bssids.binary_search(&bssid) {
+            Ok(_) => (false, None),
+            Err(index) if self.is_full() => {
+                //Only when N is 0, nothing can be remembered
+                let Some(last) = self.bssids.len().checked_sub(1) else {
+                    return (false, None);
+                };
+                //Between its neighbours either one can be replaced and the list stays sorted,
+                //without moving the rest of it
+                let forgotten = core::mem::replace(&mut self.bssids[index.min(last)], bssid);
+                (true, Some(forgotten))
+            }
+            Err(index) => (self.bssids.insert(index, bssid).is_ok(), None),
+        }
+    }
+}
+
+impl<const N: usize> FromIterator<Bssid> for SeenBssids<N> {
+    /// Keeps the first `N` different BSSIDs, for loading older saves
+    fn from_iter<I: IntoIterator<Item = Bssid>>(bssids: I) -> Self {
+        let mut set = Self::new();
+        for bssid in bssids {
+            set.see(bssid);
+        }
+        set
+    }
+}
+
+/// Counts the bssid if it has not been seen before. Returns true if the count went up.
+///
+/// Every bssid goes into `sketch` so an estimate is ready whichever way `counting` is set, with
+/// [`WifiCounting::Estimate`] it is the count and `bssids` is left alone. `forgotten` holds what
+/// [`WifiCounting::FilterWhenFull`] forgot from `bssids`
+pub fn process_bssid(
+    bssid: Bssid,
+    wifi_counted: &mut u32,
+    bssids: &mut BssidSet,
+    sketch: &mut HyperLogLog,
+    forgotten: &mut BloomFilter,
+    counting: WifiCounting,
+) -> bool {
+    sketch.add(&bssid);
+    if counting == WifiCounting::Estimate {
+        let estimate = sketch.estimate();
+        if estimate <= *wifi_counted {
+            return false;
+        }
+        *wifi_counted = estimate;
+        return true;
+    }
+
+    let new = match counting {
+        //Forgotten, or one of the few the filter mistakes for a forgotten one
+        WifiCounting::FilterWhenFull if forgotten.contains(&bssid) => false,
+        WifiCounting::FilterWhenFull => {
+            let (new, replaced) = bssids.see_replacing(bssid);
+            if let Some(replaced) = replaced {
+                forgotten.add(&replaced);
+            }
+            new
+        }
+        WifiCounting::StopWhenFull | WifiCounting::Estimate => bssids.see(bssid),
+    };
+    if !new {
+        return false;
+    }
+    *wifi_counted += 1;
+    if counting == WifiCounting::StopWhenFull && bssids.is_full() {
+        #[cfg(feature = "defmt")]
+        defmt::info!("bssid list full");
+    }
+    true
+}
+
+/// Formats a bssid as lowercase hex pairs separated by `:`
+pub fn format_bssid(bssid: Bssid) -> String<17> {
+    let mut s = String::new();
+    for (i, byte) in bssid.iter().enumerate() {
+        if i != 0 {
+            let _ = s.write_char(':');
+        }
+        core::fmt::write(&mut s, format_args!("{:02x}", byte)).unwrap();
+    }
+    s
+}
+
+/// Reads a bssid written by [`format_bssid`]
+pub fn parse_bssid(text: &str) -> Option<Bssid> {
+    let mut bssid = [0u8; 6];
+    let mut pairs = text.split(':');
+    for byte in bssid.iter_mut() {
+        let pair = pairs.next().filter(|pair| pair.len() == 2)?;
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    pairs.next().is_none().then_some(bssid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bssid(i: u32) -> Bssid {
+        let [a, b, c, d] = i.to_be_bytes();
+        [0, 0, a, b, c, d]
+    }
+
+    #[test]
+    fn formats_bssid_as_hex() {
+        let formatted = format_bssid([0x00, 0x1a, 0x2b, 0x3c, 0xd4, 0xff]);
+        assert_eq!(formatted.as_str(), "00:1a:2b:3c:d4:ff");
+        assert_eq!(
+            parse_bssid(&formatted),
+            Some([0x00, 0x1a, 0x2b, 0x3c, 0xd4, 0xff])
+        );
+        for bad in [
+            "",
+            "00:1a:2b:3c:d4",
+            "00:1a:2b:3c:d4:ff:00",
+            "0:1a:2b:3c:d4:ff0",
+        ] {
+            assert_eq!(parse_bssid(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn counts_each_bssid_once() {
+        let mut counted = 0;
+        let mut bssids = BssidSet::new();
+        let mut sketch = HyperLogLog::new();
+        let mut forgotten = BloomFilter::new();
+        let mut process = |bssid| {
+            process_bssid(
+                bssid,
+                &mut counted,
+                &mut bssids,
+                &mut sketch,
+                &mut forgotten,
+                WifiCounting::default(),
+            )
+        };
+        assert!(process([1, 2, 3, 4, 5, 6]));
+        assert!(!process([1, 2, 3, 4, 5, 6]));
+        assert!(process([1, 2, 3, 4, 5, 7]));
+        assert_eq!(counted, 2);
+        assert_eq!(bssids.len(), 2);
+        assert_eq!(sketch.estimate(), 2);
+    }
+
+    #[test]
+    fn estimates_past_the_exact_limit() {
+        let mut counted = 0;
+        let mut bssids = BssidSet::new();
+        let mut sketch = HyperLogLog::new();
+        let mut forgotten = BloomFilter::new();
+        for i in 0..20_000 {
+            process_bssid(
+                bssid(i),
+                &mut counted,
+                &mut bssids,
+                &mut sketch,
+                &mut forgotten,
+                WifiCounting::Estimate,
+            );
+        }
+        assert!((19_000..21_000).contains(&counted), "{}", counted);
+        assert!(bssids.is_empty());
+        //Seeing them all again does not count any of them twice
+        let before = counted;
+        for i in 0..20_000 {
+            assert!(!process_bssid(
+                bssid(i),
+                &mut counted,
+                &mut bssids,
+                &mut sketch,
+                &mut forgotten,
+                WifiCounting::Estimate,
+            ));
+        }
+        assert_eq!(counted, before);
+    }
+
+    #[test]
+    fn stays_sorted() {
+        let mut bssids = SeenBssids::<16>::new();
+        for i in [7, 3, 12, 0, 9, 3, 15, 1] {
+            bssids.see(bssid(i));
+        }
+        let sorted: Vec<Bssid, 16> = [0, 1, 3, 7, 9, 12, 15].into_iter().map(bssid).collect();
+        assert!(bssids.iter().eq(sorted.iter()));
+    }
+
+    #[test]
+    fn stops_counting_when_full() {
+        let mut counted = 0;
+        let mut bssids = BssidSet::new();
+        let mut sketch = HyperLogLog::new();
+        let mut forgotten = BloomFilter::new();
+        for i in 0..=BSSID_LEN as u32 {
+            process_bssid(
+                bssid(i),
+                &mut counted,
+                &mut bssids,
+                &mut sketch,
+                &mut forgotten,
+                WifiCounting::StopWhenFull,
+            );
+        }
+        assert_eq!(counted, BSSID_LEN as u32);
+        assert!(bssids.is_full());
+        //Nothing already counted is counted again
+        for i in 0..BSSID_LEN as u32 {
+            assert!(!bssids.see(bssid(i)));
+        }
+    }
+
+    #[test]
+    fn full_lists_replace_a_neighbour_and_stay_sorted() {
+        let mut bssids = SeenBssids::<4>::new();
+        for i in [10, 20, 30, 40] {
+            assert_eq!(bssids.see_replacing(bssid(i)), (true, None));
+        }
+        assert_eq!(bssids.see_replacing(bssid(20)), (false, None));
+        assert_eq!(bssids.see_replacing(bssid(25)), (true, Some(bssid(30))));
+        assert_eq!(bssids.see_replacing(bssid(50)), (true, Some(bssid(40))));
+        assert_eq!(bssids.see_replacing(bssid(5)), (true, Some(bssid(10))));
+        let sorted: Vec<Bssid, 4> = [5, 20, 25, 50].into_iter().map(bssid).collect();
+        assert!(bssids.iter().eq(sorted.iter()));
+    }
+
+    #[test]
+    fn filter_when_full_never_counts_twice() {
+        let mut counted = 0;
+        let mut bssids = BssidSet::new();
+        let mut sketch = HyperLogLog::new();
+        let mut forgotten = BloomFilter::new();
+        let mut process = |i| {
+            process_bssid(
+                bssid(i),
+                &mut counted,
+                &mut bssids,
+                &mut sketch,
+                &mut forgotten,
+                WifiCounting::FilterWhenFull,
+            )
+        };
+        for i in 0..3_000 {
+            process(i);
+        }
+        for i in 0..3_000 {
+            assert!(!process(i), "{}", i);
+        }
+        //Past the list every network is checked against the filter, a few are taken as forgotten
+        assert!((2_950..=3_000).contains(&counted), "{}", counted);
+        assert!(bssids.is_full());
+    }
+
+    #[test]
+    fn counting_setting_round_trips() {
+        for counting in [
+            WifiCounting::StopWhenFull,
+            WifiCounting::Estimate,
+            WifiCounting::FilterWhenFull,
+        ] {
+            let text = crate::helpers::easy_format::<16>(format_args!("{}", counting));
+            assert_eq!(WifiCounting::from_config(&text), Some(counting));
+        }
+        assert_eq!(
+            WifiCounting::from_config(" Estimate "),
+            Some(WifiCounting::Estimate)
+        );
+        assert_eq!(WifiCounting::from_config("forget_oldest"), None);
+    }
+}
@@ -0,0 +1,496 @@
+//! Settings that can change without reflashing the badge: who it belongs to, the wifi to join and
+//! how to show the time.
+//!
+//! The firmware seeds a [`Config`] from the `.env` it was built with, then keeps it in its own
+//! [`Journal`] next to the [`Save`](crate::save::Save) so changes made over USB serial with
+//! [`Command`](crate::console::Command)s survive a reboot, and losing power while saving one
+//! keeps the config saved before it.
+
+use core::fmt::{self, Write};
+
+use embedded_storage::nor_flash::NorFlash;
+use heapless::String;
+use postcard::{from_bytes, to_slice};
+use serde::{Deserialize, Serialize};
+
+use crate::bssid::WifiCounting;
+use crate::journal::{Journal, JournalError};
+use crate::time::format::ClockFormat;
+use crate::time::tz::TimeZone;
+use crate::time::TimeSource;
+use crate::wifi::{WifiNetwork, WifiSecurity, MAX_WIFI_NETWORKS};
+
+/// Marks a journal payload as a config
+const CONFIG_MAGIC: [u8; 4] = *b"BCFG";
+/// Bumped whenever [`Config`] changes shape
+pub const CONFIG_VERSION: u8 = 1;
+const HEADER_LEN: usize = CONFIG_MAGIC.len() + 1;
+/// Biggest a saved config can be, with every text setting at its longest it takes about 850 bytes
+pub const MAX_CONFIG_LEN: usize = 1024;
+
+const WIFI_SSID_KEYS: [&str; MAX_WIFI_NETWORKS] =
+    ["WIFI_SSID", "WIFI_SSID_2", "WIFI_SSID_3", "WIFI_SSID_4"];
+const WIFI_PASSWORD_KEYS: [&str; MAX_WIFI_NETWORKS] = [
+    "WIFI_PASSWORD",
+    "WIFI_PASSWORD_2",
+    "WIFI_PASSWORD_3",
+    "WIFI_PASSWORD_4",
+];
+const WIFI_SECURITY_KEYS: [&str; MAX_WIFI_NETWORKS] = [
+    "WIFI_SECURITY",
+    "WIFI_SECURITY_2",
+    "WIFI_SECURITY_3",
+    "WIFI_SECURITY_4",
+];
+
+/// A setting, named the same as in the `.env` file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigKey {
+    Name,
+    Details,
+    /// Settings for the saved wifi network at this index, 0 has the highest priority
+    WifiSsid(u8),
+    WifiPassword(u8),
+    WifiSecurity(u8),
+    /// How unique networks are counted, see [`WifiCounting`]
+    WifiCounting,
+    TimeSource,
+    TimeApi,
+    SntpServer,
+    Tz,
+    ClockFormat,
+    /// Partial refreshes before the display gets a full one, see
+    /// [`RefreshPolicy`](crate::badge_display::refresh::RefreshPolicy)
+    FullRefreshAfter,
+    /// What wifi image uploads have to send to be saved, empty turns them off
+    UploadKey,
+}
+
+impl ConfigKey {
+    pub const ALL: [ConfigKey; 22] = [
+        Self::Name,
+        Self::Details,
+        Self::WifiSsid(0),
+        Self::WifiPassword(0),
+        Self::WifiSecurity(0),
+        Self::WifiSsid(1),
+        Self::WifiPassword(1),
+        Self::WifiSecurity(1),
+        Self::WifiSsid(2),
+        Self::WifiPassword(2),
+        Self::WifiSecurity(2),
+        Self::WifiSsid(3),
+        Self::WifiPassword(3),
+        Self::WifiSecurity(3),
+        Self::WifiCounting,
+        Self::TimeSource,
+        Self::TimeApi,
+        Self::SntpServer,
+        Self::Tz,
+        Self::ClockFormat,
+        Self::FullRefreshAfter,
+        Self::UploadKey,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Name => "NAME",
+            Self::Details => "DETAILS",
+            Self::WifiSsid(index) => WIFI_SSID_KEYS[*index as usize],
+            Self::WifiPassword(index) => WIFI_PASSWORD_KEYS[*index as usize],
+            Self::WifiSecurity(index) => WIFI_SECURITY_KEYS[*index as usize],
+            Self::WifiCounting => "WIFI_COUNTING",
+            Self::TimeSource => "TIME_SOURCE",
+            Self::TimeApi => "TIME_API",
+            Self::SntpServer => "SNTP_SERVER",
+            Self::Tz => "TZ",
+            Self::ClockFormat => "CLOCK_FORMAT",
+            Self::FullRefreshAfter => "FULL_REFRESH_AFTER",
+            Self::UploadKey => "UPLOAD_KEY",
+        }
+    }
+
+    /// Key for a `.env` style name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|key| key.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Value used when the `.env` leaves the setting out, `None` for settings it has to have
+    pub fn default_value(&self) -> Option<&'static str> {
+        match self {
+            Self::WifiSsid(0) => None,
+            Self::WifiSsid(_) | Self::WifiPassword(_) => Some(""),
+            Self::WifiSecurity(_) => Some("auto"),
+            Self::WifiCounting => Some("stop_when_full"),
+            Self::TimeSource => Some("http"),
+            Self::SntpServer => Some("pool.ntp.org"),
+            Self::Tz => Some("UTC0"),
+            Self::ClockFormat => Some("12h"),
+            Self::FullRefreshAfter => Some("120"),
+            Self::UploadKey => Some(""),
+            _ => None,
+        }
+    }
+
+    /// Settings the console never prints, they can only be set
+    pub fn is_secret(&self) -> bool {
+        matches!(self, Self::WifiPassword(_) | Self::UploadKey)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// The `.env` does not have a setting that has no default
+    MissingKey(ConfigKey),
+    TooLong(ConfigKey),
+    InvalidValue(ConfigKey),
+    /// Nothing has been saved to flash yet
+    NotFound,
+    /// A config saved by firmware that uses a different [`CONFIG_VERSION`]
+    UnsupportedVersion(u8),
+    Serialization,
+    Journal(JournalError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingKey(key) => write!(f, "{} is not set", key.name()),
+            Self::TooLong(key) => write!(f, "{} is too long", key.name()),
+            Self::InvalidValue(key) => write!(f, "{} is not valid", key.name()),
+            Self::NotFound => f.write_str("no config saved"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "config version {} is not supported", version)
+            }
+            Self::Serialization => f.write_str("config could not be serialized"),
+            Self::Journal(e) => e.fmt(f),
+        }
+    }
+}
+
+/// Every runtime setting of the badge. Values are checked when set so the fields can be used
+/// without checking them again
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub name: String<32>,
+    pub details: String<64>,
+    /// In priority order, see [`crate::wifi`]
+    pub wifi_networks: [WifiNetwork; MAX_WIFI_NETWORKS],
+    pub wifi_counting: WifiCounting,
+    pub time_source: TimeSource,
+    pub time_api: String<128>,
+    pub sntp_server: String<64>,
+    /// POSIX TZ string, see [`Config::time_zone`]
+    pub tz: String<64>,
+    pub clock_format: ClockFormat,
+    /// Never 0
+    pub full_refresh_after: u16,
+    /// See [`ConfigKey::UploadKey`]
+    pub upload_key: String<64>,
+}
+
+impl Config {
+    /// Builds a config from `.env` style settings, `lookup` returns the value for a key name
+    pub fn from_env<'a>(lookup: impl Fn(&str) -> Option<&'a str>) -> Result<Self, ConfigError> {
+        let mut config = Self {
+            name: String::new(),
+            details: String::new(),
+            wifi_networks: core::array::from_fn(|_| WifiNetwork::unused()),
+            wifi_counting: WifiCounting::default(),
+            time_source: TimeSource::Http,
+            time_api: String::new(),
+            sntp_server: String::new(),
+            tz: String::new(),
+            clock_format: ClockFormat::default(),
+            full_refresh_after: 0,
+            upload_key: String::new(),
+        };
+        for key in ConfigKey::ALL {
+            let value = lookup(key.name())
+                .or(key.default_value())
+                .ok_or(ConfigError::MissingKey(key))?;
+            config.set(key, value)?;
+        }
+        Ok(config)
+    }
+
+    /// Checks and changes one setting
+    pub fn set(&mut self, key: ConfigKey, value: &str) -> Result<(), ConfigError> {
+        fn text<const N: usize>(key: ConfigKey, value: &str) -> Result<String<N>, ConfigError> {
+            String::try_from(value).map_err(|_| ConfigError::TooLong(key))
+        }
+
+        match key {
+            ConfigKey::Name => self.name = text(key, value)?,
+            ConfigKey::Details => self.details = text(key, value)?,
+            ConfigKey::WifiSsid(index) => {
+                self.wifi_networks[index as usize].ssid = text(key, value)?
+            }
+            ConfigKey::WifiPassword(index) => {
+                self.wifi_networks[index as usize].password = text(key, value)?
+            }
+            ConfigKey::WifiSecurity(index) => {
+                self.wifi_networks[index as usize].security =
+                    WifiSecurity::from_config(value).ok_or(ConfigError::InvalidValue(key))?
+            }
+            ConfigKey::WifiCounting => {
+                self.wifi_counting =
+                    WifiCounting::from_config(value).ok_or(ConfigError::InvalidValue(key))?
+            }
+            ConfigKey::TimeSource => {
+                self.time_source =
+                    TimeSource::from_config(value).ok_or(ConfigError::InvalidValue(key))?
+            }
+            ConfigKey::TimeApi => self.time_api = text(key, value)?,
+            ConfigKey::SntpServer => self.sntp_server = text(key, value)?,
+            ConfigKey::Tz => {
+                TimeZone::parse(value).map_err(|_| ConfigError::InvalidValue(key))?;
+                self.tz = text(key, value.trim())?;
+            }
+            ConfigKey::ClockFormat => {
+                self.clock_format =
+                    ClockFormat::from_config(value).ok_or(ConfigError::InvalidValue(key))?
+            }
+            ConfigKey::FullRefreshAfter => {
+                self.full_refresh_after = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&after| after > 0)
+                    .ok_or(ConfigError::InvalidValue(key))?
+            }
+            ConfigKey::UploadKey => self.upload_key = text(key, value)?,
+        }
+        Ok(())
+    }
+
+    /// Writes a setting out the same way it is written in the `.env`
+    pub fn write_value<W: Write>(&self, key: ConfigKey, out: &mut W) -> fmt::Result {
+        match key {
+            ConfigKey::Name => out.write_str(&self.name),
+            ConfigKey::Details => out.write_str(&self.details),
+            ConfigKey::WifiSsid(index) => out.write_str(&self.wifi_networks[index as usize].ssid),
+            ConfigKey::WifiPassword(index) => {
+                out.write_str(&self.wifi_networks[index as usize].password)
+            }
+            ConfigKey::WifiSecurity(index) => {
+                write!(out, "{}", self.wifi_networks[index as usize].security)
+            }
+            ConfigKey::WifiCounting => write!(out, "{}", self.wifi_counting),
+            ConfigKey::TimeSource => write!(out, "{}", self.time_source),
+            ConfigKey::TimeApi => out.write_str(&self.time_api),
+            ConfigKey::SntpServer => out.write_str(&self.sntp_server),
+            ConfigKey::Tz => out.write_str(&self.tz),
+            ConfigKey::ClockFormat => write!(out, "{}", self.clock_format),
+            ConfigKey::FullRefreshAfter => write!(out, "{}", self.full_refresh_after),
+            ConfigKey::UploadKey => out.write_str(&self.upload_key),
+        }
+    }
+
+    /// The time zone the clock is shown in, `tz` was checked when it was set so this only falls
+    /// back to UTC if the flash was corrupted
+    pub fn time_zone(&self) -> TimeZone {
+        TimeZone::parse(&self.tz).unwrap_or_else(|_| TimeZone::utc())
+    }
+}
+
+/// Writes `config` as the newest config in `journal`, the previous config is kept until it is
+/// written
+pub fn save_config_to_journal<F: NorFlash>(
+    flash: &mut F,
+    journal: &Journal,
+    config: &Config,
+) -> Result<(), ConfigError> {
+    let mut buf = [0u8; MAX_CONFIG_LEN];
+    buf[..CONFIG_MAGIC.len()].copy_from_slice(&CONFIG_MAGIC);
+    buf[CONFIG_MAGIC.len()] = CONFIG_VERSION;
+    let len = to_slice(config, &mut buf[HEADER_LEN..])
+        .map_err(|_| ConfigError::Serialization)?
+        .len();
+    journal
+        .append(flash, &buf[..HEADER_LEN + len])
+        .map_err(ConfigError::Journal)
+}
+
+/// Reads the newest config in `journal`
+pub fn read_config_from_journal<F: NorFlash>(
+    flash: &mut F,
+    journal: &Journal,
+) -> Result<Config, ConfigError> {
+    let mut buf = [0u8; MAX_CONFIG_LEN];
+    let data = journal.read_newest(flash, &mut buf).map_err(|e| match e {
+        JournalError::Empty => ConfigError::NotFound,
+        e => ConfigError::Journal(e),
+    })?;
+    match data.strip_prefix(&CONFIG_MAGIC) {
+        Some([CONFIG_VERSION, payload @ ..]) => {
+            from_bytes(payload).map_err(|_| ConfigError::Serialization)
+        }
+        Some([version, ..]) => Err(ConfigError::UnsupportedVersion(*version)),
+        _ => Err(ConfigError::Serialization),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem_flash::MemFlash;
+    use crate::time::format::HourFormat;
+
+    const ENV: [(&str, &str); 6] = [
+        ("NAME", "Ferris"),
+        ("DETAILS", "Likes rust"),
+        ("WIFI_SSID", "RustConf"),
+        ("WIFI_PASSWORD", "hunter22"),
+        (
+            "TIME_API",
+            "http://worldtimeapi.org/api/timezone/America/Chicago",
+        ),
+        ("TZ", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ];
+
+    fn config() -> Config {
+        Config::from_env(|key| {
+            ENV.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| *value)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn seeds_from_env_with_defaults() {
+        let config = config();
+        assert_eq!(config.name, "Ferris");
+        assert_eq!(config.wifi_networks[0].ssid, "RustConf");
+        assert_eq!(config.wifi_networks[0].password, "hunter22");
+        assert_eq!(config.wifi_networks[0].security, WifiSecurity::Auto);
+        assert!(!config.wifi_networks[1].is_used());
+        assert_eq!(config.time_source, TimeSource::Http);
+        assert_eq!(config.sntp_server, "pool.ntp.org");
+        assert_eq!(config.tz, "CET-1CEST,M3.5.0,M10.5.0/3");
+        assert_eq!(config.clock_format, ClockFormat::default());
+        assert_eq!(config.full_refresh_after, 120);
+        assert_eq!(
+            config.time_zone(),
+            TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap()
+        );
+    }
+
+    #[test]
+    fn env_without_required_keys_is_an_error() {
+        assert_eq!(
+            Config::from_env(|key| (key == "NAME").then_some("Ferris")),
+            Err(ConfigError::MissingKey(ConfigKey::Details))
+        );
+    }
+
+    #[test]
+    fn set_checks_values() {
+        let mut config = config();
+        config.set(ConfigKey::ClockFormat, "24h,seconds").unwrap();
+        assert_eq!(config.clock_format.hour, HourFormat::TwentyFourHour);
+        config.set(ConfigKey::TimeSource, "sntp").unwrap();
+        assert_eq!(config.time_source, TimeSource::Sntp);
+
+        let before = config.clone();
+        assert_eq!(
+            config.set(ConfigKey::Tz, "Europe/Berlin"),
+            Err(ConfigError::InvalidValue(ConfigKey::Tz))
+        );
+        assert_eq!(
+            config.set(ConfigKey::ClockFormat, "iso"),
+            Err(ConfigError::InvalidValue(ConfigKey::ClockFormat))
+        );
+        assert_eq!(
+            config.set(ConfigKey::FullRefreshAfter, "0"),
+            Err(ConfigError::InvalidValue(ConfigKey::FullRefreshAfter))
+        );
+        assert_eq!(
+            config.set(ConfigKey::Name, "Ferris the crab who is far too long"),
+            Err(ConfigError::TooLong(ConfigKey::Name))
+        );
+        assert_eq!(config, before);
+    }
+
+    #[test]
+    fn values_write_back_as_they_are_set() {
+        let mut config = config();
+        config.set(ConfigKey::ClockFormat, "ymd, 24H").unwrap();
+        for key in ConfigKey::ALL {
+            let mut written: String<128> = String::new();
+            config.write_value(key, &mut written).unwrap();
+            let mut reread = config.clone();
+            reread.set(key, &written).unwrap();
+            assert_eq!(reread, config, "{}", key.name());
+        }
+    }
+
+    const JOURNAL: Journal = Journal::new(0, 2);
+
+    #[test]
+    fn round_trips_through_the_journal() {
+        let mut flash = MemFlash::new();
+        assert_eq!(
+            read_config_from_journal(&mut flash, &JOURNAL),
+            Err(ConfigError::NotFound)
+        );
+
+        let mut config = config();
+        config.set(ConfigKey::Name, "Corro").unwrap();
+        save_config_to_journal(&mut flash, &JOURNAL, &config).unwrap();
+        assert_eq!(read_config_from_journal(&mut flash, &JOURNAL), Ok(config));
+    }
+
+    #[test]
+    fn longest_config_fits() {
+        let mut config = config();
+        for key in ConfigKey::ALL {
+            let mut longest: String<128> = String::new();
+            while longest.push('x').is_ok() && config.set(key, &longest).is_ok() {}
+        }
+        let mut flash = MemFlash::new();
+        save_config_to_journal(&mut flash, &JOURNAL, &config).unwrap();
+        assert_eq!(read_config_from_journal(&mut flash, &JOURNAL), Ok(config));
+    }
+
+    #[test]
+    fn losing_power_while_saving_keeps_a_whole_config() {
+        let mut flash = MemFlash::new();
+        let old = config();
+        save_config_to_journal(&mut flash, &JOURNAL, &old).unwrap();
+        let mut new = old.clone();
+        new.set(ConfigKey::Name, "Corro").unwrap();
+
+        let mut whole = flash.clone();
+        let before = whole.steps();
+        save_config_to_journal(&mut whole, &JOURNAL, &new).unwrap();
+        let steps = whole.steps() - before;
+        for cut_after in 0..steps {
+            let mut torn = flash.clone();
+            torn.cut_power_after(cut_after);
+            let _ = save_config_to_journal(&mut torn, &JOURNAL, &new);
+            torn.restore_power();
+            //Power lost after the last byte of the record leaves the new config in use
+            let loaded = read_config_from_journal(&mut torn, &JOURNAL).unwrap();
+            assert!(loaded == old || loaded == new, "step {cut_after}");
+        }
+    }
+
+    #[test]
+    fn other_versions_are_not_loaded() {
+        let mut flash = MemFlash::new();
+        let mut payload = [0u8; HEADER_LEN + 1];
+        payload[..CONFIG_MAGIC.len()].copy_from_slice(&CONFIG_MAGIC);
+        payload[CONFIG_MAGIC.len()] = 0;
+        JOURNAL.append(&mut flash, &payload).unwrap();
+        assert_eq!(
+            read_config_from_journal(&mut flash, &JOURNAL),
+            Err(ConfigError::UnsupportedVersion(0))
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Commands typed into the badge's USB serial console.
+//!
+//! They change the [`Config`](crate::config::Config), export the wardrive log and manage the
+//! images in flash. Wifi image uploads start with the same `upload` line.
+
+use core::fmt;
+
+use embedded_graphics::prelude::{Point, Size};
+
+use crate::config::ConfigKey;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConsoleError {
+    /// Not a command, or not text at all
+    UnknownCommand,
+    /// `get` or `set` a setting that does not exist
+    UnknownKey,
+    /// A command given the wrong number or kind of values
+    InvalidArguments,
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UnknownCommand => "unknown command, type help",
+            Self::UnknownKey => "unknown setting",
+            Self::InvalidArguments => "wrong values for the command, type help",
+        })
+    }
+}
+
+/// A line typed into the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'a> {
+    /// `get KEY`
+    Get(ConfigKey),
+    /// `set KEY VALUE`, the value is the rest of the line
+    Set(ConfigKey, &'a str),
+    /// `list` every setting
+    List,
+    /// `save` the config to flash
+    Save,
+    /// `reset` back to the settings the firmware was built with
+    Reset,
+    /// `reboot` to start using the saved config
+    Reboot,
+    /// `wigle` prints the wardriving log as WiGLE CSV
+    Wigle,
+    /// `images` lists the images in flash
+    Images,
+    /// `upload NAME WIDTH HEIGHT X Y [KEY]`, the rows of the image follow a line each, see
+    /// [`ImageUpload`](crate::image_store::ImageUpload). Uploads over wifi end with the
+    /// [`UploadKey`](ConfigKey::UploadKey), over USB it can be left off
+    Upload {
+        name: &'a str,
+        size: Size,
+        position: Point,
+        key: Option<&'a str>,
+    },
+    /// `delete NAME` takes an image out of flash
+    Delete(&'a str),
+    Help,
+}
+
+impl<'a> Command<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, ConsoleError> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let key = |name: &str| ConfigKey::from_name(name).ok_or(ConsoleError::UnknownKey);
+
+        match command {
+            c if c.eq_ignore_ascii_case("get") => Ok(Self::Get(key(rest)?)),
+            c if c.eq_ignore_ascii_case("set") => {
+                let (name, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                Ok(Self::Set(key(name)?, value.trim()))
+            }
+            c if c.eq_ignore_ascii_case("list") => Ok(Self::List),
+            c if c.eq_ignore_ascii_case("save") => Ok(Self::Save),
+            c if c.eq_ignore_ascii_case("reset") => Ok(Self::Reset),
+            c if c.eq_ignore_ascii_case("reboot") => Ok(Self::Reboot),
+            c if c.eq_ignore_ascii_case("wigle") => Ok(Self::Wigle),
+            c if c.eq_ignore_ascii_case("images") => Ok(Self::Images),
+            c if c.eq_ignore_ascii_case("upload") => {
+                let mut values = rest.split_whitespace();
+                let name = values.next().ok_or(ConsoleError::InvalidArguments)?;
+                let mut number = || {
+                    values
+                        .next()
+                        .and_then(|value| value.parse::<i32>().ok())
+                        .ok_or(ConsoleError::InvalidArguments)
+                };
+                let size = Size::new(number()?.max(0) as u32, number()?.max(0) as u32);
+                let position = Point::new(number()?, number()?);
+                let key = values.next();
+                if values.next().is_some() {
+                    return Err(ConsoleError::InvalidArguments);
+                }
+                Ok(Self::Upload {
+                    name,
+                    size,
+                    position,
+                    key,
+                })
+            }
+            c if c.eq_ignore_ascii_case("delete") => match rest {
+                "" => Err(ConsoleError::InvalidArguments),
+                name => Ok(Self::Delete(name)),
+            },
+            c if c.eq_ignore_ascii_case("help") => Ok(Self::Help),
+            _ => Err(ConsoleError::UnknownCommand),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse("get wifi_ssid"),
+            Ok(Command::Get(ConfigKey::WifiSsid(0)))
+        );
+        assert_eq!(
+            Command::parse("get WIFI_SECURITY_3"),
+            Ok(Command::Get(ConfigKey::WifiSecurity(2)))
+        );
+        assert_eq!(
+            Command::parse("set WIFI_PASSWORD  pass word=1 \r"),
+            Ok(Command::Set(ConfigKey::WifiPassword(0), "pass word=1"))
+        );
+        assert_eq!(
+            Command::parse("SET NAME"),
+            Ok(Command::Set(ConfigKey::Name, ""))
+        );
+        assert_eq!(Command::parse("list"), Ok(Command::List));
+        assert_eq!(Command::parse(" save\n"), Ok(Command::Save));
+        assert_eq!(Command::parse("WIGLE"), Ok(Command::Wigle));
+        assert_eq!(Command::parse("images"), Ok(Command::Images));
+        assert_eq!(
+            Command::parse("upload qr 40 40 200 -3"),
+            Ok(Command::Upload {
+                name: "qr",
+                size: Size::new(40, 40),
+                position: Point::new(200, -3),
+                key: None,
+            })
+        );
+        assert_eq!(
+            Command::parse("upload qr 40 40 200 -3 hunter2"),
+            Ok(Command::Upload {
+                name: "qr",
+                size: Size::new(40, 40),
+                position: Point::new(200, -3),
+                key: Some("hunter2"),
+            })
+        );
+        assert_eq!(
+            Command::parse("upload qr 40 40 200 -3 hunter2 x"),
+            Err(ConsoleError::InvalidArguments)
+        );
+        assert_eq!(
+            Command::parse("upload qr 40 40 200"),
+            Err(ConsoleError::InvalidArguments)
+        );
+        assert_eq!(Command::parse("delete qr"), Ok(Command::Delete("qr")));
+        assert_eq!(
+            Command::parse("delete"),
+            Err(ConsoleError::InvalidArguments)
+        );
+        assert_eq!(Command::parse("get COLOUR"), Err(ConsoleError::UnknownKey));
+        assert_eq!(Command::parse("erase"), Err(ConsoleError::UnknownCommand));
+    }
+}
@@ -0,0 +1,316 @@
+//! Parser for the `.env` file the badge's default config is built from.
+//!
+//! Each line is `KEY=value`, optionally starting with `export `. Values can be
+//! * unquoted, where `=` is kept and a `#` after a space starts a comment
+//! * single quoted, taken exactly as written
+//! * double quoted, where `\n`, `\r`, `\t`, `\"` and `\\` are escapes
+//!
+//! Blank lines and lines starting with `#` are skipped. When a key is set more than once the last
+//! one wins, the same as a shell sourcing the file.
+
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvErrorKind {
+    /// The line has no `=`
+    MissingEquals,
+    /// The key is empty or has characters other than letters, digits and `_`
+    InvalidKey,
+    /// A quoted value has no closing quote
+    UnterminatedQuote,
+    /// Something other than a comment follows a quoted value
+    TrailingCharacters,
+}
+
+/// Where and why a `.env` could not be parsed. Lines start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvError {
+    pub line: usize,
+    pub kind: EnvErrorKind,
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self.kind {
+            EnvErrorKind::MissingEquals => "expected KEY=value",
+            EnvErrorKind::InvalidKey => "keys can only have letters, digits and _",
+            EnvErrorKind::UnterminatedQuote => "the value is missing its closing quote",
+            EnvErrorKind::TrailingCharacters => "only a # comment can follow a quoted value",
+        };
+        write!(f, "line {}: {}", self.line, reason)
+    }
+}
+
+/// A value as written in the file. Use its `Display` impl to get the value with escapes applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvValue<'a> {
+    text: &'a str,
+    /// Double quoted values have escapes to apply
+    escaped: bool,
+}
+
+impl<'a> EnvValue<'a> {
+    /// The value when it has nothing to unescape
+    pub fn as_str(&self) -> Option<&'a str> {
+        if self.escaped && self.text.contains('\\') {
+            None
+        } else {
+            Some(self.text)
+        }
+    }
+}
+
+impl fmt::Display for EnvValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.escaped {
+            return f.write_str(self.text);
+        }
+        let mut chars = self.text.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                fmt::Write::write_char(f, c)?;
+                continue;
+            }
+            match chars.next() {
+                Some('n') => f.write_str("\n")?,
+                Some('r') => f.write_str("\r")?,
+                Some('t') => f.write_str("\t")?,
+                Some(c @ ('"' | '\\')) => fmt::Write::write_char(f, c)?,
+                //Not an escape, keep the backslash
+                Some(c) => {
+                    f.write_str("\\")?;
+                    fmt::Write::write_char(f, c)?;
+                }
+                None => f.write_str("\\")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvEntry<'a> {
+    /// Line the entry is on, starting at 1
+    pub line: usize,
+    pub key: &'a str,
+    pub value: EnvValue<'a>,
+}
+
+/// Every `KEY=value` in the file in order, duplicates included
+pub fn parse_env(data: &str) -> impl Iterator<Item = Result<EnvEntry<'_>, EnvError>> {
+    data.lines()
+        .enumerate()
+        .filter_map(|(index, line)| parse_line(index + 1, line).transpose())
+}
+
+/// The last value set for `key`, or the first error in the file
+pub fn find_env_value<'a>(data: &'a str, key: &str) -> Result<Option<EnvValue<'a>>, EnvError> {
+    let mut found = None;
+    for entry in parse_env(data) {
+        let entry = entry?;
+        if entry.key == key {
+            found = Some(entry.value);
+        }
+    }
+    Ok(found)
+}
+
+fn parse_line(line_number: usize, line: &str) -> Result<Option<EnvEntry<'_>>, EnvError> {
+    let error = |kind| EnvError {
+        line: line_number,
+        kind,
+    };
+
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let (key, rest) = line
+        .split_once('=')
+        .ok_or(error(EnvErrorKind::MissingEquals))?;
+    let key = key.trim();
+    let valid_key = key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && key.chars().next().is_some_and(|c| !c.is_ascii_digit());
+    if !valid_key {
+        return Err(error(EnvErrorKind::InvalidKey));
+    }
+
+    let rest = rest.trim_start();
+    let (value, after) = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let quoted = &rest[1..];
+            let end = closing_quote(quoted, quote).ok_or(error(EnvErrorKind::UnterminatedQuote))?;
+            let value = EnvValue {
+                text: &quoted[..end],
+                escaped: quote == '"',
+            };
+            (value, &quoted[end + 1..])
+        }
+        _ => {
+            //A # only starts a comment after whitespace so values like `#ff0000` or `a#b` work
+            let starts_comment =
+                |i: usize, c: char| c == '#' && rest[..i].ends_with(|x: char| x.is_whitespace());
+            let end = rest
+                .char_indices()
+                .find(|(i, c)| starts_comment(*i, *c))
+                .map_or(rest.len(), |(i, _)| i);
+            let value = EnvValue {
+                text: rest[..end].trim_end(),
+                escaped: false,
+            };
+            (value, "")
+        }
+    };
+
+    let after = after.trim_start();
+    if !after.is_empty() && !after.starts_with('#') {
+        return Err(error(EnvErrorKind::TrailingCharacters));
+    }
+    Ok(Some(EnvEntry {
+        line: line_number,
+        key,
+        value,
+    }))
+}
+
+/// Byte index of the quote closing a value, skipping escaped quotes in double quoted values
+fn closing_quote(quoted: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in quoted.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quote == '"' {
+            escaped = true;
+        } else if c == quote {
+            return Some(i);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::easy_format;
+    use heapless::String;
+
+    fn value(data: &str, key: &str) -> Option<String<64>> {
+        find_env_value(data, key)
+            .unwrap()
+            .map(|value| easy_format::<64>(format_args!("{}", value)))
+    }
+
+    #[test]
+    fn quoting() {
+        let env = r#"DOUBLE="Ferris"
+SINGLE='Likes rust'
+UNQUOTED=Crab
+EMPTY=
+EMPTY_QUOTES=""
+SINGLE_KEEPS_ESCAPES='a\nb'
+QUOTES_INSIDE='say "hi"'
+"#;
+        assert_eq!(value(env, "DOUBLE").unwrap(), "Ferris");
+        assert_eq!(value(env, "SINGLE").unwrap(), "Likes rust");
+        assert_eq!(value(env, "UNQUOTED").unwrap(), "Crab");
+        assert_eq!(value(env, "EMPTY").unwrap(), "");
+        assert_eq!(value(env, "EMPTY_QUOTES").unwrap(), "");
+        assert_eq!(value(env, "SINGLE_KEEPS_ESCAPES").unwrap(), "a\\nb");
+        assert_eq!(value(env, "QUOTES_INSIDE").unwrap(), "say \"hi\"");
+        assert_eq!(value(env, "MISSING"), None);
+    }
+
+    #[test]
+    fn equals_signs_in_values() {
+        let env = "WIFI_PASSWORD=\"pa==word\"\nUNQUOTED=a=b=c\nTIME_API=\"http://example.com/?tz=UTC&a=b\"";
+        assert_eq!(value(env, "WIFI_PASSWORD").unwrap(), "pa==word");
+        assert_eq!(value(env, "UNQUOTED").unwrap(), "a=b=c");
+        assert_eq!(
+            value(env, "TIME_API").unwrap(),
+            "http://example.com/?tz=UTC&a=b"
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let env = r#"DETAILS="Line one\nLine two"
+QUOTE="say \"hi\""
+BACKSLASH="C:\\badge"
+TAB="a\tb"
+UNKNOWN="\d stays"
+"#;
+        assert_eq!(value(env, "DETAILS").unwrap(), "Line one\nLine two");
+        assert_eq!(value(env, "QUOTE").unwrap(), "say \"hi\"");
+        assert_eq!(value(env, "BACKSLASH").unwrap(), "C:\\badge");
+        assert_eq!(value(env, "TAB").unwrap(), "a\tb");
+        assert_eq!(value(env, "UNKNOWN").unwrap(), "\\d stays");
+        assert_eq!(
+            find_env_value(env, "QUOTE").unwrap().unwrap().as_str(),
+            None
+        );
+        assert_eq!(find_env_value(env, "TAB").unwrap().unwrap().as_str(), None);
+        assert_eq!(
+            find_env_value("A=\"plain\"", "A")
+                .unwrap()
+                .unwrap()
+                .as_str(),
+            Some("plain")
+        );
+    }
+
+    #[test]
+    fn whitespace() {
+        let env = "  NAME  =  \"  Ferris  \"  \r\n\tDETAILS=  Likes rust  \r\nexport TZ=UTC0\n";
+        assert_eq!(value(env, "NAME").unwrap(), "  Ferris  ");
+        assert_eq!(value(env, "DETAILS").unwrap(), "Likes rust");
+        assert_eq!(value(env, "TZ").unwrap(), "UTC0");
+    }
+
+    #[test]
+    fn comments() {
+        let env = r##"# The badge owner
+NAME="Ferris" # trailing comment
+  # indented comment
+DETAILS=Likes rust # comment
+COLOR=#ff0000
+HASH="# not a comment"
+TAG=a#b
+"##;
+        assert_eq!(value(env, "NAME").unwrap(), "Ferris");
+        assert_eq!(value(env, "DETAILS").unwrap(), "Likes rust");
+        assert_eq!(value(env, "COLOR").unwrap(), "#ff0000");
+        assert_eq!(value(env, "HASH").unwrap(), "# not a comment");
+        assert_eq!(value(env, "TAG").unwrap(), "a#b");
+    }
+
+    #[test]
+    fn duplicate_keys_use_the_last_value() {
+        let env = "NAME=\"First\"\nNAME=\"Second\"\n";
+        assert_eq!(value(env, "NAME").unwrap(), "Second");
+        let lines: heapless::Vec<usize, 4> =
+            parse_env(env).map(|entry| entry.unwrap().line).collect();
+        assert_eq!(lines.as_slice(), &[1, 2]);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let cases = [
+            ("NAME=\"a\"\nJUST TEXT", 2, EnvErrorKind::MissingEquals),
+            ("=value", 1, EnvErrorKind::InvalidKey),
+            ("MY KEY=value", 1, EnvErrorKind::InvalidKey),
+            ("1KEY=value", 1, EnvErrorKind::InvalidKey),
+            ("\n\nNAME=\"Ferris", 3, EnvErrorKind::UnterminatedQuote),
+            ("NAME='Ferris", 1, EnvErrorKind::UnterminatedQuote),
+            ("NAME=\"Fer\\\"", 1, EnvErrorKind::UnterminatedQuote),
+            ("NAME=\"Ferris\" crab", 1, EnvErrorKind::TrailingCharacters),
+        ];
+        for (env, line, kind) in cases {
+            assert_eq!(
+                find_env_value(env, "NAME"),
+                Err(EnvError { line, kind }),
+                "{env}"
+            );
+        }
+    }
+}
@@ -12,3 +12,20 @@ pub fn easy_format<const N: usize>(args: Arguments<'_>) -> String<N> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_into_string() {
+        let formatted = easy_format::<16>(format_args!("{}F {}%", 72, 40));
+        assert_eq!(formatted.as_str(), "72F 40%");
+    }
+
+    #[test]
+    #[should_panic(expected = "Error formatting the string")]
+    fn panics_when_too_long() {
+        let _ = easy_format::<4>(format_args!("{}", "too long"));
+    }
+}
@@ -0,0 +1,388 @@
+//! Images for the badge screen kept in their own flash region, so they can be uploaded over USB
+//! or wifi without rebuilding the firmware.
+//!
+//! Each image takes a whole erase sector, so replacing or deleting one never touches the others
+//! and losing power mid-write only loses that image. Images are found by reading every slot when
+//! the store is loaded, their order on the badge is the order of their slots. A slot is:
+//!
+//! | bytes | field                                                     |
+//! |-------|-----------------------------------------------------------|
+//! | 4     | marker `BIMG`                                             |
+//! | 1     | name length                                               |
+//! | 16    | name                                                      |
+//! | 2     | width, little endian                                      |
+//! | 2     | height                                                    |
+//! | 2     | x of the top left corner on the screen, signed            |
+//! | 2     | y                                                         |
+//! | 3     | 0                                                         |
+//! | 4     | CRC-32 of everything before it and the pixels             |
+//! | ..    | pixels as [`DisplayImage::pixels`], up to [`MAX_IMAGE_LEN`] |
+
+use core::fmt;
+
+use embedded_graphics::prelude::*;
+use embedded_storage::nor_flash::NorFlash;
+use heapless::{String, Vec};
+
+use crate::badge_display::display_image::{
+    row_len, DisplayImage, ImageError, IMAGE_NAME_LEN, MAX_IMAGE_LEN, MAX_ROW_LEN,
+};
+use crate::journal::{crc32, CRC_INIT};
+use crate::save::ERASE_SIZE;
+
+const SLOT_MARKER: [u8; 4] = *b"BIMG";
+const CRC_OFFSET: usize = 32;
+/// Bytes before the pixels
+pub const IMAGE_HEADER_LEN: usize = CRC_OFFSET + 4;
+const SLOT_LEN: usize = IMAGE_HEADER_LEN + MAX_IMAGE_LEN;
+const _: () = assert!(SLOT_LEN <= ERASE_SIZE, "an image has to fit in a sector");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ImageStoreError {
+    Flash,
+    /// Every slot has an image, one has to be deleted first
+    Full,
+    /// No image with that name or index
+    NotFound,
+}
+
+impl fmt::Display for ImageStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Flash => "flash error",
+            Self::Full => "no room for another image, delete one first",
+            Self::NotFound => "no such image",
+        })
+    }
+}
+
+/// Which slot holds an image and its name, so they can be listed without reading flash
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexEntry {
+    slot: u8,
+    name: String<IMAGE_NAME_LEN>,
+}
+
+/// Up to `SLOTS` images, one per erase sector starting at `offset` in flash
+#[derive(Debug, Clone)]
+pub struct ImageStore<const SLOTS: usize> {
+    offset: u32,
+    /// Sorted by slot
+    index: Vec<IndexEntry, SLOTS>,
+}
+
+impl<const SLOTS: usize> ImageStore<SLOTS> {
+    /// An empty store, [`ImageStore::load`] finds the images already in flash
+    pub const fn new(offset: u32) -> Self {
+        assert!(SLOTS <= u8::MAX as usize, "slots are numbered with a u8");
+        Self {
+            offset,
+            index: Vec::new(),
+        }
+    }
+
+    /// Bytes of flash the store covers
+    pub const fn flash_len(&self) -> u32 {
+        (SLOTS * ERASE_SIZE) as u32
+    }
+
+    /// Images in the store
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Names of the images in the order they are shown
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.index.iter().map(|entry| entry.name.as_str())
+    }
+
+    /// Finds the images in flash. Slots that fail their CRC, like one being written when power
+    /// was lost, are left out and reused
+    pub fn load<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), ImageStoreError> {
+        self.index.clear();
+        for slot in 0..SLOTS {
+            if let Some(image) = self.read_slot(flash, slot)? {
+                //One entry per slot so it always fits
+                let _ = self.index.push(IndexEntry {
+                    slot: slot as u8,
+                    name: String::try_from(image.name()).unwrap_or_default(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the `index`th image
+    pub fn read<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        index: usize,
+    ) -> Result<DisplayImage, ImageStoreError> {
+        let entry = self.index.get(index).ok_or(ImageStoreError::NotFound)?;
+        self.read_slot(flash, entry.slot as usize)?
+            .ok_or(ImageStoreError::NotFound)
+    }
+
+    /// Writes `image`, over the one with the same name if there is one. Returns its index
+    pub fn save<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        image: &DisplayImage,
+    ) -> Result<usize, ImageStoreError> {
+        let (index, slot) = match self.find(image.name()) {
+            Some(index) => (index, self.index[index].slot),
+            None => {
+                let slot = (0..SLOTS as u8)
+                    .find(|slot| !self.index.iter().any(|entry| entry.slot == *slot))
+                    .ok_or(ImageStoreError::Full)?;
+                let index = self
+                    .index
+                    .iter()
+                    .take_while(|entry| entry.slot < slot)
+                    .count();
+                (index, slot)
+            }
+        };
+        //Out of the index while it is rewritten, so a failed write does not leave it pointing at
+        //a bad slot
+        if self
+            .index
+            .get(index)
+            .is_some_and(|entry| entry.slot == slot)
+        {
+            self.index.remove(index);
+        }
+        let offset = self.slot_offset(slot as usize);
+        flash
+            .erase(offset, offset + ERASE_SIZE as u32)
+            .map_err(|_| ImageStoreError::Flash)?;
+        let (bytes, len) = encode_slot(image);
+        flash
+            .write(offset, &bytes[..len])
+            .map_err(|_| ImageStoreError::Flash)?;
+        //There was a free slot or the entry was just removed
+        let _ = self.index.insert(
+            index,
+            IndexEntry {
+                slot,
+                name: String::try_from(image.name()).unwrap_or_default(),
+            },
+        );
+        Ok(index)
+    }
+
+    /// Erases the image called `name`
+    pub fn delete<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        name: &str,
+    ) -> Result<(), ImageStoreError> {
+        let index = self.find(name).ok_or(ImageStoreError::NotFound)?;
+        let offset = self.slot_offset(self.index[index].slot as usize);
+        self.index.remove(index);
+        flash
+            .erase(offset, offset + ERASE_SIZE as u32)
+            .map_err(|_| ImageStoreError::Flash)
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.index.iter().position(|entry| entry.name == name)
+    }
+
+    fn slot_offset(&self, slot: usize) -> u32 {
+        self.offset + (slot * ERASE_SIZE) as u32
+    }
+
+    /// The image in `slot`, `None` if it is erased or fails its CRC
+    fn read_slot<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        slot: usize,
+    ) -> Result<Option<DisplayImage>, ImageStoreError> {
+        let mut header = [0u8; IMAGE_HEADER_LEN];
+        flash
+            .read(self.slot_offset(slot), &mut header)
+            .map_err(|_| ImageStoreError::Flash)?;
+        if header[..4] != SLOT_MARKER {
+            return Ok(None);
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+        let size = Size::new(u16_at(21) as u32, u16_at(23) as u32);
+        let position = Point::new(u16_at(25) as i16 as i32, u16_at(27) as i16 as i32);
+        let len = row_len(size.width) * size.height as usize;
+        if len > MAX_IMAGE_LEN {
+            return Ok(None);
+        }
+        let mut pixels = [0u8; MAX_IMAGE_LEN];
+        let pixels = &mut pixels[..len];
+        flash
+            .read(self.slot_offset(slot) + IMAGE_HEADER_LEN as u32, pixels)
+            .map_err(|_| ImageStoreError::Flash)?;
+        let crc = crc32(crc32(CRC_INIT, &header[..CRC_OFFSET]), pixels) ^ CRC_INIT;
+        if crc.to_le_bytes() != header[CRC_OFFSET..] {
+            return Ok(None);
+        }
+        let name_len = (header[4] as usize).min(IMAGE_NAME_LEN);
+        let name = core::str::from_utf8(&header[5..5 + name_len]).unwrap_or("");
+        Ok(DisplayImage::new(name, size, position, pixels).ok())
+    }
+}
+
+/// The slot for `image` and how many bytes of it are used
+fn encode_slot(image: &DisplayImage) -> ([u8; SLOT_LEN], usize) {
+    let mut slot = [0u8; SLOT_LEN];
+    let name = image.name().as_bytes();
+    slot[..4].copy_from_slice(&SLOT_MARKER);
+    slot[4] = name.len() as u8;
+    slot[5..5 + name.len()].copy_from_slice(name);
+    slot[21..23].copy_from_slice(&(image.size().width as u16).to_le_bytes());
+    slot[23..25].copy_from_slice(&(image.size().height as u16).to_le_bytes());
+    slot[25..27].copy_from_slice(&(image.position().x as i16).to_le_bytes());
+    slot[27..29].copy_from_slice(&(image.position().y as i16).to_le_bytes());
+    let pixels = image.pixels();
+    slot[IMAGE_HEADER_LEN..IMAGE_HEADER_LEN + pixels.len()].copy_from_slice(pixels);
+    let crc = crc32(crc32(CRC_INIT, &slot[..CRC_OFFSET]), pixels) ^ CRC_INIT;
+    slot[CRC_OFFSET..IMAGE_HEADER_LEN].copy_from_slice(&crc.to_le_bytes());
+    (slot, IMAGE_HEADER_LEN + pixels.len())
+}
+
+/// An image sent a row at a time, each row a line of hex. E.g. `FF0F` is a row 16 pixels wide
+/// with the last 4 black
+#[derive(Debug, Clone)]
+pub struct ImageUpload {
+    image: DisplayImage,
+    rows: u32,
+}
+
+impl ImageUpload {
+    /// Starts an upload, see [`DisplayImage::blank`]
+    pub fn new(name: &str, size: Size, position: Point) -> Result<Self, ImageError> {
+        Ok(Self {
+            image: DisplayImage::blank(name, size, position)?,
+            rows: 0,
+        })
+    }
+
+    /// Takes the next row of pixels. True once that was the last one
+    pub fn push_row(&mut self, line: &str) -> Result<bool, ImageError> {
+        let hex = line.trim().as_bytes();
+        let mut row: Vec<u8, MAX_ROW_LEN> = Vec::new();
+        for pair in hex.chunks(2) {
+            let byte = core::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or(ImageError::BadRow)?;
+            row.push(byte).map_err(|_| ImageError::BadRow)?;
+        }
+        self.image
+            .set_row(self.rows, &row)
+            .ok_or(ImageError::BadRow)?;
+        self.rows += 1;
+        Ok(self.is_done())
+    }
+
+    /// Every row has been sent
+    pub fn is_done(&self) -> bool {
+        self.rows == self.image.size().height
+    }
+
+    pub fn image(&self) -> &DisplayImage {
+        &self.image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem_flash::MemFlash;
+
+    const SLOTS: usize = 4;
+
+    fn image(name: &str, shade: u8) -> DisplayImage {
+        let size = Size::new(20, 10);
+        let pixels = [shade; 30];
+        DisplayImage::new(name, size, Point::new(160, 40), &pixels).unwrap()
+    }
+
+    #[test]
+    fn images_survive_a_reboot() {
+        let mut flash = MemFlash::new();
+        let mut store: ImageStore<SLOTS> = ImageStore::new(ERASE_SIZE as u32);
+        store.load(&mut flash).unwrap();
+        assert!(store.is_empty());
+        for image in DisplayImage::bundled() {
+            store.save(&mut flash, &image).unwrap();
+        }
+        assert_eq!(store.save(&mut flash, &image("qr", 0x0F)), Ok(2));
+
+        let mut rebooted: ImageStore<SLOTS> = ImageStore::new(ERASE_SIZE as u32);
+        rebooted.load(&mut flash).unwrap();
+        assert!(rebooted.names().eq(["ferris", "repo", "qr"]));
+        assert_eq!(
+            rebooted.read(&mut flash, 0),
+            Ok(DisplayImage::bundled().next().unwrap())
+        );
+        assert_eq!(rebooted.read(&mut flash, 2), Ok(image("qr", 0x0F)));
+        assert_eq!(rebooted.read(&mut flash, 3), Err(ImageStoreError::NotFound));
+    }
+
+    #[test]
+    fn saving_a_name_again_replaces_it() {
+        let mut flash = MemFlash::new();
+        let mut store: ImageStore<SLOTS> = ImageStore::new(0);
+        for (shade, name) in ["a", "b", "c", "d"].iter().enumerate() {
+            store.save(&mut flash, &image(name, shade as u8)).unwrap();
+        }
+        assert_eq!(
+            store.save(&mut flash, &image("e", 0)),
+            Err(ImageStoreError::Full)
+        );
+        assert_eq!(store.save(&mut flash, &image("b", 9)), Ok(1));
+        assert_eq!(store.read(&mut flash, 1), Ok(image("b", 9)));
+
+        //A deleted image's slot is reused, keeping the order of the slots
+        store.delete(&mut flash, "b").unwrap();
+        assert_eq!(
+            store.delete(&mut flash, "b"),
+            Err(ImageStoreError::NotFound)
+        );
+        assert_eq!(store.save(&mut flash, &image("e", 7)), Ok(1));
+        assert!(store.names().eq(["a", "e", "c", "d"]));
+    }
+
+    #[test]
+    fn half_written_images_are_skipped() {
+        let mut flash = MemFlash::new();
+        let mut store: ImageStore<SLOTS> = ImageStore::new(0);
+        store.save(&mut flash, &image("a", 1)).unwrap();
+        //Erase and most of the write
+        flash.cut_power_after(1 + IMAGE_HEADER_LEN + 10);
+        assert_eq!(
+            store.save(&mut flash, &image("b", 2)),
+            Err(ImageStoreError::Flash)
+        );
+        flash.restore_power();
+        store.load(&mut flash).unwrap();
+        assert!(store.names().eq(["a"]));
+        assert_eq!(store.save(&mut flash, &image("b", 2)), Ok(1));
+    }
+
+    #[test]
+    fn uploads_take_a_row_of_hex_per_line() {
+        let mut upload = ImageUpload::new("dot", Size::new(12, 2), Point::zero()).unwrap();
+        assert_eq!(upload.push_row("FFF"), Err(ImageError::BadRow));
+        assert_eq!(upload.push_row("ffff00"), Err(ImageError::BadRow));
+        assert_eq!(upload.push_row("zz0f"), Err(ImageError::BadRow));
+        assert_eq!(upload.push_row(" ff0f\r"), Ok(false));
+        assert_eq!(upload.push_row("00F0"), Ok(true));
+        assert!(upload.is_done());
+        assert_eq!(upload.image().pixels(), &[0xFF, 0x0F, 0x00, 0xF0]);
+        assert_eq!(upload.push_row("0000"), Err(ImageError::BadRow));
+    }
+}
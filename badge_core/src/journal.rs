@@ -0,0 +1,855 @@
+//! Log-structured record store spread over a ring of flash sectors.
+//!
+//! Rewriting one sector in place wears it out and loses everything if power drops between the
+//! erase and the write. Instead each write appends records after the last save, and only when a
+//! sector is full is the next sector in the ring erased. The newest save whose records pass their
+//! CRCs is the current one, so a write cut short by power loss leaves the previous save in use.
+//!
+//! A payload that fits in one record is written as a single record. Bigger payloads are split
+//! into chunk records that fill whole sectors, followed by a manifest record saying where the
+//! chunks start, how many there are and the CRC of the whole payload. Sectors holding the current
+//! save are never erased to make room for the next one, so a journal can hold payloads up to
+//! [`Journal::capacity`], about half its size.
+//!
+//! Each record is a header followed by the payload, padded to [`RECORD_ALIGN`]:
+//!
+//! | bytes | field                                                |
+//! |-------|------------------------------------------------------|
+//! | 4     | magic `JRNL`                                         |
+//! | 4     | sequence number, little endian                       |
+//! | 2     | payload length, little endian                        |
+//! | 1     | kind, 0 a whole payload, 1 a chunk or 2 a manifest   |
+//! | 1     | 0                                                    |
+//! | 4     | CRC-32 of the sequence, length, kind and payload     |
+
+use core::fmt;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::save::ERASE_SIZE;
+
+const RECORD_MAGIC: [u8; 4] = *b"JRNL";
+const HEADER_LEN: usize = 16;
+/// Records start on a multiple of this
+pub const RECORD_ALIGN: usize = 4;
+/// Biggest payload a single record can hold, a record never crosses a sector
+pub const MAX_RECORD_LEN: usize = ERASE_SIZE - HEADER_LEN;
+const MANIFEST_LEN: usize = 20;
+/// A chunk is not started at the end of a sector with less room than this, the next sector is
+/// used instead
+const MIN_CHUNK_LEN: usize = 64;
+/// Flash is read in pieces this big to keep the stack small
+const READ_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JournalError {
+    /// No valid save has been written yet
+    Empty,
+    /// The payload is bigger than [`Journal::capacity`] or the buffer it is read into
+    TooLarge,
+    /// A manifest's chunks are missing or do not match its CRC
+    Corrupt,
+    Flash,
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Empty => "nothing saved",
+            Self::TooLarge => "too large for the journal",
+            Self::Corrupt => "saved chunks are corrupt",
+            Self::Flash => "flash error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    /// A payload in one record
+    Whole,
+    /// Part of a payload, only valid once its manifest is written
+    Chunk,
+    Manifest,
+}
+
+impl RecordKind {
+    fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0 => Some(Self::Whole),
+            1 => Some(Self::Chunk),
+            2 => Some(Self::Manifest),
+            _ => None,
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match self {
+            Self::Whole => 0,
+            Self::Chunk => 1,
+            Self::Manifest => 2,
+        }
+    }
+}
+
+/// Where a record is, it may not have passed its CRC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Record {
+    offset: u32,
+    sequence: u32,
+    kind: RecordKind,
+    len: usize,
+}
+
+impl Record {
+    /// Offset right after the record, where the next one goes
+    fn end(&self) -> u32 {
+        self.offset + record_len(self.len) as u32
+    }
+
+    fn payload_offset(&self) -> u32 {
+        self.offset + HEADER_LEN as u32
+    }
+
+    /// A whole payload or a manifest, what a read starts from
+    fn is_save(&self) -> bool {
+        self.kind != RecordKind::Chunk
+    }
+}
+
+/// Says where a chunked payload's chunks are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Manifest {
+    first_offset: u32,
+    first_sequence: u32,
+    chunks: u32,
+    len: usize,
+    crc: u32,
+}
+
+impl Manifest {
+    fn to_bytes(self) -> [u8; MANIFEST_LEN] {
+        let mut bytes = [0u8; MANIFEST_LEN];
+        bytes[0..4].copy_from_slice(&self.first_offset.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.first_sequence.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.chunks.to_le_bytes());
+        bytes[12..16].copy_from_slice(&(self.len as u32).to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; MANIFEST_LEN]) -> Self {
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        Self {
+            first_offset: word(0),
+            first_sequence: word(4),
+            chunks: word(8),
+            len: word(12) as usize,
+            crc: word(16),
+        }
+    }
+}
+
+/// What a scan of every sector found
+struct Scan {
+    /// Newest valid record of any kind, sequence numbers carry on from it
+    newest: Option<Record>,
+    /// Newest valid whole payload or manifest
+    newest_save: Option<Record>,
+}
+
+/// A ring of `sectors` erase sectors starting at `offset`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Journal {
+    offset: u32,
+    sectors: u32,
+}
+
+impl Journal {
+    /// Needs at least 2 sectors so the newest save survives erasing the next sector
+    pub const fn new(offset: u32, sectors: u32) -> Self {
+        assert!(sectors >= 2, "a journal needs at least 2 sectors");
+        Self { offset, sectors }
+    }
+
+    /// Bytes of flash the journal covers
+    pub const fn flash_len(&self) -> u32 {
+        self.sectors * ERASE_SIZE as u32
+    }
+
+    /// Biggest payload that can always be written, whatever the size of the current save.
+    ///
+    /// The current save and the new one each need up to half the sectors: a chunked payload can
+    /// start at the end of a partly used sector and then fills whole sectors
+    pub const fn capacity(&self) -> usize {
+        let full_sectors = (self.sectors as usize / 2).saturating_sub(1);
+        let chunked = (full_sectors * MAX_RECORD_LEN).saturating_sub(record_len(MANIFEST_LEN));
+        if chunked > MAX_RECORD_LEN {
+            chunked
+        } else {
+            MAX_RECORD_LEN
+        }
+    }
+
+    /// Reads the newest save into `buf`, returning the payload
+    pub fn read_newest<'a, F: NorFlash>(
+        &self,
+        flash: &mut F,
+        buf: &'a mut [u8],
+    ) -> Result<&'a [u8], JournalError> {
+        let save = self.scan(flash)?.newest_save.ok_or(JournalError::Empty)?;
+        if save.kind == RecordKind::Whole {
+            let payload = buf.get_mut(..save.len).ok_or(JournalError::TooLarge)?;
+            read(flash, save.payload_offset(), payload)?;
+            return Ok(payload);
+        }
+
+        let manifest = read_manifest(flash, &save)?;
+        let payload = buf.get_mut(..manifest.len).ok_or(JournalError::TooLarge)?;
+        let mut sector = self.sector_start(manifest.first_offset);
+        let mut offset = manifest.first_offset;
+        let mut filled = 0;
+        for index in 0..manifest.chunks {
+            let sequence = manifest.first_sequence.wrapping_add(index);
+            let is_chunk =
+                |record: &Record| record.kind == RecordKind::Chunk && record.sequence == sequence;
+            //Each chunk follows the last one or starts the next sector
+            let chunk = match self.valid_record_at(flash, sector, offset)? {
+                Some(record) if is_chunk(&record) => record,
+                _ => {
+                    sector = self.next_sector(sector);
+                    self.valid_record_at(flash, sector, sector)?
+                        .filter(is_chunk)
+                        .ok_or(JournalError::Corrupt)?
+                }
+            };
+            let part = payload
+                .get_mut(filled..filled + chunk.len)
+                .ok_or(JournalError::Corrupt)?;
+            read(flash, chunk.payload_offset(), part)?;
+            filled += chunk.len;
+            offset = chunk.end();
+        }
+        if filled != manifest.len || crc32(CRC_INIT, payload) ^ CRC_INIT != manifest.crc {
+            return Err(JournalError::Corrupt);
+        }
+        Ok(payload)
+    }
+
+    /// Appends `payload` as the newest save
+    pub fn append<F: NorFlash>(&self, flash: &mut F, payload: &[u8]) -> Result<(), JournalError> {
+        if payload.len() > self.capacity() {
+            return Err(JournalError::TooLarge);
+        }
+        let scan = self.scan(flash)?;
+        //The current save's sectors, from its first record to its last
+        let protected = match scan.newest_save {
+            Some(save) if save.kind == RecordKind::Manifest => {
+                let first = read_manifest(flash, &save)?.first_offset;
+                Some((self.sector_start(first), self.sector_start(save.offset)))
+            }
+            Some(save) => Some((
+                self.sector_start(save.offset),
+                self.sector_start(save.offset),
+            )),
+            None => None,
+        };
+        //Anything after the current save is from a write that never finished and can go
+        let start = scan.newest_save.map_or(self.offset, |save| save.end());
+        let mut writer = Writer {
+            journal: self,
+            protected,
+            sector: scan
+                .newest_save
+                .map_or(self.offset, |save| self.sector_start(save.offset)),
+            offset: start,
+            sequence: scan
+                .newest
+                .map_or(0, |record| record.sequence.wrapping_add(1)),
+        };
+
+        if payload.len() <= MAX_RECORD_LEN {
+            writer.write(flash, RecordKind::Whole, payload)?;
+            return Ok(());
+        }
+
+        let mut first = None;
+        let mut chunks = 0;
+        let mut rest = payload;
+        while !rest.is_empty() {
+            let room = writer.room().filter(|room| *room >= MIN_CHUNK_LEN);
+            let len = rest.len().min(room.unwrap_or(MAX_RECORD_LEN));
+            let (chunk, after) = rest.split_at(len);
+            let record = writer.write(flash, RecordKind::Chunk, chunk)?;
+            first.get_or_insert(record);
+            chunks += 1;
+            rest = after;
+        }
+        let first = first.unwrap();
+        let manifest = Manifest {
+            first_offset: first.offset,
+            first_sequence: first.sequence,
+            chunks,
+            len: payload.len(),
+            crc: crc32(CRC_INIT, payload) ^ CRC_INIT,
+        };
+        writer.write(flash, RecordKind::Manifest, &manifest.to_bytes())?;
+        Ok(())
+    }
+
+    /// Scans every sector for the newest valid records
+    fn scan<F: NorFlash>(&self, flash: &mut F) -> Result<Scan, JournalError> {
+        let mut scan = Scan {
+            newest: None,
+            newest_save: None,
+        };
+        let is_newest = |newest: Option<Record>, record: &Record| {
+            newest.is_none_or(|newest| is_newer(record.sequence, newest.sequence))
+        };
+        for sector in 0..self.sectors {
+            let sector_start = self.offset + sector * ERASE_SIZE as u32;
+            let mut offset = sector_start;
+            //Erased space or a torn header ends the sector, nothing valid comes after either
+            while let Some((record, crc)) = record_at(flash, sector_start, offset)? {
+                if record_crc(flash, &record)? == crc {
+                    if is_newest(scan.newest, &record) {
+                        scan.newest = Some(record);
+                    }
+                    if record.is_save() && is_newest(scan.newest_save, &record) {
+                        scan.newest_save = Some(record);
+                    }
+                }
+                offset = record.end();
+            }
+        }
+        Ok(scan)
+    }
+
+    /// The record at `offset` if it passes its CRC
+    fn valid_record_at<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        sector_start: u32,
+        offset: u32,
+    ) -> Result<Option<Record>, JournalError> {
+        match record_at(flash, sector_start, offset)? {
+            Some((record, crc)) if record_crc(flash, &record)? == crc => Ok(Some(record)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Start of the sector `offset` is in
+    fn sector_start(&self, offset: u32) -> u32 {
+        offset - (offset - self.offset) % ERASE_SIZE as u32
+    }
+
+    /// Start of the sector after the one starting at `sector_start`, wrapping around the ring
+    fn next_sector(&self, sector_start: u32) -> u32 {
+        let sector = (sector_start - self.offset) / ERASE_SIZE as u32;
+        self.offset + (sector + 1) % self.sectors * ERASE_SIZE as u32
+    }
+
+    /// Position of a sector in the ring counting from `from`
+    fn distance(&self, from: u32, to: u32) -> u32 {
+        let sector = |offset: u32| (offset - self.offset) / ERASE_SIZE as u32;
+        (sector(to) + self.sectors - sector(from)) % self.sectors
+    }
+}
+
+/// Writes records one after another, moving on to the next sector when one is full
+struct Writer<'a> {
+    journal: &'a Journal,
+    /// First and last sector of the current save, these are never erased
+    protected: Option<(u32, u32)>,
+    /// Start of the sector being written
+    sector: u32,
+    offset: u32,
+    sequence: u32,
+}
+
+impl Writer<'_> {
+    /// Payload that fits in a record in what is left of the sector, `None` when nothing does
+    fn room(&self) -> Option<usize> {
+        let used = (self.offset - self.sector) as usize;
+        let left = ERASE_SIZE.checked_sub(used + HEADER_LEN)?;
+        Some(left - left % RECORD_ALIGN).filter(|room| *room > 0)
+    }
+
+    fn write<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        kind: RecordKind,
+        payload: &[u8],
+    ) -> Result<Record, JournalError> {
+        let len = record_len(payload.len());
+        //Leftovers from a torn write are never written over
+        if !fits_in_sector(self.sector, self.offset, len) || !is_erased(flash, self.offset, len)? {
+            self.next_sector(flash)?;
+        }
+
+        let record = Record {
+            offset: self.offset,
+            sequence: self.sequence,
+            kind,
+            len: payload.len(),
+        };
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(&RECORD_MAGIC);
+        header[4..8].copy_from_slice(&record.sequence.to_le_bytes());
+        header[8..10].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        header[10] = kind.as_u8();
+        header[11] = 0;
+        let crc = crc32(CRC_INIT, &header[4..12]);
+        let crc = crc32(crc, payload) ^ CRC_INIT;
+        header[12..16].copy_from_slice(&crc.to_le_bytes());
+
+        //Written in place from `payload`, only the unaligned end is copied to be padded
+        let aligned = payload.len() - payload.len() % RECORD_ALIGN;
+        let (body, end) = payload.split_at(aligned);
+        let mut tail = [0xFF; RECORD_ALIGN];
+        tail[..end.len()].copy_from_slice(end);
+        let mut at = record.offset;
+        for part in [&header[..], body, &tail[..len - HEADER_LEN - aligned]] {
+            if !part.is_empty() {
+                flash.write(at, part).map_err(|_| JournalError::Flash)?;
+                at += part.len() as u32;
+            }
+        }
+
+        self.offset = record.end();
+        self.sequence = self.sequence.wrapping_add(1);
+        Ok(record)
+    }
+
+    /// Erases the next sector to write in, unless it holds the current save
+    fn next_sector<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), JournalError> {
+        let next = self.journal.next_sector(self.sector);
+        if let Some((first, last)) = self.protected {
+            if self.journal.distance(first, next) <= self.journal.distance(first, last) {
+                return Err(JournalError::TooLarge);
+            }
+        }
+        erase_sector(flash, next)?;
+        self.sector = next;
+        self.offset = next;
+        Ok(())
+    }
+}
+
+/// The header at `offset`, `None` if there is no record there
+fn record_at<F: NorFlash>(
+    flash: &mut F,
+    sector_start: u32,
+    offset: u32,
+) -> Result<Option<(Record, u32)>, JournalError> {
+    if !fits_in_sector(sector_start, offset, HEADER_LEN) {
+        return Ok(None);
+    }
+    let mut header = [0u8; HEADER_LEN];
+    read(flash, offset, &mut header)?;
+    if header[..4] != RECORD_MAGIC {
+        return Ok(None);
+    }
+    let Some(kind) = RecordKind::from_u8(header[10]) else {
+        return Ok(None);
+    };
+    let record = Record {
+        offset,
+        sequence: u32::from_le_bytes(header[4..8].try_into().unwrap()),
+        kind,
+        len: u16::from_le_bytes(header[8..10].try_into().unwrap()) as usize,
+    };
+    if !fits_in_sector(sector_start, offset, record_len(record.len)) {
+        return Ok(None);
+    }
+    let crc = u32::from_le_bytes(header[12..16].try_into().unwrap());
+    Ok(Some((record, crc)))
+}
+
+fn read_manifest<F: NorFlash>(flash: &mut F, record: &Record) -> Result<Manifest, JournalError> {
+    if record.len != MANIFEST_LEN {
+        return Err(JournalError::Corrupt);
+    }
+    let mut bytes = [0u8; MANIFEST_LEN];
+    read(flash, record.payload_offset(), &mut bytes)?;
+    Ok(Manifest::from_bytes(&bytes))
+}
+
+/// True if `len` bytes at `offset` end before the sector starting at `sector_start` does
+fn fits_in_sector(sector_start: u32, offset: u32, len: usize) -> bool {
+    (offset - sector_start) as usize + len <= ERASE_SIZE
+}
+
+/// Sequence numbers wrap, a record is newer if it is less than half the range ahead
+fn is_newer(sequence: u32, than: u32) -> bool {
+    let ahead = sequence.wrapping_sub(than);
+    ahead != 0 && ahead < u32::MAX / 2
+}
+
+const fn record_len(payload_len: usize) -> usize {
+    (HEADER_LEN + payload_len).next_multiple_of(RECORD_ALIGN)
+}
+
+fn read<F: NorFlash>(flash: &mut F, offset: u32, bytes: &mut [u8]) -> Result<(), JournalError> {
+    flash.read(offset, bytes).map_err(|_| JournalError::Flash)
+}
+
+fn erase_sector<F: NorFlash>(flash: &mut F, offset: u32) -> Result<(), JournalError> {
+    flash
+        .erase(offset, offset + ERASE_SIZE as u32)
+        .map_err(|_| JournalError::Flash)
+}
+
+fn is_erased<F: NorFlash>(flash: &mut F, offset: u32, len: usize) -> Result<bool, JournalError> {
+    let mut piece = [0u8; READ_LEN];
+    for start in (0..len).step_by(READ_LEN) {
+        let piece = &mut piece[..READ_LEN.min(len - start)];
+        read(flash, offset + start as u32, piece)?;
+        if piece.iter().any(|byte| *byte != 0xFF) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// CRC of a record's header fields and payload as read from flash
+fn record_crc<F: NorFlash>(flash: &mut F, record: &Record) -> Result<u32, JournalError> {
+    let mut header = [0u8; HEADER_LEN];
+    read(flash, record.offset, &mut header)?;
+    let mut crc = crc32(CRC_INIT, &header[4..12]);
+    let mut piece = [0u8; READ_LEN];
+    for start in (0..record.len).step_by(READ_LEN) {
+        let piece = &mut piece[..READ_LEN.min(record.len - start)];
+        read(flash, record.payload_offset() + start as u32, piece)?;
+        crc = crc32(crc, piece);
+    }
+    Ok(crc ^ CRC_INIT)
+}
+
+pub(crate) const CRC_INIT: u32 = 0xFFFF_FFFF;
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// Lookup table for the reflected CRC-32 polynomial, one entry per byte value
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// Continues a CRC-32 (the zlib/ethernet one) over `bytes`. Start with [`CRC_INIT`] and xor the
+/// result with it when done
+pub(crate) fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        crc = CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem_flash::MemFlash;
+    use embedded_storage::nor_flash::ReadNorFlash;
+
+    const JOURNAL: Journal = Journal::new(ERASE_SIZE as u32, 3);
+
+    fn read(flash: &mut MemFlash) -> Result<heapless::Vec<u8, ERASE_SIZE>, JournalError> {
+        let mut buf = [0u8; ERASE_SIZE];
+        JOURNAL
+            .read_newest(flash, &mut buf)
+            .map(|payload| heapless::Vec::from_slice(payload).unwrap())
+    }
+
+    /// A payload that is different for every `n`
+    fn payload(n: u32, len: usize) -> heapless::Vec<u8, ERASE_SIZE> {
+        (0..len).map(|i| (n as usize * 31 + i) as u8).collect()
+    }
+
+    #[test]
+    fn crc_matches_the_standard_check_value() {
+        assert_eq!(crc32(CRC_INIT, b"123456789") ^ CRC_INIT, 0xCBF4_3926);
+    }
+
+    #[test]
+    fn empty_until_something_is_written() {
+        let mut flash = MemFlash::new();
+        assert_eq!(read(&mut flash), Err(JournalError::Empty));
+        JOURNAL.append(&mut flash, b"crab").unwrap();
+        assert_eq!(read(&mut flash).unwrap().as_slice(), b"crab");
+    }
+
+    #[test]
+    fn newest_record_wins_across_the_ring() {
+        let mut flash = MemFlash::new();
+        //Enough writes to wrap around the ring a few times
+        for n in 0..100 {
+            let payload = payload(n, 300);
+            JOURNAL.append(&mut flash, &payload).unwrap();
+            assert_eq!(read(&mut flash).unwrap(), payload, "write {n}");
+        }
+    }
+
+    #[test]
+    fn appends_instead_of_erasing_every_write() {
+        let mut flash = MemFlash::new();
+        JOURNAL.append(&mut flash, &payload(0, 100)).unwrap();
+        let before = flash.steps();
+        JOURNAL.append(&mut flash, &payload(1, 100)).unwrap();
+        //Only the record's bytes were written, no erase
+        assert_eq!(flash.steps() - before, record_len(100));
+    }
+
+    #[test]
+    fn records_that_fill_a_sector_exactly() {
+        let mut flash = MemFlash::new();
+        let half = payload(1, ERASE_SIZE / 2 - HEADER_LEN);
+        JOURNAL.append(&mut flash, &half).unwrap();
+        JOURNAL.append(&mut flash, &half).unwrap();
+        //The sector is full so the next record erases and starts the next one
+        let before = flash.steps();
+        JOURNAL.append(&mut flash, b"next").unwrap();
+        assert_eq!(flash.steps() - before, 1 + record_len(4));
+        assert_eq!(read(&mut flash).unwrap().as_slice(), b"next");
+    }
+
+    #[test]
+    fn stays_inside_its_sectors() {
+        let mut flash = MemFlash::new();
+        for n in 0..50 {
+            JOURNAL.append(&mut flash, &payload(n, 1000)).unwrap();
+        }
+        let mut outside = [0u8; ERASE_SIZE];
+        flash.read(0, &mut outside).unwrap();
+        assert!(outside.iter().all(|byte| *byte == 0xFF));
+    }
+
+    #[test]
+    fn largest_payload_fits() {
+        let mut flash = MemFlash::new();
+        let largest = payload(7, MAX_RECORD_LEN);
+        JOURNAL.append(&mut flash, &largest).unwrap();
+        JOURNAL.append(&mut flash, &largest).unwrap();
+        assert_eq!(read(&mut flash).unwrap(), largest);
+        //Too small a ring to hold chunked payloads
+        assert_eq!(JOURNAL.capacity(), MAX_RECORD_LEN);
+        assert_eq!(
+            JOURNAL.append(&mut flash, &[0; MAX_RECORD_LEN + 1]),
+            Err(JournalError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn corrupt_records_are_skipped() {
+        let mut flash = MemFlash::new();
+        JOURNAL.append(&mut flash, b"old").unwrap();
+        JOURNAL.append(&mut flash, b"new").unwrap();
+        //Clear a bit in the newest payload
+        let newest = JOURNAL.offset + record_len(3) as u32 + HEADER_LEN as u32;
+        flash.write(newest, &[0x00]).unwrap();
+        assert_eq!(read(&mut flash).unwrap().as_slice(), b"old");
+        //The next write does not land on top of the corrupt record
+        JOURNAL.append(&mut flash, b"newer").unwrap();
+        assert_eq!(read(&mut flash).unwrap().as_slice(), b"newer");
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        assert!(is_newer(1, 0));
+        assert!(is_newer(0, u32::MAX));
+        assert!(!is_newer(u32::MAX, 0));
+        assert!(!is_newer(5, 5));
+    }
+
+    /// Cuts the power at every step of every write, the journal must always come back with
+    /// either the previous or the new payload and keep working after
+    #[test]
+    fn survives_power_loss_at_every_step() {
+        let mut flash = MemFlash::new();
+        let mut previous = None;
+        //Lengths that fill sectors unevenly so writes land at sector ends and wrap the ring
+        for n in 0..16u32 {
+            let next = payload(n, 300 + n as usize * 37);
+            let mut dry_run = flash.clone();
+            let steps_before = dry_run.steps();
+            JOURNAL.append(&mut dry_run, &next).unwrap();
+            let steps = dry_run.steps() - steps_before;
+
+            for cut_after in 0..steps {
+                let mut torn = flash.clone();
+                torn.cut_power_after(cut_after);
+                assert_eq!(JOURNAL.append(&mut torn, &next), Err(JournalError::Flash));
+                torn.restore_power();
+
+                match (read(&mut torn), &previous) {
+                    (Ok(found), Some(previous)) => {
+                        assert!(
+                            found == *previous || found == next,
+                            "write {n} step {cut_after}"
+                        )
+                    }
+                    (Ok(found), None) => assert_eq!(found, next),
+                    (Err(JournalError::Empty), None) => {}
+                    (result, _) => panic!("write {n} step {cut_after}: {result:?}"),
+                }
+                //Writing again after the reboot works
+                let retry = payload(n + 100, 200);
+                JOURNAL.append(&mut torn, &retry).unwrap();
+                assert_eq!(
+                    read(&mut torn).unwrap(),
+                    retry,
+                    "write {n} step {cut_after}"
+                );
+            }
+
+            JOURNAL.append(&mut flash, &next).unwrap();
+            previous = Some(next);
+        }
+    }
+
+    const BIG_JOURNAL: Journal = Journal::new(ERASE_SIZE as u32, 8);
+    const BIG_LEN: usize = 3 * ERASE_SIZE;
+
+    fn big_payload(n: u32, len: usize) -> [u8; BIG_LEN] {
+        let mut payload = [0u8; BIG_LEN];
+        for (i, byte) in payload[..len].iter_mut().enumerate() {
+            *byte = (n as usize * 31 + i / 7) as u8;
+        }
+        payload
+    }
+
+    fn read_big(journal: &Journal, flash: &mut MemFlash) -> Result<usize, JournalError> {
+        let mut buf = [0u8; BIG_LEN];
+        let payload = journal.read_newest(flash, &mut buf)?;
+        Ok(payload.len())
+    }
+
+    fn assert_reads(journal: &Journal, flash: &mut MemFlash, n: u32, len: usize) {
+        let mut buf = [0u8; BIG_LEN];
+        let payload = journal.read_newest(flash, &mut buf).unwrap();
+        assert_eq!(payload, &big_payload(n, len)[..len], "payload {n}");
+    }
+
+    #[test]
+    fn capacity_is_about_half_the_journal() {
+        assert_eq!(
+            BIG_JOURNAL.capacity(),
+            3 * MAX_RECORD_LEN - record_len(MANIFEST_LEN)
+        );
+        assert!(BIG_JOURNAL.capacity() < BIG_JOURNAL.flash_len() as usize / 2);
+        assert_eq!(Journal::new(0, 2).capacity(), MAX_RECORD_LEN);
+    }
+
+    #[test]
+    fn payloads_bigger_than_a_sector_are_chunked() {
+        let mut flash = MemFlash::new();
+        let len = 2 * ERASE_SIZE + 100;
+        BIG_JOURNAL
+            .append(&mut flash, &big_payload(1, len)[..len])
+            .unwrap();
+        assert_reads(&BIG_JOURNAL, &mut flash, 1, len);
+        //Small saves after a big one go back to single records
+        BIG_JOURNAL
+            .append(&mut flash, &big_payload(2, 10)[..10])
+            .unwrap();
+        assert_reads(&BIG_JOURNAL, &mut flash, 2, 10);
+    }
+
+    #[test]
+    fn full_capacity_writes_always_fit() {
+        let mut flash = MemFlash::new();
+        let capacity = BIG_JOURNAL.capacity();
+        //Mixing sizes starts the big writes at every kind of position in a sector
+        let sizes = [
+            capacity, 100, capacity, 3000, 5000, capacity, 1, capacity, 4500, capacity,
+        ];
+        for (n, len) in sizes.into_iter().cycle().take(40).enumerate() {
+            let payload = big_payload(n as u32, len);
+            BIG_JOURNAL.append(&mut flash, &payload[..len]).unwrap();
+            assert_reads(&BIG_JOURNAL, &mut flash, n as u32, len);
+        }
+        assert_eq!(
+            BIG_JOURNAL.append(&mut flash, &big_payload(0, capacity + 1)[..capacity + 1]),
+            Err(JournalError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn a_damaged_chunk_is_corrupt() {
+        let mut flash = MemFlash::new();
+        let len = ERASE_SIZE + 500;
+        BIG_JOURNAL
+            .append(&mut flash, &big_payload(1, len)[..len])
+            .unwrap();
+        //Clear a bit in the first chunk
+        flash
+            .write(BIG_JOURNAL.offset + HEADER_LEN as u32 + 10, &[0x00])
+            .unwrap();
+        assert_eq!(
+            read_big(&BIG_JOURNAL, &mut flash),
+            Err(JournalError::Corrupt)
+        );
+        //It can still be saved over
+        BIG_JOURNAL
+            .append(&mut flash, &big_payload(2, len)[..len])
+            .unwrap();
+        assert_reads(&BIG_JOURNAL, &mut flash, 2, len);
+    }
+
+    /// The same as [`survives_power_loss_at_every_step`] for chunked payloads. Every 5th step is
+    /// enough to cut inside each header and is a lot faster
+    #[test]
+    fn chunked_writes_survive_power_loss() {
+        const JOURNAL: Journal = Journal::new(ERASE_SIZE as u32, 6);
+        let mut flash = MemFlash::new();
+        let mut previous = None;
+        let retry = big_payload(100, JOURNAL.capacity());
+        let retry = &retry[..JOURNAL.capacity()];
+        let payloads = [big_payload(0, 4500), big_payload(1, 5000)];
+        for (n, payload) in [&payloads[0][..4500], &payloads[1][..5000]]
+            .into_iter()
+            .enumerate()
+        {
+            let mut dry_run = flash.clone();
+            let steps_before = dry_run.steps();
+            JOURNAL.append(&mut dry_run, payload).unwrap();
+            let steps = dry_run.steps() - steps_before;
+
+            for cut_after in (0..steps).step_by(5) {
+                let mut torn = flash.clone();
+                torn.cut_power_after(cut_after);
+                assert_eq!(JOURNAL.append(&mut torn, payload), Err(JournalError::Flash));
+                torn.restore_power();
+
+                let mut buf = [0u8; BIG_LEN];
+                match (JOURNAL.read_newest(&mut torn, &mut buf), previous) {
+                    (Ok(found), Some(previous)) => assert!(
+                        found == previous || found == payload,
+                        "write {n} step {cut_after}"
+                    ),
+                    (Err(JournalError::Empty), None) => {}
+                    (result, _) => panic!("write {n} step {cut_after}: {result:?}"),
+                }
+                //A full size write after the reboot still fits
+                JOURNAL.append(&mut torn, retry).unwrap();
+                assert_eq!(JOURNAL.read_newest(&mut torn, &mut buf), Ok(retry));
+            }
+
+            JOURNAL.append(&mut flash, payload).unwrap();
+            previous = Some(payload);
+        }
+    }
+}
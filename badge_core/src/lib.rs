@@ -0,0 +1,27 @@
+//! Hardware-free logic for the badge.
+//!
+//! Everything in here is `no_std` and does not depend on embassy or the rp2040 so it can be unit
+//! tested on the host. Hardware is reached through traits (`Clock` for the RTC and
+//! `embedded_storage`'s `NorFlash` for flash) that the firmware implements.
+
+#![no_std]
+
+pub mod badge_display;
+pub mod bloom;
+pub mod boot;
+pub mod bssid;
+pub mod config;
+pub mod console;
+pub mod env;
+pub mod helpers;
+pub mod image_store;
+pub mod journal;
+#[cfg(test)]
+mod mem_flash;
+pub mod save;
+pub mod scan;
+pub mod sketch;
+pub mod stats;
+pub mod time;
+pub mod wardrive;
+pub mod wifi;
@@ -0,0 +1,107 @@
+//! NOR flash in RAM for testing code that reads and writes flash on the host
+
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+
+use crate::save::ERASE_SIZE;
+
+const FLASH_LEN: usize = 16 * ERASE_SIZE;
+
+/// Flash that lives in RAM and behaves like NOR flash, writes can only clear bits.
+///
+/// Every erase and every byte written is a step. [`MemFlash::cut_power_after`] makes everything
+/// after a number of steps fail, leaving the flash as it would be if power was lost right then
+#[derive(Clone)]
+pub struct MemFlash {
+    data: [u8; FLASH_LEN],
+    steps: usize,
+    power_lost_at: Option<usize>,
+}
+
+impl MemFlash {
+    pub fn new() -> Self {
+        Self {
+            data: [0xFF; FLASH_LEN],
+            steps: 0,
+            power_lost_at: None,
+        }
+    }
+
+    /// Erases and bytes written so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn cut_power_after(&mut self, steps: usize) {
+        self.power_lost_at = Some(self.steps + steps);
+    }
+
+    /// Power back on, as after a reboot
+    pub fn restore_power(&mut self) {
+        self.power_lost_at = None;
+    }
+
+    /// Takes a step, false once the power is gone
+    fn step(&mut self) -> bool {
+        if self.power_lost_at.is_some_and(|at| self.steps >= at) {
+            return false;
+        }
+        self.steps += 1;
+        true
+    }
+}
+
+impl ErrorType for MemFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for MemFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        let end = start + bytes.len();
+        if end > FLASH_LEN {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        bytes.copy_from_slice(&self.data[start..end]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_LEN
+    }
+}
+
+impl NorFlash for MemFlash {
+    const WRITE_SIZE: usize = 1;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if !(from as usize).is_multiple_of(ERASE_SIZE) || !(to as usize).is_multiple_of(ERASE_SIZE)
+        {
+            return Err(NorFlashErrorKind::NotAligned);
+        }
+        if to as usize > FLASH_LEN {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        if !self.step() {
+            return Err(NorFlashErrorKind::Other);
+        }
+        self.data[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let start = offset as usize;
+        if start + bytes.len() > FLASH_LEN {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        for (index, byte) in bytes.iter().enumerate() {
+            if !self.step() {
+                return Err(NorFlashErrorKind::Other);
+            }
+            self.data[start + index] &= byte;
+        }
+        Ok(())
+    }
+}
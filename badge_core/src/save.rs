@@ -0,0 +1,317 @@
+use core::fmt;
+
+use embedded_storage::nor_flash::NorFlash;
+use heapless::{String, Vec};
+use postcard::{from_bytes, to_slice};
+use serde::{Deserialize, Serialize};
+
+use crate::bloom::{BloomFilter, FILTER_LEN};
+use crate::bssid::{parse_bssid, BssidSet, BSSID_LEN};
+use crate::journal::{Journal, JournalError};
+use crate::sketch::{HyperLogLog, REGISTERS};
+
+/// Erase sector size of the rp2040's flash
+pub const ERASE_SIZE: usize = 4096;
+
+/// Start of every save so anything else in flash is not read as one
+const SAVE_MAGIC: [u8; 4] = *b"BSAV";
+/// Bump when [`Save`] changes, keep the old struct and add a step to [`migrate`]
+pub const SAVE_VERSION: u8 = 1;
+const HEADER_LEN: usize = SAVE_MAGIC.len() + 1;
+/// Longest an encoded save can be, with every BSSID counted and the largest varints
+pub const MAX_SAVE_LEN: usize =
+    HEADER_LEN + 5 + 2 + BSSID_LEN * 6 + 5 + 2 + REGISTERS + 2 + FILTER_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SaveError {
+    Serialization,
+    /// The payload does not match its version's struct
+    Deserialization,
+    /// No magic, so not something this firmware saved
+    NotASave,
+    /// Saved by newer firmware
+    UnsupportedVersion(u8),
+    Journal(JournalError),
+}
+
+impl SaveError {
+    /// The save is there but can not be loaded, as opposed to nothing being saved yet
+    pub fn is_corrupt(&self) -> bool {
+        matches!(
+            self,
+            Self::Deserialization | Self::NotASave | Self::UnsupportedVersion(_)
+        )
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serialization => f.write_str("could not serialize the save"),
+            Self::Deserialization => f.write_str("could not deserialize the save"),
+            Self::NotASave => f.write_str("not a save"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported save version {}", version),
+            Self::Journal(e) => e.fmt(f),
+        }
+    }
+}
+
+/// Writes `save` as the newest save in `journal`, the previous save is kept until it is written.
+/// Returns how many bytes the save took, to compare with [`Journal::capacity`]
+pub fn save_to_journal<F: NorFlash>(
+    flash: &mut F,
+    journal: &Journal,
+    save: &Save,
+) -> Result<usize, SaveError> {
+    let mut buf = [0u8; MAX_SAVE_LEN];
+    let written = encode(save, &mut buf)?;
+    journal.append(flash, written).map_err(SaveError::Journal)?;
+    Ok(written.len())
+}
+
+/// Reads the newest save in `journal`
+pub fn read_from_journal<F: NorFlash>(flash: &mut F, journal: &Journal) -> Result<Save, SaveError> {
+    let mut buf = [0u8; MAX_SAVE_LEN];
+    let payload = journal
+        .read_newest(flash, &mut buf)
+        .map_err(SaveError::Journal)?;
+    decode(payload)
+}
+
+/// The header and postcard payload for `save`, written into `buf`
+pub fn encode<'a>(save: &Save, buf: &'a mut [u8]) -> Result<&'a [u8], SaveError> {
+    if buf.len() < HEADER_LEN {
+        return Err(SaveError::Serialization);
+    }
+    buf[..SAVE_MAGIC.len()].copy_from_slice(&SAVE_MAGIC);
+    buf[SAVE_MAGIC.len()] = SAVE_VERSION;
+    let len = to_slice(save, &mut buf[HEADER_LEN..])
+        .map_err(|_| SaveError::Serialization)?
+        .len();
+    Ok(&buf[..HEADER_LEN + len])
+}
+
+/// Reads a save written by [`encode`] by this or any older firmware
+pub fn decode(data: &[u8]) -> Result<Save, SaveError> {
+    match data.strip_prefix(&SAVE_MAGIC) {
+        Some([version, payload @ ..]) => migrate(*version, payload),
+        _ => Err(SaveError::NotASave),
+    }
+}
+
+/// Loads a payload of any version into the current [`Save`]
+fn migrate(version: u8, payload: &[u8]) -> Result<Save, SaveError> {
+    let deserialize_error = |_| SaveError::Deserialization;
+    match version {
+        SAVE_VERSION => from_bytes::<Save>(payload).map_err(deserialize_error),
+        version => Err(SaveError::UnsupportedVersion(version)),
+    }
+}
+
+/// Reads a [`LegacySave`] from before the journal, when the whole sector at `offset` was rewritten
+/// each time and saves had no header
+pub fn read_postcard_from_flash<F: NorFlash>(
+    base_offset: u32,
+    flash: &mut F,
+    offset: u32,
+) -> Result<Save, &'static str> {
+    let mut buf = [0u8; ERASE_SIZE];
+
+    flash
+        .read(base_offset + offset, &mut buf)
+        .map_err(|_| "Read error")?;
+
+    let data = from_bytes::<LegacySave>(&buf).map_err(|_| "Deserialization error")?;
+
+    Ok(data.into())
+}
+
+/// Version [`SAVE_VERSION`] of the save
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Default)]
+pub struct Save {
+    pub wifi_counted: u32,
+    pub bssid: BssidSet,
+    /// How fast the RTC runs in parts per million, measured when the clock is synced
+    pub clock_drift_ppm: i32,
+    /// Every BSSID seen since the count was last reset, for
+    /// [`WifiCounting::Estimate`](crate::bssid::WifiCounting::Estimate)
+    pub sketch: HyperLogLog,
+    /// BSSIDs [`WifiCounting::FilterWhenFull`](crate::bssid::WifiCounting::FilterWhenFull) forgot
+    /// to make room in `bssid`
+    pub forgotten: BloomFilter,
+}
+
+/// The sketch for saves from before it was kept, it starts with the BSSIDs that are remembered
+fn seed_sketch(bssids: &BssidSet) -> HyperLogLog {
+    let mut sketch = HyperLogLog::new();
+    for bssid in bssids.iter() {
+        sketch.add(bssid);
+    }
+    sketch
+}
+
+/// BSSIDs as the firmware before the journal saved them, formatted as text
+type BssidStrings = Vec<String<17>, BSSID_LEN>;
+
+/// The headerless save written before the journal, only read to carry the count over
+#[derive(Serialize, Deserialize)]
+struct LegacySave {
+    wifi_counted: u32,
+    bssid: BssidStrings,
+}
+
+impl From<LegacySave> for Save {
+    fn from(old: LegacySave) -> Self {
+        let bssid: BssidSet = old
+            .bssid
+            .iter()
+            .filter_map(|bssid| parse_bssid(bssid))
+            .collect();
+        Self {
+            wifi_counted: old.wifi_counted,
+            sketch: seed_sketch(&bssid),
+            bssid,
+            clock_drift_ppm: 0,
+            forgotten: BloomFilter::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bssid::{format_bssid, Bssid};
+    use crate::mem_flash::MemFlash;
+
+    const JOURNAL: Journal = Journal::new(ERASE_SIZE as u32, 2);
+
+    fn bssids(seen: &[Bssid]) -> BssidSet {
+        seen.iter().copied().collect()
+    }
+
+    #[test]
+    fn save_round_trips_through_the_journal() {
+        let mut flash = MemFlash::new();
+        let save = Save {
+            wifi_counted: 2,
+            bssid: bssids(&[[1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1]]),
+            clock_drift_ppm: -12,
+            sketch: HyperLogLog::new(),
+            forgotten: BloomFilter::new(),
+        };
+
+        save_to_journal(&mut flash, &JOURNAL, &save).unwrap();
+        assert_eq!(read_from_journal(&mut flash, &JOURNAL).unwrap(), save);
+    }
+
+    #[test]
+    fn newest_save_is_read() {
+        let mut flash = MemFlash::new();
+        assert_eq!(
+            read_from_journal(&mut flash, &JOURNAL),
+            Err(SaveError::Journal(JournalError::Empty))
+        );
+        let mut save = Save {
+            wifi_counted: 1,
+            bssid: bssids(&[[1, 1, 1, 1, 1, 1]]),
+            ..Default::default()
+        };
+        save_to_journal(&mut flash, &JOURNAL, &save).unwrap();
+
+        save.wifi_counted = 0;
+        save.bssid.clear();
+        save_to_journal(&mut flash, &JOURNAL, &save).unwrap();
+        assert_eq!(read_from_journal(&mut flash, &JOURNAL).unwrap(), save);
+    }
+
+    #[test]
+    fn a_full_save_spans_several_sectors() {
+        const BIG_JOURNAL: Journal = Journal::new(ERASE_SIZE as u32, 8);
+        let mut save = Save {
+            wifi_counted: u32::MAX,
+            bssid: BssidSet::new(),
+            clock_drift_ppm: i32::MIN,
+            sketch: HyperLogLog::new(),
+            forgotten: BloomFilter::new(),
+        };
+        for i in 0..BSSID_LEN as u32 {
+            let [a, b, c, d] = i.to_le_bytes();
+            save.bssid.see([a, b, c, d, 0xFF, 0xFF]);
+            save.sketch.add(&[a, b, c, d, 0xFF, 0xFF]);
+            save.forgotten.add(&[a, b, c, d, 0xFF, 0xFF]);
+        }
+        assert!(save.bssid.is_full());
+        assert!(BIG_JOURNAL.capacity() >= MAX_SAVE_LEN);
+
+        let mut flash = MemFlash::new();
+        for _ in 0..3 {
+            let len = save_to_journal(&mut flash, &BIG_JOURNAL, &save).unwrap();
+            assert!(len > ERASE_SIZE && len <= MAX_SAVE_LEN);
+            assert_eq!(read_from_journal(&mut flash, &BIG_JOURNAL).unwrap(), save);
+        }
+    }
+
+    #[test]
+    fn saves_from_before_the_journal_carry_over() {
+        let mut old = LegacySave {
+            wifi_counted: 2,
+            bssid: BssidStrings::new(),
+        };
+        //Saved in the order they were seen, which is not sorted
+        for bssid in [[0x0a, 0, 0, 0, 0, 2], [1, 2, 3, 4, 5, 6]] {
+            old.bssid.push(format_bssid(bssid)).unwrap();
+        }
+        //Nothing after the save is read, whatever is there
+        let mut buf = [0xFF; ERASE_SIZE];
+        to_slice(&old, &mut buf).unwrap();
+        let mut flash = MemFlash::new();
+        flash.write(0, &buf).unwrap();
+
+        let loaded = read_postcard_from_flash(0, &mut flash, 0).unwrap();
+        assert_eq!(loaded.wifi_counted, 2);
+        assert_eq!(
+            loaded.bssid,
+            bssids(&[[1, 2, 3, 4, 5, 6], [0x0a, 0, 0, 0, 0, 2]])
+        );
+        assert_eq!(loaded.clock_drift_ppm, 0);
+        assert_eq!(loaded.sketch.estimate(), 2);
+    }
+
+    fn sample_save() -> Save {
+        Save {
+            wifi_counted: 3,
+            bssid: bssids(&[[1, 2, 3, 4, 5, 6]]),
+            clock_drift_ppm: 7,
+            sketch: HyperLogLog::new(),
+            forgotten: BloomFilter::new(),
+        }
+    }
+
+    #[test]
+    fn encoded_saves_start_with_the_header() {
+        let mut buf = [0u8; MAX_SAVE_LEN];
+        let encoded = encode(&sample_save(), &mut buf).unwrap();
+        assert_eq!(&encoded[..4], b"BSAV");
+        assert_eq!(encoded[4], SAVE_VERSION);
+        assert_eq!(decode(encoded).unwrap(), sample_save());
+    }
+
+    #[test]
+    fn bad_data_is_corrupt() {
+        let mut buf = [0u8; MAX_SAVE_LEN];
+        let encoded = encode(&sample_save(), &mut buf).unwrap();
+        let cases: [(&[u8], SaveError); 5] = [
+            (&[0xFF; 32], SaveError::NotASave),
+            (b"BSAV", SaveError::NotASave),
+            (&encoded[..encoded.len() - 3], SaveError::Deserialization),
+            (b"BSAV\x01\xFF\xFF\xFF\xFF\xFF", SaveError::Deserialization),
+            (b"BSAV\x09", SaveError::UnsupportedVersion(9)),
+        ];
+        for (data, error) in cases {
+            assert_eq!(decode(data), Err(error));
+            assert!(error.is_corrupt());
+        }
+        assert!(!SaveError::Journal(JournalError::Empty).is_corrupt());
+    }
+}
@@ -0,0 +1,241 @@
+//! What the wifi scans have found, kept for the display and exports.
+//!
+//! Each access point seen is a [`ScanRecord`], updated every time a scan sees it again.
+//! [`ScanHistory`] keeps the most recently seen ones and drops the one seen longest ago when full.
+
+use core::fmt;
+
+use heapless::{String, Vec};
+
+use crate::bssid::Bssid;
+
+/// Access points kept in the firmware's history
+pub const SCAN_HISTORY_LEN: usize = 32;
+
+/// The 802.11 capability bits an access point advertises
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SecurityFlags(pub u16);
+
+impl SecurityFlags {
+    const ESS: u16 = 1 << 0;
+    const IBSS: u16 = 1 << 1;
+    const PRIVACY: u16 = 1 << 4;
+
+    /// Needs a password, which one (WEP, WPA2 or WPA3) is not in the capability bits
+    pub fn is_protected(&self) -> bool {
+        self.0 & Self::PRIVACY != 0
+    }
+
+    /// An access point rather than a device to device network
+    pub fn is_access_point(&self) -> bool {
+        self.0 & Self::ESS != 0 && self.0 & Self::IBSS == 0
+    }
+}
+
+impl fmt::Display for SecurityFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.is_protected() {
+            "secured"
+        } else {
+            "open"
+        })
+    }
+}
+
+/// One access point and when it was seen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanRecord {
+    /// Empty for hidden networks
+    pub ssid: String<32>,
+    pub bssid: Bssid,
+    /// Signal strength of the latest sighting in dBm
+    pub rssi: i16,
+    pub channel: u8,
+    pub security: SecurityFlags,
+    /// RTC time in unix seconds, `None` if the clock was not set yet
+    pub first_seen: Option<u32>,
+    pub last_seen: Option<u32>,
+}
+
+impl ScanRecord {
+    /// A record for a sighting at `now`. `ssid` is the raw bytes the access point sent and
+    /// `chanspec` the Broadcom channel spec the wifi chip reports
+    pub fn new(
+        ssid: &[u8],
+        bssid: Bssid,
+        rssi: i16,
+        chanspec: u16,
+        security: SecurityFlags,
+        now: Option<u32>,
+    ) -> Self {
+        Self {
+            ssid: ssid_from_bytes(ssid),
+            bssid,
+            rssi,
+            channel: channel_from_chanspec(chanspec),
+            security,
+            first_seen: now,
+            last_seen: now,
+        }
+    }
+
+    /// Takes the details of a newer sighting of the same access point
+    fn update(&mut self, newer: Self) {
+        self.first_seen = self.first_seen.or(newer.first_seen);
+        self.last_seen = newer.last_seen.or(self.last_seen);
+        self.ssid = newer.ssid;
+        self.rssi = newer.rssi;
+        self.channel = newer.channel;
+        self.security = newer.security;
+    }
+}
+
+/// The SSID as text, cut at the first NUL and with anything that is not UTF-8 replaced by `?`
+pub fn ssid_from_bytes(bytes: &[u8]) -> String<32> {
+    let bytes = bytes.split(|byte| *byte == 0).next().unwrap_or_default();
+    let mut ssid = String::new();
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            //Multi byte characters can not overflow as the SSID is at most 32 bytes
+            let _ = ssid.push(c);
+        }
+        if !chunk.invalid().is_empty() {
+            let _ = ssid.push('?');
+        }
+    }
+    ssid
+}
+
+/// The channel number from the low byte of a Broadcom chanspec
+pub fn channel_from_chanspec(chanspec: u16) -> u8 {
+    (chanspec & 0xFF) as u8
+}
+
+/// The `N` most recently seen access points, newest first
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScanHistory<const N: usize> {
+    /// Oldest first, so updating moves a record to the end
+    records: Vec<ScanRecord, N>,
+}
+
+impl<const N: usize> ScanHistory<N> {
+    pub const fn new() -> Self {
+        Self {
+            records: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Adds a sighting, updating the record for its BSSID if there is one
+    pub fn record(&mut self, sighting: ScanRecord) {
+        let record = match self
+            .records
+            .iter()
+            .position(|record| record.bssid == sighting.bssid)
+        {
+            Some(index) => {
+                let mut record = self.records.remove(index);
+                record.update(sighting);
+                record
+            }
+            None => {
+                if self.records.is_full() {
+                    self.records.remove(0);
+                }
+                sighting
+            }
+        };
+        //Room was made above
+        let _ = self.records.push(record);
+    }
+
+    /// Newest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &ScanRecord> + ExactSizeIterator + '_ {
+        self.records.iter().rev()
+    }
+
+    /// The `index`th newest record
+    pub fn get(&self, index: usize) -> Option<&ScanRecord> {
+        self.iter().nth(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sighting(last_byte: u8, rssi: i16, now: Option<u32>) -> ScanRecord {
+        ScanRecord::new(
+            b"RustConf\0\0\0",
+            [0, 1, 2, 3, 4, last_byte],
+            rssi,
+            0x1006,
+            SecurityFlags(0x0011),
+            now,
+        )
+    }
+
+    #[test]
+    fn reads_the_raw_fields() {
+        let record = sighting(5, -60, Some(100));
+        assert_eq!(record.ssid, "RustConf");
+        assert_eq!(record.channel, 6);
+        assert!(record.security.is_protected());
+        assert!(record.security.is_access_point());
+        assert_eq!(record.first_seen, Some(100));
+        assert!(!SecurityFlags(0x0001).is_protected());
+    }
+
+    #[test]
+    fn ssids_that_are_not_utf8_are_kept() {
+        assert_eq!(ssid_from_bytes(b""), "");
+        assert_eq!(ssid_from_bytes(b"caf\xc3\xa9"), "café");
+        assert_eq!(ssid_from_bytes(b"bad\xffbyte"), "bad?byte");
+        assert_eq!(ssid_from_bytes(&[b'x'; 32]).len(), 32);
+    }
+
+    #[test]
+    fn sightings_update_the_same_record() {
+        let mut history = ScanHistory::<4>::new();
+        history.record(sighting(1, -70, None));
+        history.record(sighting(2, -50, Some(10)));
+        history.record(sighting(1, -40, Some(20)));
+        assert_eq!(history.len(), 2);
+
+        let newest = history.get(0).unwrap();
+        assert_eq!(newest.bssid[5], 1);
+        assert_eq!(newest.rssi, -40);
+        //The first sighting was before the clock was set, the earliest known time is used
+        assert_eq!(newest.first_seen, Some(20));
+        assert_eq!(newest.last_seen, Some(20));
+
+        history.record(sighting(1, -45, Some(30)));
+        assert_eq!(history.get(0).unwrap().first_seen, Some(20));
+        assert_eq!(history.get(0).unwrap().last_seen, Some(30));
+    }
+
+    #[test]
+    fn drops_the_one_seen_longest_ago() {
+        let mut history = ScanHistory::<3>::new();
+        for i in 0..3 {
+            history.record(sighting(i, -60, Some(i as u32)));
+        }
+        //0 is seen again so 1 is now the oldest
+        history.record(sighting(0, -60, Some(5)));
+        history.record(sighting(9, -60, Some(6)));
+        let order: Vec<u8, 3> = history.iter().map(|record| record.bssid[5]).collect();
+        assert_eq!(order, [9, 0, 2]);
+    }
+}
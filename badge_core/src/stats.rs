@@ -0,0 +1,278 @@
+//! Numbers for the wifi statistics screen, worked out from the scan history and a log of when
+//! new networks were counted.
+
+use heapless::{String, Vec};
+
+use crate::scan::ScanRecord;
+
+/// 2.4GHz channels, all the badge's wifi chip can scan
+pub const CHANNELS: usize = 14;
+/// Strongest networks listed
+pub const TOP_NETWORKS: usize = 3;
+/// Hours shown in the networks per hour sparkline, the last is the current hour
+pub const HOURS: usize = 24;
+
+const SECONDS_PER_HOUR: i64 = 60 * 60;
+const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+
+/// Signal strength as 0 to 4 bars, like a phone shows
+pub fn signal_bars(rssi: i16) -> u8 {
+    match rssi {
+        -55.. => 4,
+        -67..=-56 => 3,
+        -75..=-68 => 2,
+        -85..=-76 => 1,
+        _ => 0,
+    }
+}
+
+/// How many new networks were counted each hour of the last day and since local midnight.
+///
+/// Times are local unix seconds, UTC plus the time zone's offset, so days start at local
+/// midnight. Nothing is logged until the clock is set and the log starts over on reboot
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DiscoveryLog {
+    /// Counted in each hour, indexed by hour number modulo [`HOURS`]
+    hours: [u16; HOURS],
+    /// Hour number (local unix seconds / 3600) of the newest entry in `hours`
+    newest_hour: Option<i64>,
+    today: u32,
+    /// Day number of `today`
+    day: Option<i64>,
+}
+
+impl DiscoveryLog {
+    pub const fn new() -> Self {
+        Self {
+            hours: [0; HOURS],
+            newest_hour: None,
+            today: 0,
+            day: None,
+        }
+    }
+
+    /// Logs `count` newly counted networks at `local_time`, ignored when the clock is not set
+    pub fn counted(&mut self, count: u32, local_time: Option<i64>) {
+        let Some(local_time) = local_time else {
+            return;
+        };
+        self.advance(local_time);
+        let hour = local_time.div_euclid(SECONDS_PER_HOUR);
+        let slot = &mut self.hours[hour.rem_euclid(HOURS as i64) as usize];
+        *slot = slot.saturating_add(count.min(u16::MAX as u32) as u16);
+        self.today = self.today.saturating_add(count);
+    }
+
+    /// Networks counted since local midnight
+    pub fn today(&self, local_time: Option<i64>) -> u32 {
+        match (local_time, self.day) {
+            (Some(time), Some(day)) if time.div_euclid(SECONDS_PER_DAY) == day => self.today,
+            _ => 0,
+        }
+    }
+
+    /// Networks counted in each of the last [`HOURS`] hours, oldest first and ending with the
+    /// hour `local_time` is in
+    pub fn per_hour(&self, local_time: Option<i64>) -> [u16; HOURS] {
+        let mut per_hour = [0; HOURS];
+        let (Some(time), Some(newest)) = (local_time, self.newest_hour) else {
+            return per_hour;
+        };
+        let current = time.div_euclid(SECONDS_PER_HOUR);
+        for (index, count) in per_hour.iter_mut().enumerate() {
+            let hour = current - (HOURS - 1 - index) as i64;
+            //Slots older than a day or newer than the log hold other hours
+            if hour <= newest && newest - hour < HOURS as i64 {
+                *count = self.hours[hour.rem_euclid(HOURS as i64) as usize];
+            }
+        }
+        per_hour
+    }
+
+    /// Clears the hours and day that have passed since the last entry
+    fn advance(&mut self, local_time: i64) {
+        let hour = local_time.div_euclid(SECONDS_PER_HOUR);
+        match self.newest_hour {
+            Some(newest) if hour <= newest => {}
+            Some(newest) => {
+                for skipped in (newest + 1..=hour).take(HOURS) {
+                    self.hours[skipped.rem_euclid(HOURS as i64) as usize] = 0;
+                }
+                self.newest_hour = Some(hour);
+            }
+            None => self.newest_hour = Some(hour),
+        }
+        let day = local_time.div_euclid(SECONDS_PER_DAY);
+        if self.day != Some(day) {
+            self.day = Some(day);
+            self.today = 0;
+        }
+    }
+}
+
+/// One of the strongest networks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopNetwork {
+    pub ssid: String<32>,
+    pub rssi: i16,
+}
+
+/// Everything the statistics screen shows
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WifiStats {
+    /// Unique networks ever counted
+    pub all_time: u32,
+    pub today: u32,
+    /// Access points in the scan history on each channel, channel 1 first
+    pub channels: [u16; CHANNELS],
+    pub open: u16,
+    pub secured: u16,
+    /// Strongest first
+    pub strongest: Vec<TopNetwork, TOP_NETWORKS>,
+    /// See [`DiscoveryLog::per_hour`]
+    pub per_hour: [u16; HOURS],
+}
+
+impl WifiStats {
+    pub fn new<'a>(
+        history: impl Iterator<Item = &'a ScanRecord>,
+        all_time: u32,
+        log: &DiscoveryLog,
+        local_time: Option<i64>,
+    ) -> Self {
+        let mut stats = Self {
+            all_time,
+            today: log.today(local_time),
+            per_hour: log.per_hour(local_time),
+            ..Self::default()
+        };
+        for record in history {
+            if let Some(channel) = stats
+                .channels
+                .get_mut((record.channel as usize).wrapping_sub(1))
+            {
+                *channel += 1;
+            }
+            if record.security.is_protected() {
+                stats.secured += 1;
+            } else {
+                stats.open += 1;
+            }
+            stats.add_if_strong(record);
+        }
+        stats
+    }
+
+    /// Keeps `record` if it is one of the strongest so far, hidden networks are left out
+    fn add_if_strong(&mut self, record: &ScanRecord) {
+        if record.ssid.is_empty() {
+            return;
+        }
+        let index = self
+            .strongest
+            .iter()
+            .position(|top| record.rssi > top.rssi)
+            .unwrap_or(self.strongest.len());
+        if index >= TOP_NETWORKS {
+            return;
+        }
+        if self.strongest.is_full() {
+            self.strongest.pop();
+        }
+        let _ = self.strongest.insert(
+            index,
+            TopNetwork {
+                ssid: record.ssid.clone(),
+                rssi: record.rssi,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{ScanHistory, SecurityFlags};
+
+    const HOUR: i64 = SECONDS_PER_HOUR;
+    /// 2024-08-16 00:00 local
+    const MIDNIGHT: i64 = 1_723_766_400;
+
+    fn record(ssid: &str, last_byte: u8, rssi: i16, channel: u16, capability: u16) -> ScanRecord {
+        ScanRecord::new(
+            ssid.as_bytes(),
+            [0, 0, 0, 0, 0, last_byte],
+            rssi,
+            channel,
+            SecurityFlags(capability),
+            None,
+        )
+    }
+
+    #[test]
+    fn bars_for_signal_strength() {
+        assert_eq!(signal_bars(-40), 4);
+        assert_eq!(signal_bars(-55), 4);
+        assert_eq!(signal_bars(-60), 3);
+        assert_eq!(signal_bars(-70), 2);
+        assert_eq!(signal_bars(-80), 1);
+        assert_eq!(signal_bars(-95), 0);
+    }
+
+    #[test]
+    fn counts_channels_security_and_strongest() {
+        let mut history = ScanHistory::<8>::new();
+        history.record(record("cafe", 1, -80, 1, 0x01));
+        history.record(record("venue", 2, -50, 6, 0x11));
+        history.record(record("", 3, -30, 6, 0x11));
+        history.record(record("hotel", 4, -65, 11, 0x11));
+        history.record(record("phone", 5, -70, 6, 0x11));
+        history.record(record("odd", 6, -90, 200, 0x01));
+
+        let stats = WifiStats::new(history.iter(), 42, &DiscoveryLog::new(), None);
+        assert_eq!(stats.all_time, 42);
+        assert_eq!(stats.channels[0], 1);
+        assert_eq!(stats.channels[5], 3);
+        assert_eq!(stats.channels[10], 1);
+        assert_eq!(stats.channels.iter().sum::<u16>(), 5);
+        assert_eq!((stats.open, stats.secured), (2, 4));
+        //The hidden network is stronger but has no name to show
+        let strongest: Vec<&str, 3> = stats.strongest.iter().map(|x| x.ssid.as_str()).collect();
+        assert_eq!(strongest, ["venue", "hotel", "phone"]);
+    }
+
+    #[test]
+    fn logs_new_networks_per_hour_and_day() {
+        let mut log = DiscoveryLog::new();
+        log.counted(5, None);
+        assert_eq!(log.today(Some(MIDNIGHT)), 0);
+
+        log.counted(2, Some(MIDNIGHT - HOUR));
+        log.counted(3, Some(MIDNIGHT + 10));
+        log.counted(4, Some(MIDNIGHT + 2 * HOUR + 5));
+        let now = Some(MIDNIGHT + 2 * HOUR + 30);
+        assert_eq!(log.today(now), 7);
+        let per_hour = log.per_hour(now);
+        assert_eq!(per_hour[HOURS - 1], 4);
+        assert_eq!(per_hour[HOURS - 2], 0);
+        assert_eq!(per_hour[HOURS - 3], 3);
+        assert_eq!(per_hour[HOURS - 4], 2);
+        assert_eq!(per_hour.iter().sum::<u16>(), 9);
+    }
+
+    #[test]
+    fn old_hours_and_days_roll_off() {
+        let mut log = DiscoveryLog::new();
+        log.counted(6, Some(MIDNIGHT + 3 * HOUR));
+        //A day later nothing is left, even before anything new is logged
+        let next_day = Some(MIDNIGHT + 27 * HOUR);
+        assert_eq!(log.per_hour(next_day), [0; HOURS]);
+        assert_eq!(log.today(next_day), 0);
+
+        log.counted(1, next_day);
+        assert_eq!(log.today(next_day), 1);
+        assert_eq!(log.per_hour(next_day).iter().sum::<u16>(), 1);
+        //The slot the first entry was in was reused, not added to
+        assert_eq!(log.per_hour(next_day)[HOURS - 1], 1);
+    }
+}
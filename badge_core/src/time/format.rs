@@ -0,0 +1,287 @@
+//! How the clock in the time box is written out, set with `CLOCK_FORMAT` in the .env file as a
+//! comma separated list of options, e.g. `24h,seconds` or `12h,weekday,dmy`:
+//!
+//! * `12h` (the default) or `24h`
+//! * `seconds` to show seconds
+//! * `weekday` to show the short weekday name, e.g. `Fri`
+//! * `dmy`, `mdy` or `ymd` to show the date as `16/08/2024`, `08/16/2024` or `2024-08-16`
+
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+use super::{DateTime, DayOfWeek};
+use crate::helpers::easy_format;
+
+/// Longest string any format produces, `Fri 2024-08-16 06:30:05 PM`
+pub const CLOCK_STRING_LEN: usize = 26;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HourFormat {
+    /// `06:30 PM`
+    TwelveHour,
+    /// `18:30`
+    TwentyFourHour,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DateOrder {
+    /// `16/08/2024`
+    DayMonthYear,
+    /// `08/16/2024`
+    MonthDayYear,
+    /// `2024-08-16`
+    YearMonthDay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ClockFormat {
+    pub hour: HourFormat,
+    pub seconds: bool,
+    pub weekday: bool,
+    /// `None` leaves the date off
+    pub date: Option<DateOrder>,
+}
+
+impl Default for ClockFormat {
+    fn default() -> Self {
+        Self {
+            hour: HourFormat::TwelveHour,
+            seconds: false,
+            weekday: false,
+            date: None,
+        }
+    }
+}
+
+impl DayOfWeek {
+    /// Three letter English name, e.g. `Fri`
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Self::Sunday => "Sun",
+            Self::Monday => "Mon",
+            Self::Tuesday => "Tue",
+            Self::Wednesday => "Wed",
+            Self::Thursday => "Thu",
+            Self::Friday => "Fri",
+            Self::Saturday => "Sat",
+        }
+    }
+}
+
+impl ClockFormat {
+    /// Reads the `CLOCK_FORMAT` config value. Options left out keep their default, `None` if an
+    /// option is not known
+    pub fn from_config(value: &str) -> Option<Self> {
+        let mut format = Self::default();
+        for option in value.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            match option {
+                o if o.eq_ignore_ascii_case("12h") => format.hour = HourFormat::TwelveHour,
+                o if o.eq_ignore_ascii_case("24h") => format.hour = HourFormat::TwentyFourHour,
+                o if o.eq_ignore_ascii_case("seconds") => format.seconds = true,
+                o if o.eq_ignore_ascii_case("weekday") => format.weekday = true,
+                o if o.eq_ignore_ascii_case("dmy") => format.date = Some(DateOrder::DayMonthYear),
+                o if o.eq_ignore_ascii_case("mdy") => format.date = Some(DateOrder::MonthDayYear),
+                o if o.eq_ignore_ascii_case("ymd") => format.date = Some(DateOrder::YearMonthDay),
+                _ => return None,
+            }
+        }
+        Some(format)
+    }
+
+    /// Length of every string [`ClockFormat::format`] returns for this format, the time box is
+    /// sized from this so it does not change size from minute to minute
+    pub fn text_len(&self) -> usize {
+        let mut len = 5;
+        if self.seconds {
+            len += 3;
+        }
+        if self.hour == HourFormat::TwelveHour {
+            len += 3;
+        }
+        if self.weekday {
+            len += 4;
+        }
+        if self.date.is_some() {
+            len += 11;
+        }
+        len
+    }
+
+    pub fn format(&self, time: &DateTime) -> String<CLOCK_STRING_LEN> {
+        let mut text: String<CLOCK_STRING_LEN> = String::new();
+        if self.weekday {
+            push(&mut text, time.day_of_week.short_name());
+            push(&mut text, " ");
+        }
+        if let Some(order) = self.date {
+            let date = match order {
+                DateOrder::DayMonthYear => easy_format::<10>(format_args!(
+                    "{:02}/{:02}/{:04}",
+                    time.day, time.month, time.year
+                )),
+                DateOrder::MonthDayYear => easy_format::<10>(format_args!(
+                    "{:02}/{:02}/{:04}",
+                    time.month, time.day, time.year
+                )),
+                DateOrder::YearMonthDay => easy_format::<10>(format_args!(
+                    "{:04}-{:02}-{:02}",
+                    time.year, time.month, time.day
+                )),
+            };
+            push(&mut text, &date);
+            push(&mut text, " ");
+        }
+
+        let hour = match self.hour {
+            HourFormat::TwentyFourHour => time.hour,
+            HourFormat::TwelveHour if time.hour == 0 => 12,
+            HourFormat::TwelveHour if time.hour > 12 => time.hour - 12,
+            HourFormat::TwelveHour => time.hour,
+        };
+        push(
+            &mut text,
+            &easy_format::<5>(format_args!("{:02}:{:02}", hour, time.minute)),
+        );
+        if self.seconds {
+            push(
+                &mut text,
+                &easy_format::<3>(format_args!(":{:02}", time.second)),
+            );
+        }
+        if self.hour == HourFormat::TwelveHour {
+            push(&mut text, if time.hour < 12 { " AM" } else { " PM" });
+        }
+        text
+    }
+}
+
+impl core::fmt::Display for ClockFormat {
+    /// The `CLOCK_FORMAT` config value, read back by [`ClockFormat::from_config`]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self.hour {
+            HourFormat::TwelveHour => "12h",
+            HourFormat::TwentyFourHour => "24h",
+        })?;
+        if self.seconds {
+            f.write_str(",seconds")?;
+        }
+        if self.weekday {
+            f.write_str(",weekday")?;
+        }
+        match self.date {
+            None => Ok(()),
+            Some(DateOrder::DayMonthYear) => f.write_str(",dmy"),
+            Some(DateOrder::MonthDayYear) => f.write_str(",mdy"),
+            Some(DateOrder::YearMonthDay) => f.write_str(",ymd"),
+        }
+    }
+}
+
+/// Every part fits in [`CLOCK_STRING_LEN`] so this never drops anything
+fn push(text: &mut String<CLOCK_STRING_LEN>, part: &str) {
+    let _ = text.push_str(part);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::day_of_week;
+
+    fn at(hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year: 2024,
+            month: 8,
+            day: 16,
+            day_of_week: day_of_week(2024, 8, 16),
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn twelve_hour_clock() {
+        let format = ClockFormat::default();
+        let cases = [
+            (at(0, 0, 0), "12:00 AM"),
+            (at(9, 5, 0), "09:05 AM"),
+            (at(11, 59, 59), "11:59 AM"),
+            (at(12, 0, 0), "12:00 PM"),
+            (at(12, 30, 0), "12:30 PM"),
+            (at(18, 30, 5), "06:30 PM"),
+            (at(23, 59, 0), "11:59 PM"),
+        ];
+        for (time, expected) in cases {
+            assert_eq!(format.format(&time).as_str(), expected);
+        }
+    }
+
+    #[test]
+    fn twenty_four_hour_clock_with_seconds() {
+        let format = ClockFormat::from_config("24h, seconds").unwrap();
+        assert_eq!(format.format(&at(0, 0, 0)).as_str(), "00:00:00");
+        assert_eq!(format.format(&at(12, 0, 9)).as_str(), "12:00:09");
+        assert_eq!(format.format(&at(18, 30, 5)).as_str(), "18:30:05");
+    }
+
+    #[test]
+    fn dates_and_weekdays() {
+        let time = at(18, 30, 5);
+        let cases = [
+            ("weekday", "Fri 06:30 PM"),
+            ("24h,dmy", "16/08/2024 18:30"),
+            ("24h,mdy", "08/16/2024 18:30"),
+            ("24h,ymd,weekday", "Fri 2024-08-16 18:30"),
+            ("12h,seconds,weekday,ymd", "Fri 2024-08-16 06:30:05 PM"),
+        ];
+        for (config, expected) in cases {
+            let format = ClockFormat::from_config(config).unwrap();
+            assert_eq!(format.format(&time).as_str(), expected, "{config}");
+        }
+    }
+
+    #[test]
+    fn len_matches_every_format() {
+        let time = at(18, 30, 5);
+        for hour in ["12h", "24h"] {
+            for seconds in ["", "seconds"] {
+                for weekday in ["", "weekday"] {
+                    for date in ["", "dmy", "mdy", "ymd"] {
+                        let mut config: String<32> = String::new();
+                        for option in [hour, seconds, weekday, date] {
+                            config.push_str(option).unwrap();
+                            config.push(',').unwrap();
+                        }
+                        let format = ClockFormat::from_config(&config).unwrap();
+                        assert_eq!(format.format(&time).len(), format.text_len(), "{config}");
+                        let written = easy_format::<32>(format_args!("{}", format));
+                        assert_eq!(ClockFormat::from_config(&written), Some(format));
+                        assert!(format.text_len() <= CLOCK_STRING_LEN);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn config_parsing() {
+        assert_eq!(ClockFormat::from_config(""), Some(ClockFormat::default()));
+        assert_eq!(
+            ClockFormat::from_config("12h"),
+            Some(ClockFormat::default())
+        );
+        assert_eq!(
+            ClockFormat::from_config(" 24H ,Seconds"),
+            Some(ClockFormat {
+                hour: HourFormat::TwentyFourHour,
+                seconds: true,
+                weekday: false,
+                date: None,
+            })
+        );
+        assert_eq!(ClockFormat::from_config("24h,iso"), None);
+    }
+}
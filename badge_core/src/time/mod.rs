@@ -0,0 +1,379 @@
+pub mod format;
+pub mod rfc3339;
+pub mod sntp;
+pub mod sync;
+pub mod tz;
+
+use rfc3339::{parse_rfc3339, Rfc3339Error};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DayOfWeek {
+    Sunday = 0,
+    Monday = 1,
+    Tuesday = 2,
+    Wednesday = 3,
+    Thursday = 4,
+    Friday = 5,
+    Saturday = 6,
+}
+
+impl DayOfWeek {
+    /// Day of week from the 0 = Sunday numbering used by the rp2040 RTC and most time APIs
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Sunday),
+            1 => Some(Self::Monday),
+            2 => Some(Self::Tuesday),
+            3 => Some(Self::Wednesday),
+            4 => Some(Self::Thursday),
+            5 => Some(Self::Friday),
+            6 => Some(Self::Saturday),
+            _ => None,
+        }
+    }
+}
+
+/// A calendar date and wall clock time, mirrors the fields of the rp2040 RTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub day_of_week: DayOfWeek,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Something that keeps the time, on the badge this is the rp2040 RTC
+pub trait Clock {
+    type Error;
+
+    fn now(&self) -> Result<DateTime, Self::Error>;
+
+    fn set_datetime(&mut self, time: DateTime) -> Result<(), Self::Error>;
+}
+
+pub fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+/// Number of days in a month, months start at 1
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Days since 1970-01-01 for a date in the proleptic Gregorian calendar
+pub fn days_from_civil(year: u16, month: u8, day: u8) -> i32 {
+    // Howard Hinnant's days_from_civil, shifting the year to start in March so leap days are last
+    let year = year as i32 - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i32;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i32 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Date for a number of days since 1970-01-01, the inverse of [`days_from_civil`]
+pub fn civil_from_days(days: i32) -> (u16, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as u16, month as u8, day as u8)
+}
+
+/// Date and time for a number of seconds since 1970-01-01T00:00:00
+pub fn datetime_from_unix(unix_seconds: i64) -> DateTime {
+    let days = unix_seconds.div_euclid(86_400);
+    let second_of_day = unix_seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days as i32);
+    DateTime {
+        year,
+        month,
+        day,
+        day_of_week: day_of_week(year, month, day),
+        hour: (second_of_day / 3_600) as u8,
+        minute: (second_of_day % 3_600 / 60) as u8,
+        second: (second_of_day % 60) as u8,
+    }
+}
+
+/// Seconds since 1970-01-01T00:00:00, the inverse of [`datetime_from_unix`]
+pub fn unix_from_datetime(datetime: &DateTime) -> i64 {
+    days_from_civil(datetime.year, datetime.month, datetime.day) as i64 * 86_400
+        + datetime.hour as i64 * 3_600
+        + datetime.minute as i64 * 60
+        + datetime.second as i64
+}
+
+pub fn day_of_week(year: u16, month: u8, day: u8) -> DayOfWeek {
+    // 1970-01-01 was a Thursday
+    let day_number = (days_from_civil(year, month, day) + 4).rem_euclid(7);
+    DayOfWeek::from_u8(day_number as u8).unwrap()
+}
+
+/// Where the badge gets the time from when it connects to wifi
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimeSource {
+    /// JSON from an HTTP(S) endpoint, see [`TimeApiResponse`]
+    Http,
+    /// An SNTP server, see [`sntp`]
+    Sntp,
+}
+
+impl TimeSource {
+    /// Reads the `TIME_SOURCE` config value, `http` or `sntp`
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.trim() {
+            v if v.eq_ignore_ascii_case("http") => Some(Self::Http),
+            v if v.eq_ignore_ascii_case("sntp") || v.eq_ignore_ascii_case("ntp") => {
+                Some(Self::Sntp)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for TimeSource {
+    /// The `TIME_SOURCE` config value, read back by [`TimeSource::from_config`]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Http => "http",
+            Self::Sntp => "sntp",
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TimeApiResponse<'a> {
+    /// RFC 3339 timestamp, `dateTime` is what timeapi.io calls it
+    #[serde(alias = "dateTime")]
+    pub datetime: &'a str,
+    /// The same time in UTC, only worldtimeapi.org sends it
+    #[serde(default)]
+    pub utc_datetime: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimeApiError {
+    /// The body is not JSON or has no `datetime`
+    InvalidResponse,
+    InvalidDateTime(Rfc3339Error),
+}
+
+/// Parses the JSON body returned by the `TIME_API` endpoint into UTC. A timestamp without an
+/// offset is taken to already be UTC
+pub fn parse_time_api_response(body: &[u8]) -> Result<DateTime, TimeApiError> {
+    let (output, _used) = serde_json_core::de::from_slice::<TimeApiResponse>(body)
+        .map_err(|_| TimeApiError::InvalidResponse)?;
+    let timestamp = parse_rfc3339(output.utc_datetime.unwrap_or(output.datetime))
+        .map_err(TimeApiError::InvalidDateTime)?;
+    let offset_seconds = timestamp.utc_offset_minutes.unwrap_or(0) as i64 * 60;
+    if offset_seconds == 0 {
+        return Ok(timestamp.datetime);
+    }
+    Ok(datetime_from_unix(
+        unix_from_datetime(&timestamp.datetime) - offset_seconds,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_from_civil_matches_unix_epoch() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2024, 8, 16), 19_951);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+    }
+
+    #[test]
+    fn civil_from_days_round_trips() {
+        for days in [-719_468, -1, 0, 11_016, 11_017, 19_951, 24_855, 50_000] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn datetime_from_unix_time() {
+        assert_eq!(
+            datetime_from_unix(1_723_851_005),
+            DateTime {
+                year: 2024,
+                month: 8,
+                day: 16,
+                day_of_week: DayOfWeek::Friday,
+                hour: 23,
+                minute: 30,
+                second: 5,
+            }
+        );
+        assert_eq!(datetime_from_unix(0).day_of_week, DayOfWeek::Thursday);
+        for unix in [0, 951_782_399, 1_723_851_005, 4_102_444_800] {
+            assert_eq!(unix_from_datetime(&datetime_from_unix(unix)), unix);
+        }
+        assert_eq!(datetime_from_unix(951_782_400).day, 29);
+    }
+
+    #[test]
+    fn time_source_from_config() {
+        assert_eq!(TimeSource::from_config("http"), Some(TimeSource::Http));
+        assert_eq!(TimeSource::from_config(" SNTP "), Some(TimeSource::Sntp));
+        assert_eq!(TimeSource::from_config("ntp"), Some(TimeSource::Sntp));
+        assert_eq!(TimeSource::from_config("gps"), None);
+    }
+
+    #[test]
+    fn parses_real_api_payloads() {
+        // 2024-08-16T18:30:05-05:00
+        let friday_evening_utc = datetime_from_unix(1_723_851_005);
+        let friday_evening = DateTime {
+            year: 2024,
+            month: 8,
+            day: 16,
+            day_of_week: DayOfWeek::Friday,
+            hour: 18,
+            minute: 30,
+            second: 5,
+        };
+        let cases: [(&[u8], DateTime); 10] = [
+            // worldtimeapi.org
+            (
+                br#"{"utc_offset":"-05:00","timezone":"America/Chicago","day_of_week":5,"day_of_year":229,"datetime":"2024-08-16T18:30:05.123456-05:00","utc_datetime":"2024-08-16T23:30:05.123456+00:00","unixtime":1723851005,"raw_offset":-21600,"week_number":33,"dst":true,"abbreviation":"CDT","dst_offset":3600,"dst_from":"2024-03-10T08:00:00+00:00","dst_until":"2024-11-03T07:00:00+00:00","client_ip":"203.0.113.7"}"#,
+                friday_evening_utc,
+            ),
+            // worldtimeapi.org for a zone without daylight saving
+            (
+                br#"{"utc_offset":"+00:00","timezone":"Etc/UTC","day_of_week":5,"day_of_year":229,"datetime":"2024-08-16T18:30:05.000001+00:00","utc_datetime":"2024-08-16T18:30:05.000001+00:00","unixtime":1723833005,"raw_offset":0,"week_number":33,"dst":false,"abbreviation":"UTC","dst_offset":0,"dst_from":null,"dst_until":null,"client_ip":"203.0.113.7"}"#,
+                friday_evening,
+            ),
+            // timeapi.io asked for timeZone=UTC, it never sends an offset
+            (
+                br#"{"year":2024,"month":8,"day":16,"hour":18,"minute":30,"seconds":5,"milliSeconds":123,"dateTime":"2024-08-16T18:30:05.1234567","date":"08/16/2024","time":"18:30","timeZone":"UTC","dayOfWeek":"Friday","dstActive":false}"#,
+                friday_evening,
+            ),
+            // A self hosted endpoint returning UTC
+            (br#"{"datetime":"2024-08-16T18:30:05Z"}"#, friday_evening),
+            // Pretty printed
+            (
+                b"{\n  \"datetime\": \"2024-08-16T18:30:05+00:00\",\n  \"day_of_week\": 5\n}\n",
+                friday_evening,
+            ),
+            // Fields in a different order
+            (
+                br#"{"day_of_week":5,"datetime":"2024-08-16T18:30:05.5-05:00"}"#,
+                friday_evening_utc,
+            ),
+            // Day of week is worked out from the UTC date, not taken from the API
+            (
+                br#"{"datetime":"2024-08-16T18:30:05-05:00","day_of_week":9}"#,
+                friday_evening_utc,
+            ),
+            // Explicit null UTC time
+            (
+                br#"{"datetime":"2024-08-16T18:30:05-05:00","utc_datetime":null}"#,
+                friday_evening_utc,
+            ),
+            // Local evening that is already Saturday in UTC
+            (
+                br#"{"datetime":"2024-08-16T22:30:05-05:00"}"#,
+                DateTime {
+                    year: 2024,
+                    month: 8,
+                    day: 17,
+                    day_of_week: DayOfWeek::Saturday,
+                    hour: 3,
+                    minute: 30,
+                    second: 5,
+                },
+            ),
+            // Local morning that is still Thursday in UTC
+            (
+                br#"{"datetime":"2024-08-16T05:00:05+05:30"}"#,
+                DateTime {
+                    year: 2024,
+                    month: 8,
+                    day: 15,
+                    day_of_week: DayOfWeek::Thursday,
+                    hour: 23,
+                    minute: 30,
+                    second: 5,
+                },
+            ),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(
+                parse_time_api_response(body),
+                Ok(expected),
+                "{}",
+                core::str::from_utf8(body).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_api_payloads() {
+        assert_eq!(
+            parse_time_api_response(b"not json"),
+            Err(TimeApiError::InvalidResponse)
+        );
+        assert_eq!(
+            parse_time_api_response(b""),
+            Err(TimeApiError::InvalidResponse)
+        );
+        assert_eq!(
+            parse_time_api_response(br#"{"day_of_week":5}"#),
+            Err(TimeApiError::InvalidResponse)
+        );
+        assert_eq!(
+            parse_time_api_response(br#"{"error":"unknown location"}"#),
+            Err(TimeApiError::InvalidResponse)
+        );
+        assert_eq!(
+            parse_time_api_response(br#"{"datetime":"2024-08-16 18:30"}"#),
+            Ok(DateTime {
+                year: 2024,
+                month: 8,
+                day: 16,
+                day_of_week: DayOfWeek::Friday,
+                hour: 18,
+                minute: 30,
+                second: 0,
+            })
+        );
+        assert_eq!(
+            parse_time_api_response(br#"{"datetime":"Fri, 16 Aug 2024 18:30:05 GMT"}"#),
+            Err(TimeApiError::InvalidDateTime(Rfc3339Error::InvalidSyntax))
+        );
+        assert_eq!(
+            parse_time_api_response(br#"{"datetime":"2024-02-30T18:30:05Z"}"#),
+            Err(TimeApiError::InvalidDateTime(Rfc3339Error::InvalidDate))
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Parser for [RFC 3339](https://www.rfc-editor.org/rfc/rfc3339) timestamps as returned by time
+//! APIs, e.g. `2024-08-16T18:30:05.123456-05:00` or `2024-08-16T23:30:05Z`.
+//!
+//! It is a little more forgiving than the RFC so it also takes the common ISO 8601 shapes APIs
+//! send: a space instead of `T`, no seconds, no offset and offsets written as `+0530` or `+05`.
+
+use super::{day_of_week, days_in_month, DateTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Rfc3339Error {
+    /// The text is not shaped like a timestamp
+    InvalidSyntax,
+    /// Month or day is out of range
+    InvalidDate,
+    /// Hour, minute or second is out of range
+    InvalidTime,
+    /// The UTC offset is out of range
+    InvalidOffset,
+}
+
+/// A parsed timestamp. `datetime` is the wall clock time in the timestamp's own offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    pub datetime: DateTime,
+    /// Offset from UTC in minutes, `None` when the timestamp did not have one
+    pub utc_offset_minutes: Option<i16>,
+}
+
+/// Reads the bytes of the timestamp one field at a time
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn is_done(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    /// Consumes `byte` if it is next
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Rfc3339Error> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(Rfc3339Error::InvalidSyntax)
+        }
+    }
+
+    /// Reads exactly `count` ascii digits as a number
+    fn digits(&mut self, count: usize) -> Result<u16, Rfc3339Error> {
+        let end = self.position + count;
+        let digits = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(Rfc3339Error::InvalidSyntax)?;
+        let mut value = 0u16;
+        for digit in digits {
+            if !digit.is_ascii_digit() {
+                return Err(Rfc3339Error::InvalidSyntax);
+            }
+            value = value * 10 + (digit - b'0') as u16;
+        }
+        self.position = end;
+        Ok(value)
+    }
+
+    /// Skips one or more ascii digits
+    fn skip_digits(&mut self) -> Result<(), Rfc3339Error> {
+        let start = self.position;
+        while self.peek().is_some_and(|x| x.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(Rfc3339Error::InvalidSyntax);
+        }
+        Ok(())
+    }
+}
+
+/// Parses a timestamp, working out the day of the week from the date
+pub fn parse_rfc3339(text: &str) -> Result<Timestamp, Rfc3339Error> {
+    let mut cursor = Cursor {
+        bytes: text.trim().as_bytes(),
+        position: 0,
+    };
+
+    let year = cursor.digits(4)?;
+    cursor.expect(b'-')?;
+    let month = cursor.digits(2)? as u8;
+    cursor.expect(b'-')?;
+    let day = cursor.digits(2)? as u8;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(Rfc3339Error::InvalidDate);
+    }
+
+    if !(cursor.eat(b'T') || cursor.eat(b't') || cursor.eat(b' ')) {
+        return Err(Rfc3339Error::InvalidSyntax);
+    }
+
+    let hour = cursor.digits(2)? as u8;
+    cursor.expect(b':')?;
+    let minute = cursor.digits(2)? as u8;
+    let mut second = 0;
+    if cursor.eat(b':') {
+        second = cursor.digits(2)? as u8;
+        //Fractions of a second are not kept, the RTC only has whole seconds
+        if cursor.eat(b'.') || cursor.eat(b',') {
+            cursor.skip_digits()?;
+        }
+    }
+    // 60 is allowed for leap seconds
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(Rfc3339Error::InvalidTime);
+    }
+
+    let utc_offset_minutes = match cursor.peek() {
+        None => None,
+        Some(b'Z') | Some(b'z') => {
+            cursor.position += 1;
+            Some(0)
+        }
+        Some(sign @ (b'+' | b'-')) => {
+            cursor.position += 1;
+            let hours = cursor.digits(2)?;
+            let minutes = if cursor.is_done() {
+                0
+            } else {
+                cursor.eat(b':');
+                cursor.digits(2)?
+            };
+            if hours > 23 || minutes > 59 {
+                return Err(Rfc3339Error::InvalidOffset);
+            }
+            let offset = (hours * 60 + minutes) as i16;
+            Some(if sign == b'-' { -offset } else { offset })
+        }
+        Some(_) => return Err(Rfc3339Error::InvalidSyntax),
+    };
+
+    if !cursor.is_done() {
+        return Err(Rfc3339Error::InvalidSyntax);
+    }
+
+    Ok(Timestamp {
+        datetime: DateTime {
+            year,
+            month,
+            day,
+            day_of_week: day_of_week(year, month, day),
+            hour,
+            minute,
+            // The RTC has no leap seconds so hold on the last second of the minute
+            second: second.min(59),
+        },
+        utc_offset_minutes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::DayOfWeek;
+
+    fn datetime(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            day_of_week: day_of_week(year, month, day),
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn parses_valid_timestamps() {
+        let cases = [
+            (
+                "2024-08-16T18:30:05.123456-05:00",
+                datetime(2024, 8, 16, 18, 30, 5),
+                Some(-300),
+            ),
+            (
+                "2024-08-16T23:30:05.123456+00:00",
+                datetime(2024, 8, 16, 23, 30, 5),
+                Some(0),
+            ),
+            (
+                "2024-08-16T23:30:05Z",
+                datetime(2024, 8, 16, 23, 30, 5),
+                Some(0),
+            ),
+            (
+                "2024-08-16t23:30:05z",
+                datetime(2024, 8, 16, 23, 30, 5),
+                Some(0),
+            ),
+            (
+                "2024-08-16T23:30:05.1Z",
+                datetime(2024, 8, 16, 23, 30, 5),
+                Some(0),
+            ),
+            (
+                "2024-08-16T18:30:05.1234567",
+                datetime(2024, 8, 16, 18, 30, 5),
+                None,
+            ),
+            (
+                "2024-08-16T18:30:05",
+                datetime(2024, 8, 16, 18, 30, 5),
+                None,
+            ),
+            ("2024-08-16T18:30", datetime(2024, 8, 16, 18, 30, 0), None),
+            (
+                "2024-08-16 18:30:05+02:00",
+                datetime(2024, 8, 16, 18, 30, 5),
+                Some(120),
+            ),
+            (
+                "2024-08-17T05:00:05+05:30",
+                datetime(2024, 8, 17, 5, 0, 5),
+                Some(330),
+            ),
+            (
+                "2024-08-17T05:00:05+0530",
+                datetime(2024, 8, 17, 5, 0, 5),
+                Some(330),
+            ),
+            (
+                "2024-08-17T05:00:05+05",
+                datetime(2024, 8, 17, 5, 0, 5),
+                Some(300),
+            ),
+            (
+                "2024-08-17T12:45:05+12:45",
+                datetime(2024, 8, 17, 12, 45, 5),
+                Some(765),
+            ),
+            (
+                "2024-08-16T13:30:05-10:00",
+                datetime(2024, 8, 16, 13, 30, 5),
+                Some(-600),
+            ),
+            (
+                "2024-08-16T18:30:05,5-05:00",
+                datetime(2024, 8, 16, 18, 30, 5),
+                Some(-300),
+            ),
+            (
+                "2024-02-29T00:00:00Z",
+                datetime(2024, 2, 29, 0, 0, 0),
+                Some(0),
+            ),
+            (
+                "2000-02-29T12:00:00Z",
+                datetime(2000, 2, 29, 12, 0, 0),
+                Some(0),
+            ),
+            (
+                "2016-12-31T23:59:60Z",
+                datetime(2016, 12, 31, 23, 59, 59),
+                Some(0),
+            ),
+            (
+                "1999-12-31T23:59:59-00:00",
+                datetime(1999, 12, 31, 23, 59, 59),
+                Some(0),
+            ),
+            (
+                "  2024-08-16T18:30:05Z\n",
+                datetime(2024, 8, 16, 18, 30, 5),
+                Some(0),
+            ),
+        ];
+        for (text, expected, offset) in cases {
+            let parsed = parse_rfc3339(text).unwrap_or_else(|e| panic!("{text}: {e:?}"));
+            assert_eq!(parsed.datetime, expected, "{text}");
+            assert_eq!(parsed.utc_offset_minutes, offset, "{text}");
+        }
+    }
+
+    #[test]
+    fn works_out_day_of_week() {
+        let cases = [
+            ("2024-08-16T00:00:00Z", DayOfWeek::Friday),
+            ("2024-02-29T00:00:00Z", DayOfWeek::Thursday),
+            ("2000-01-01T00:00:00Z", DayOfWeek::Saturday),
+            ("1970-01-01T00:00:00Z", DayOfWeek::Thursday),
+            ("2038-01-19T03:14:07Z", DayOfWeek::Tuesday),
+            ("2025-01-05T00:00:00Z", DayOfWeek::Sunday),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                parse_rfc3339(text).unwrap().datetime.day_of_week,
+                expected,
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        let cases = [
+            ("", Rfc3339Error::InvalidSyntax),
+            ("not a date", Rfc3339Error::InvalidSyntax),
+            ("2024-08-16", Rfc3339Error::InvalidSyntax),
+            ("2024-08-16T", Rfc3339Error::InvalidSyntax),
+            ("2024/08/16T18:30:05Z", Rfc3339Error::InvalidSyntax),
+            ("24-08-16T18:30:05Z", Rfc3339Error::InvalidSyntax),
+            ("2024-8-16T18:30:05Z", Rfc3339Error::InvalidSyntax),
+            ("2024-08-16T18:30:05.Z", Rfc3339Error::InvalidSyntax),
+            ("2024-08-16T18:30:05 UTC", Rfc3339Error::InvalidSyntax),
+            ("2024-08-16T18:30:05+5:00", Rfc3339Error::InvalidSyntax),
+            ("2024-08-16T18:30:05Zjunk", Rfc3339Error::InvalidSyntax),
+            ("2024-13-16T18:30:05Z", Rfc3339Error::InvalidDate),
+            ("2024-00-16T18:30:05Z", Rfc3339Error::InvalidDate),
+            ("2024-08-00T18:30:05Z", Rfc3339Error::InvalidDate),
+            ("2024-04-31T18:30:05Z", Rfc3339Error::InvalidDate),
+            ("2023-02-29T18:30:05Z", Rfc3339Error::InvalidDate),
+            ("1900-02-29T18:30:05Z", Rfc3339Error::InvalidDate),
+            ("2024-08-16T24:00:00Z", Rfc3339Error::InvalidTime),
+            ("2024-08-16T18:60:05Z", Rfc3339Error::InvalidTime),
+            ("2024-08-16T18:30:61Z", Rfc3339Error::InvalidTime),
+            ("2024-08-16T18:30:05+24:00", Rfc3339Error::InvalidOffset),
+            ("2024-08-16T18:30:05+05:60", Rfc3339Error::InvalidOffset),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_rfc3339(text), Err(expected), "{text}");
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Packet encoding and decoding for a [RFC 4330](https://www.rfc-editor.org/rfc/rfc4330) SNTP
+//! client. Sending and receiving is left to the caller so this works with any UDP socket.
+
+/// UDP port NTP servers listen on
+pub const NTP_PORT: u16 = 123;
+/// Size of an NTP packet without the optional authenticator
+pub const PACKET_LEN: usize = 48;
+
+/// Seconds from 1900-01-01, the NTP epoch, to 1970-01-01
+const NTP_TO_UNIX_SECONDS: u64 = 2_208_988_800;
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const MODE_BROADCAST: u8 = 5;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SntpError {
+    /// Fewer than 48 bytes came back
+    TooShort,
+    /// The packet is not a reply from a server
+    WrongMode,
+    /// The server asked us to go away, holds the 4 letter kiss code like `RATE` or `DENY`
+    KissOfDeath([u8; 4]),
+    /// The server's own clock is not synchronized
+    Unsynchronized,
+    /// The reply is not for the request we sent
+    OriginateMismatch,
+    /// The server did not fill in the time
+    ZeroTransmit,
+    /// The time is before 1970, which no server that knows the time sends
+    BeforeUnixEpoch,
+}
+
+/// An NTP timestamp, seconds and fractions of a second since 1900-01-01
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NtpTimestamp {
+    pub seconds: u32,
+    pub fraction: u32,
+}
+
+impl NtpTimestamp {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            seconds: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            fraction: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&self.seconds.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.fraction.to_be_bytes());
+        bytes
+    }
+
+    /// Milliseconds since 1970-01-01, `None` if it is before then. Seconds with the top bit clear
+    /// are treated as being after the 2036 rollover as RFC 4330 section 3 suggests
+    pub fn to_unix_millis(self) -> Option<u64> {
+        let mut seconds = self.seconds as u64;
+        if self.seconds & 0x8000_0000 == 0 {
+            seconds += 1 << 32;
+        }
+        let millis = (self.fraction as u64 * 1_000) >> 32;
+        Some(seconds.checked_sub(NTP_TO_UNIX_SECONDS)? * 1_000 + millis)
+    }
+}
+
+/// The parts of a server reply the badge uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SntpResponse {
+    pub stratum: u8,
+    /// When the server sent the reply
+    pub transmit: NtpTimestamp,
+    /// `transmit` in milliseconds since 1970-01-01
+    pub unix_millis: u64,
+}
+
+/// Builds a client request. `transmit` is echoed back by the server so any unique value works
+/// when the real time is not known yet
+pub fn encode_request(transmit: NtpTimestamp) -> [u8; PACKET_LEN] {
+    let mut packet = [0u8; PACKET_LEN];
+    packet[0] = (VERSION << 3) | MODE_CLIENT;
+    packet[40..48].copy_from_slice(&transmit.to_bytes());
+    packet
+}
+
+/// Checks a server reply against the request that was sent and pulls out the time
+pub fn decode_response(
+    packet: &[u8],
+    request_transmit: NtpTimestamp,
+) -> Result<SntpResponse, SntpError> {
+    if packet.len() < PACKET_LEN {
+        return Err(SntpError::TooShort);
+    }
+    let leap = packet[0] >> 6;
+    let mode = packet[0] & 0b111;
+    let stratum = packet[1];
+    if mode != MODE_SERVER && mode != MODE_BROADCAST {
+        return Err(SntpError::WrongMode);
+    }
+    if stratum == 0 {
+        return Err(SntpError::KissOfDeath([
+            packet[12], packet[13], packet[14], packet[15],
+        ]));
+    }
+    if leap == LEAP_UNSYNCHRONIZED {
+        return Err(SntpError::Unsynchronized);
+    }
+    if mode == MODE_SERVER && NtpTimestamp::from_bytes(&packet[24..32]) != request_transmit {
+        return Err(SntpError::OriginateMismatch);
+    }
+    let transmit = NtpTimestamp::from_bytes(&packet[40..48]);
+    if transmit.seconds == 0 && transmit.fraction == 0 {
+        return Err(SntpError::ZeroTransmit);
+    }
+    let unix_millis = transmit
+        .to_unix_millis()
+        .ok_or(SntpError::BeforeUnixEpoch)?;
+    Ok(SntpResponse {
+        stratum,
+        transmit,
+        unix_millis,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+
+    const REQUEST_TRANSMIT: NtpTimestamp = NtpTimestamp {
+        seconds: 0x1234_5678,
+        fraction: 0x9abc_def0,
+    };
+    /// 2024-08-16T23:30:05.5Z
+    const SERVER_TIME: NtpTimestamp = NtpTimestamp {
+        seconds: (1_723_851_005 + NTP_TO_UNIX_SECONDS) as u32,
+        fraction: 0x8000_0000,
+    };
+
+    /// What a well behaved server replies with
+    fn reply_to(request: &[u8]) -> [u8; PACKET_LEN] {
+        let mut reply = [0u8; PACKET_LEN];
+        reply[0] = (VERSION << 3) | MODE_SERVER;
+        reply[1] = 2;
+        reply[12..16].copy_from_slice(b"GPS\0");
+        reply[24..32].copy_from_slice(&request[40..48]);
+        reply[32..40].copy_from_slice(&SERVER_TIME.to_bytes());
+        reply[40..48].copy_from_slice(&SERVER_TIME.to_bytes());
+        reply
+    }
+
+    #[test]
+    fn request_is_a_version_4_client_packet() {
+        let request = encode_request(REQUEST_TRANSMIT);
+        assert_eq!(request[0], 0b00_100_011);
+        assert!(request[1..40].iter().all(|x| *x == 0));
+        assert_eq!(NtpTimestamp::from_bytes(&request[40..]), REQUEST_TRANSMIT);
+    }
+
+    #[test]
+    fn converts_to_unix_time() {
+        assert_eq!(SERVER_TIME.to_unix_millis(), Some(1_723_851_005_500));
+        // The first second after the 2036 rollover
+        let after_rollover = NtpTimestamp {
+            seconds: 0,
+            fraction: 0,
+        };
+        assert_eq!(after_rollover.to_unix_millis(), Some(2_085_978_496_000));
+        // The last second of 1969
+        let before_epoch = NtpTimestamp {
+            seconds: (NTP_TO_UNIX_SECONDS - 1) as u32,
+            fraction: 0,
+        };
+        assert_eq!(before_epoch.to_unix_millis(), None);
+    }
+
+    #[test]
+    fn round_trips_through_a_local_server() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_address = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 128];
+            let (len, from) = server.recv_from(&mut buf).unwrap();
+            assert_eq!(len, PACKET_LEN);
+            server.send_to(&reply_to(&buf[..len]), from).unwrap();
+        });
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client
+            .send_to(&encode_request(REQUEST_TRANSMIT), server_address)
+            .unwrap();
+        let mut buf = [0u8; 128];
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        handle.join().unwrap();
+
+        let response = decode_response(&buf[..len], REQUEST_TRANSMIT).unwrap();
+        assert_eq!(response.stratum, 2);
+        assert_eq!(response.transmit, SERVER_TIME);
+        assert_eq!(response.unix_millis, 1_723_851_005_500);
+    }
+
+    #[test]
+    fn rejects_bad_replies() {
+        let good = reply_to(&encode_request(REQUEST_TRANSMIT));
+        assert!(decode_response(&good, REQUEST_TRANSMIT).is_ok());
+
+        assert_eq!(
+            decode_response(&good[..47], REQUEST_TRANSMIT),
+            Err(SntpError::TooShort)
+        );
+
+        let mut client_mode = good;
+        client_mode[0] = (VERSION << 3) | MODE_CLIENT;
+        assert_eq!(
+            decode_response(&client_mode, REQUEST_TRANSMIT),
+            Err(SntpError::WrongMode)
+        );
+
+        let mut kiss = good;
+        kiss[1] = 0;
+        kiss[12..16].copy_from_slice(b"RATE");
+        assert_eq!(
+            decode_response(&kiss, REQUEST_TRANSMIT),
+            Err(SntpError::KissOfDeath(*b"RATE"))
+        );
+
+        let mut unsynchronized = good;
+        unsynchronized[0] |= LEAP_UNSYNCHRONIZED << 6;
+        assert_eq!(
+            decode_response(&unsynchronized, REQUEST_TRANSMIT),
+            Err(SntpError::Unsynchronized)
+        );
+
+        let other_request = NtpTimestamp {
+            seconds: 1,
+            fraction: 2,
+        };
+        assert_eq!(
+            decode_response(&good, other_request),
+            Err(SntpError::OriginateMismatch)
+        );
+
+        let mut no_time = good;
+        no_time[40..48].fill(0);
+        assert_eq!(
+            decode_response(&no_time, REQUEST_TRANSMIT),
+            Err(SntpError::ZeroTransmit)
+        );
+
+        let mut before_epoch = good;
+        before_epoch[40..44].copy_from_slice(&0x8000_0000u32.to_be_bytes());
+        assert_eq!(
+            decode_response(&before_epoch, REQUEST_TRANSMIT),
+            Err(SntpError::BeforeUnixEpoch)
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Decides when to sync the clock and corrects for the RTC drifting between syncs.
+//!
+//! The rp2040's RTC runs off a crystal that is a few tens of ppm off, which adds up to seconds a
+//! day over a multi day event. Each sync compares what the RTC read against the real time to
+//! measure that drift, which is then taken off the RTC's time until the next sync.
+
+use super::{datetime_from_unix, unix_from_datetime, DateTime};
+
+/// Drift is only measured over at least this many seconds, the RTC only has whole seconds
+const MIN_DRIFT_SAMPLE_SECONDS: i64 = 60 * 60;
+/// Anything more than this is not drift, the time was changed some other way
+const MAX_DRIFT_PPM: i64 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncConfig {
+    /// Seconds to wait after the first failed sync, doubled for each failure after
+    pub retry_initial_seconds: u32,
+    /// Longest wait between retries while the clock is not synced
+    pub retry_max_seconds: u32,
+    /// Seconds between syncs once the clock is synced
+    pub resync_seconds: u32,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            retry_initial_seconds: 10,
+            retry_max_seconds: 10 * 60,
+            resync_seconds: 6 * 60 * 60,
+        }
+    }
+}
+
+pub struct ClockSync {
+    config: SyncConfig,
+    drift_ppm: i32,
+    /// Unix seconds of the last successful sync
+    last_sync: Option<i64>,
+    failures: u32,
+}
+
+impl ClockSync {
+    /// `drift_ppm` is the drift measured on a previous boot, positive means the RTC runs fast
+    pub fn new(config: SyncConfig, drift_ppm: i32) -> Self {
+        Self {
+            config,
+            drift_ppm,
+            last_sync: None,
+            failures: 0,
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.last_sync.is_some()
+    }
+
+    pub fn drift_ppm(&self) -> i32 {
+        self.drift_ppm
+    }
+
+    /// Seconds to wait before the next sync attempt
+    pub fn next_attempt_in(&self) -> u32 {
+        if self.failures == 0 {
+            return if self.is_synced() {
+                self.config.resync_seconds
+            } else {
+                0
+            };
+        }
+        let backoff = self
+            .config
+            .retry_initial_seconds
+            .saturating_mul(1u32.checked_shl(self.failures - 1).unwrap_or(u32::MAX));
+        let retry = backoff.min(self.config.retry_max_seconds);
+        if self.is_synced() {
+            // Still synced from before so there is no rush, just don't wait longer than a resync
+            retry.min(self.config.resync_seconds)
+        } else {
+            retry
+        }
+    }
+
+    pub fn sync_failed(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    /// Records a successful sync where the RTC read `rtc_before` just before being set to
+    /// `true_time`. Returns the drift to use from now on
+    pub fn synced(&mut self, rtc_before: Option<DateTime>, true_time: DateTime) -> i32 {
+        let now = unix_from_datetime(&true_time);
+        if let (Some(last_sync), Some(rtc_before)) = (self.last_sync, rtc_before) {
+            let elapsed = now - last_sync;
+            let error = unix_from_datetime(&rtc_before) - now;
+            if elapsed >= MIN_DRIFT_SAMPLE_SECONDS {
+                let measured = error * 1_000_000 / elapsed;
+                if measured.abs() <= MAX_DRIFT_PPM {
+                    self.drift_ppm = measured as i32;
+                }
+            }
+        }
+        self.last_sync = Some(now);
+        self.failures = 0;
+        self.drift_ppm
+    }
+
+    /// Takes the expected drift since the last sync off of what the RTC reads
+    pub fn corrected(&self, rtc_now: DateTime) -> DateTime {
+        let Some(last_sync) = self.last_sync else {
+            return rtc_now;
+        };
+        let rtc_unix = unix_from_datetime(&rtc_now);
+        let since_sync = rtc_unix - last_sync;
+        let correction = since_sync * self.drift_ppm as i64 / 1_000_000;
+        if correction == 0 {
+            return rtc_now;
+        }
+        datetime_from_unix(rtc_unix - correction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(unix: i64) -> DateTime {
+        datetime_from_unix(unix)
+    }
+
+    const START: i64 = 1_723_851_005;
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn backs_off_while_unsynced() {
+        let mut sync = ClockSync::new(SyncConfig::default(), 0);
+        assert_eq!(sync.next_attempt_in(), 0);
+        let mut waits = [0; 8];
+        for wait in waits.iter_mut() {
+            sync.sync_failed();
+            *wait = sync.next_attempt_in();
+        }
+        assert_eq!(waits, [10, 20, 40, 80, 160, 320, 600, 600]);
+        for _ in 0..100 {
+            sync.sync_failed();
+        }
+        assert_eq!(sync.next_attempt_in(), 600);
+    }
+
+    #[test]
+    fn resyncs_on_interval_once_synced() {
+        let mut sync = ClockSync::new(SyncConfig::default(), 0);
+        sync.sync_failed();
+        sync.synced(None, at(START));
+        assert!(sync.is_synced());
+        assert_eq!(sync.next_attempt_in(), 6 * 60 * 60);
+        sync.sync_failed();
+        assert_eq!(sync.next_attempt_in(), 10);
+    }
+
+    #[test]
+    fn measures_drift_between_syncs() {
+        let mut sync = ClockSync::new(SyncConfig::default(), 0);
+        sync.synced(None, at(START));
+        // 4 seconds fast after a day is ~46 ppm
+        let drift = sync.synced(Some(at(START + DAY + 4)), at(START + DAY));
+        assert_eq!(drift, 46);
+        let drift = sync.synced(Some(at(START + 2 * DAY - 2)), at(START + 2 * DAY));
+        assert_eq!(drift, -23);
+    }
+
+    #[test]
+    fn ignores_short_samples_and_time_jumps() {
+        let mut sync = ClockSync::new(SyncConfig::default(), 30);
+        sync.synced(None, at(START));
+        assert_eq!(sync.synced(Some(at(START + 61)), at(START + 60)), 30);
+        // An hour off after a day is a time zone change, not drift
+        assert_eq!(
+            sync.synced(Some(at(START + DAY + 3_600)), at(START + DAY)),
+            30
+        );
+    }
+
+    #[test]
+    fn corrects_the_rtc_between_syncs() {
+        let mut sync = ClockSync::new(SyncConfig::default(), 50);
+        assert_eq!(sync.corrected(at(START + DAY)), at(START + DAY));
+        sync.synced(None, at(START));
+        assert_eq!(sync.corrected(at(START + 10)), at(START + 10));
+        // 50 ppm fast is 4.32 seconds a day
+        assert_eq!(sync.corrected(at(START + DAY)), at(START + DAY - 4));
+    }
+}
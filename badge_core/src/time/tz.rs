@@ -0,0 +1,430 @@
+//! Time zones from [POSIX TZ strings](https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/V1_chap08.html#tag_08_03)
+//! like `CET-1CEST,M3.5.0,M10.5.0/3` or `EST5EDT,M3.2.0,M11.1.0`.
+//!
+//! The RTC is kept in UTC and this turns it into local time for the display, so moving to a
+//! conference in another zone is only a config change and daylight saving switches on its own.
+//! The string for a zone is the last line of its file in `/usr/share/zoneinfo` on most Linux
+//! machines.
+
+use heapless::String;
+
+use super::{
+    datetime_from_unix, day_of_week, days_from_civil, days_in_month, is_leap_year,
+    unix_from_datetime, DateTime,
+};
+
+/// When daylight saving time starts or ends if the TZ string has a DST name but no rules. This is
+/// the current US rule, which is what glibc does too
+const DEFAULT_DST_RULES: &str = ",M3.2.0,M11.1.0";
+/// Transitions happen at 02:00 local time unless the rule says otherwise
+const DEFAULT_TRANSITION_TIME: i32 = 2 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TzError {
+    /// Zone abbreviations have to be at least 3 letters, or anything in `<>`
+    InvalidName,
+    InvalidOffset,
+    InvalidRule,
+    /// Something was left after the rules
+    TrailingCharacters,
+}
+
+/// Day of the year a transition happens on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    /// `Jn`, 1 to 365 where February 29th is never counted
+    Julian(u16),
+    /// `n`, 0 to 365 counting February 29th in leap years
+    DayOfYear(u16),
+    /// `Mm.w.d`, day `d` (0 = Sunday) of week `w` (5 = last) of month `m`
+    MonthWeekDay { month: u8, week: u8, day: u8 },
+}
+
+impl Rule {
+    /// Days since 1970-01-01 of the transition day in `year`
+    fn days(&self, year: u16) -> i32 {
+        let january_first = days_from_civil(year, 1, 1);
+        match *self {
+            Rule::Julian(day) => {
+                let leap_day = if is_leap_year(year) && day >= 60 {
+                    1
+                } else {
+                    0
+                };
+                january_first + day as i32 - 1 + leap_day
+            }
+            Rule::DayOfYear(day) => january_first + day as i32,
+            Rule::MonthWeekDay { month, week, day } => {
+                let first_weekday = day_of_week(year, month, 1) as u8;
+                let mut day_of_month = 1 + (day + 7 - first_weekday) % 7 + (week - 1) * 7;
+                while day_of_month > days_in_month(year, month) {
+                    day_of_month -= 7;
+                }
+                days_from_civil(year, month, day_of_month)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DaylightSaving {
+    name: String<8>,
+    /// Seconds east of UTC while daylight saving is on
+    offset: i32,
+    start: Rule,
+    /// Seconds after local midnight the change happens, in standard time
+    start_time: i32,
+    end: Rule,
+    /// Seconds after local midnight the change happens, in daylight saving time
+    end_time: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeZone {
+    name: String<8>,
+    /// Seconds east of UTC, note POSIX strings have the opposite sign
+    offset: i32,
+    dst: Option<DaylightSaving>,
+}
+
+/// Reads a TZ string front to back
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn number(&mut self, error: TzError) -> Result<u16, TzError> {
+        let start = self.position;
+        let mut value: u16 = 0;
+        while let Some(digit) = self.peek().filter(|x| x.is_ascii_digit()) {
+            value = value
+                .checked_mul(10)
+                .and_then(|x| x.checked_add((digit - b'0') as u16))
+                .ok_or(error)?;
+            self.position += 1;
+        }
+        if self.position == start {
+            return Err(error);
+        }
+        Ok(value)
+    }
+
+    fn name(&mut self) -> Result<String<8>, TzError> {
+        let start = self.position;
+        let name = if self.eat(b'<') {
+            while self.peek().is_some_and(|x| x != b'>') {
+                self.position += 1;
+            }
+            let name = &self.bytes[start + 1..self.position];
+            if !self.eat(b'>') {
+                return Err(TzError::InvalidName);
+            }
+            name
+        } else {
+            while self.peek().is_some_and(|x| x.is_ascii_alphabetic()) {
+                self.position += 1;
+            }
+            &self.bytes[start..self.position]
+        };
+        if name.len() < 3 {
+            return Err(TzError::InvalidName);
+        }
+        let name = core::str::from_utf8(name).map_err(|_| TzError::InvalidName)?;
+        String::try_from(name).map_err(|_| TzError::InvalidName)
+    }
+
+    /// `[+-]hh[:mm[:ss]]` as seconds, hours can go up to 167 for transition times
+    fn time(&mut self, error: TzError) -> Result<i32, TzError> {
+        let negative = if self.eat(b'-') {
+            true
+        } else {
+            self.eat(b'+');
+            false
+        };
+        let hours = self.number(error)?;
+        let mut minutes = 0;
+        let mut seconds = 0;
+        if self.eat(b':') {
+            minutes = self.number(error)?;
+            if self.eat(b':') {
+                seconds = self.number(error)?;
+            }
+        }
+        if hours > 167 || minutes > 59 || seconds > 59 {
+            return Err(error);
+        }
+        let total = hours as i32 * 3_600 + minutes as i32 * 60 + seconds as i32;
+        Ok(if negative { -total } else { total })
+    }
+
+    /// A POSIX offset is hours west of UTC, this returns seconds east
+    fn offset(&mut self) -> Result<i32, TzError> {
+        let offset = self.time(TzError::InvalidOffset)?;
+        if offset.abs() > 24 * 3_600 {
+            return Err(TzError::InvalidOffset);
+        }
+        Ok(-offset)
+    }
+
+    fn rule(&mut self) -> Result<(Rule, i32), TzError> {
+        let rule = if self.eat(b'J') {
+            let day = self.number(TzError::InvalidRule)?;
+            if !(1..=365).contains(&day) {
+                return Err(TzError::InvalidRule);
+            }
+            Rule::Julian(day)
+        } else if self.eat(b'M') {
+            let month = self.number(TzError::InvalidRule)?;
+            if !self.eat(b'.') {
+                return Err(TzError::InvalidRule);
+            }
+            let week = self.number(TzError::InvalidRule)?;
+            if !self.eat(b'.') {
+                return Err(TzError::InvalidRule);
+            }
+            let day = self.number(TzError::InvalidRule)?;
+            if !(1..=12).contains(&month) || !(1..=5).contains(&week) || day > 6 {
+                return Err(TzError::InvalidRule);
+            }
+            Rule::MonthWeekDay {
+                month: month as u8,
+                week: week as u8,
+                day: day as u8,
+            }
+        } else {
+            let day = self.number(TzError::InvalidRule)?;
+            if day > 365 {
+                return Err(TzError::InvalidRule);
+            }
+            Rule::DayOfYear(day)
+        };
+        let time = if self.eat(b'/') {
+            self.time(TzError::InvalidRule)?
+        } else {
+            DEFAULT_TRANSITION_TIME
+        };
+        Ok((rule, time))
+    }
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        Self {
+            name: String::try_from("UTC").unwrap(),
+            offset: 0,
+            dst: None,
+        }
+    }
+
+    pub fn parse(tz: &str) -> Result<Self, TzError> {
+        let mut parser = Parser {
+            bytes: tz.trim().as_bytes(),
+            position: 0,
+        };
+        let name = parser.name()?;
+        let offset = parser.offset()?;
+        if parser.peek().is_none() {
+            return Ok(Self {
+                name,
+                offset,
+                dst: None,
+            });
+        }
+
+        let dst_name = parser.name()?;
+        let dst_offset = match parser.peek() {
+            Some(b',') | None => offset + 3_600,
+            Some(_) => parser.offset()?,
+        };
+        if parser.peek().is_none() {
+            parser = Parser {
+                bytes: DEFAULT_DST_RULES.as_bytes(),
+                position: 0,
+            };
+        }
+        if !parser.eat(b',') {
+            return Err(TzError::InvalidRule);
+        }
+        let (start, start_time) = parser.rule()?;
+        if !parser.eat(b',') {
+            return Err(TzError::InvalidRule);
+        }
+        let (end, end_time) = parser.rule()?;
+        if parser.peek().is_some() {
+            return Err(TzError::TrailingCharacters);
+        }
+
+        Ok(Self {
+            name,
+            offset,
+            dst: Some(DaylightSaving {
+                name: dst_name,
+                offset: dst_offset,
+                start,
+                start_time,
+                end,
+                end_time,
+            }),
+        })
+    }
+
+    /// Whether daylight saving time is on at `unix_utc` seconds
+    pub fn is_dst(&self, unix_utc: i64) -> bool {
+        let Some(dst) = &self.dst else {
+            return false;
+        };
+        let year = datetime_from_unix(unix_utc + self.offset as i64).year;
+        // Both transitions as UTC, start happens in standard time and end in daylight time
+        let start =
+            dst.start.days(year) as i64 * 86_400 + dst.start_time as i64 - self.offset as i64;
+        let end = dst.end.days(year) as i64 * 86_400 + dst.end_time as i64 - dst.offset as i64;
+        if start < end {
+            unix_utc >= start && unix_utc < end
+        } else {
+            // Southern hemisphere, daylight saving goes over the new year
+            unix_utc >= start || unix_utc < end
+        }
+    }
+
+    /// Seconds east of UTC at `unix_utc` seconds
+    pub fn utc_offset(&self, unix_utc: i64) -> i32 {
+        match &self.dst {
+            Some(dst) if self.is_dst(unix_utc) => dst.offset,
+            _ => self.offset,
+        }
+    }
+
+    /// The abbreviation in use at `unix_utc` seconds, like `CET` or `CEST`
+    pub fn abbreviation(&self, unix_utc: i64) -> &str {
+        match &self.dst {
+            Some(dst) if self.is_dst(unix_utc) => &dst.name,
+            _ => &self.name,
+        }
+    }
+
+    /// Local wall clock time for a UTC time
+    pub fn to_local(&self, utc: &DateTime) -> DateTime {
+        let unix = unix_from_datetime(utc);
+        datetime_from_unix(unix + self.utc_offset(unix) as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unix seconds for a UTC time
+    fn utc(year: u16, month: u8, day: u8, hour: u8, minute: u8) -> i64 {
+        days_from_civil(year, month, day) as i64 * 86_400 + hour as i64 * 3_600 + minute as i64 * 60
+    }
+
+    #[test]
+    fn fixed_offsets() {
+        assert_eq!(TimeZone::parse("UTC0").unwrap(), TimeZone::utc());
+        let india = TimeZone::parse("IST-5:30").unwrap();
+        assert_eq!(india.utc_offset(0), 5 * 3_600 + 30 * 60);
+        let iran = TimeZone::parse("<+0330>-3:30").unwrap();
+        assert_eq!(iran.utc_offset(0), 3 * 3_600 + 30 * 60);
+        assert_eq!(iran.abbreviation(0), "+0330");
+        let hawaii = TimeZone::parse("HST10").unwrap();
+        assert_eq!(hawaii.utc_offset(0), -10 * 3_600);
+    }
+
+    #[test]
+    fn central_europe() {
+        let tz = TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        // 2024 switches at 01:00 UTC on March 31st and October 27th
+        assert_eq!(tz.utc_offset(utc(2024, 3, 31, 0, 59)), 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 3, 31, 1, 0)), 7_200);
+        assert_eq!(tz.abbreviation(utc(2024, 7, 1, 0, 0)), "CEST");
+        assert_eq!(tz.utc_offset(utc(2024, 10, 27, 0, 59)), 7_200);
+        assert_eq!(tz.utc_offset(utc(2024, 10, 27, 1, 0)), 3_600);
+        assert_eq!(tz.abbreviation(utc(2024, 12, 1, 0, 0)), "CET");
+    }
+
+    #[test]
+    fn us_eastern() {
+        let tz = TimeZone::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        // 2024 switches on March 10th at 2am EST and November 3rd at 2am EDT
+        assert_eq!(tz.utc_offset(utc(2024, 3, 10, 6, 59)), -5 * 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 3, 10, 7, 0)), -4 * 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 11, 3, 5, 59)), -4 * 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 11, 3, 6, 0)), -5 * 3_600);
+        // No rules means the US ones
+        assert_eq!(TimeZone::parse("EST5EDT").unwrap(), tz);
+    }
+
+    #[test]
+    fn southern_hemisphere() {
+        let tz = TimeZone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(tz.utc_offset(utc(2024, 1, 15, 0, 0)), 11 * 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 7, 15, 0, 0)), 10 * 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 12, 31, 23, 0)), 11 * 3_600);
+        // Ends 2024-04-07 at 3am AEDT, 16:00 UTC the day before
+        assert_eq!(tz.utc_offset(utc(2024, 4, 6, 15, 59)), 11 * 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 4, 6, 16, 0)), 10 * 3_600);
+        // Starts 2024-10-06 at 2am AEST, 16:00 UTC the day before
+        assert_eq!(tz.utc_offset(utc(2024, 10, 5, 15, 59)), 10 * 3_600);
+        assert_eq!(tz.utc_offset(utc(2024, 10, 5, 16, 0)), 11 * 3_600);
+    }
+
+    #[test]
+    fn julian_and_day_of_year_rules() {
+        // March 1st both with and without a leap day
+        let julian = TimeZone::parse("AAA0BBB,J60/0,J305/0").unwrap();
+        assert!(!julian.is_dst(utc(2024, 2, 29, 23, 59)));
+        assert!(julian.is_dst(utc(2024, 3, 1, 0, 0)));
+        let zero_based = TimeZone::parse("AAA0BBB,59/0,304/0").unwrap();
+        assert!(zero_based.is_dst(utc(2024, 2, 29, 23, 0)));
+        assert!(!zero_based.is_dst(utc(2023, 2, 28, 23, 0)));
+        assert!(zero_based.is_dst(utc(2023, 3, 1, 0, 0)));
+    }
+
+    #[test]
+    fn converts_to_local_time() {
+        let tz = TimeZone::parse("CST6CDT,M3.2.0,M11.1.0").unwrap();
+        let local = tz.to_local(&datetime_from_unix(utc(2024, 8, 16, 23, 30)));
+        assert_eq!(local, datetime_from_unix(utc(2024, 8, 16, 18, 30)));
+        let local = tz.to_local(&datetime_from_unix(utc(2025, 1, 1, 3, 0)));
+        assert_eq!(local, datetime_from_unix(utc(2024, 12, 31, 21, 0)));
+    }
+
+    #[test]
+    fn rejects_bad_strings() {
+        assert_eq!(TimeZone::parse(""), Err(TzError::InvalidName));
+        assert_eq!(TimeZone::parse("C-1"), Err(TzError::InvalidName));
+        assert_eq!(TimeZone::parse("<+01-1"), Err(TzError::InvalidName));
+        assert_eq!(TimeZone::parse("CET"), Err(TzError::InvalidOffset));
+        assert_eq!(TimeZone::parse("CET-25"), Err(TzError::InvalidOffset));
+        assert_eq!(
+            TimeZone::parse("CET-1CEST,M13.5.0,M10.5.0/3"),
+            Err(TzError::InvalidRule)
+        );
+        assert_eq!(
+            TimeZone::parse("CET-1CEST,M3.5.0"),
+            Err(TzError::InvalidRule)
+        );
+        assert_eq!(
+            TimeZone::parse("CET-1CEST,J0,J100"),
+            Err(TzError::InvalidRule)
+        );
+        assert_eq!(
+            TimeZone::parse("CET-1CEST,M3.5.0,M10.5.0/3 extra"),
+            Err(TzError::TrailingCharacters)
+        );
+    }
+}
@@ -0,0 +1,463 @@
+//! Wardriving log: every access point scanned, kept in flash and exported as WiGLE CSV.
+//!
+//! The log is a ring of fixed size slots over a few flash sectors. Each access point is logged
+//! with the strongest signal it has been seen at, a sighting is only appended when it beats the
+//! one already logged for its BSSID. When the ring is full the oldest sector is erased to make
+//! room, dropping the networks logged in it. An index in RAM of where each BSSID's best sighting
+//! is gets rebuilt from the slots by [`WardriveLog::load`], so nothing else has to be saved.
+//!
+//! Each slot is [`SLOT_LEN`] bytes:
+//!
+//! | bytes | field                                                    |
+//! |-------|----------------------------------------------------------|
+//! | 1     | marker `W`                                               |
+//! | 4     | sequence number, little endian                           |
+//! | 6     | BSSID                                                    |
+//! | 2     | RSSI in dBm, little endian                               |
+//! | 1     | channel                                                  |
+//! | 2     | capability bits, little endian                           |
+//! | 4     | first seen in unix seconds, 0 if the clock was not set   |
+//! | 4     | last seen                                                |
+//! | 1     | SSID length                                              |
+//! | 32    | SSID                                                     |
+//! | 3     | 0                                                        |
+//! | 4     | CRC-32 of everything before it                           |
+
+use core::fmt::{self, Write};
+
+use embedded_storage::nor_flash::NorFlash;
+use heapless::Vec;
+
+use crate::bssid::{format_bssid, Bssid};
+use crate::journal::{crc32, CRC_INIT};
+use crate::save::ERASE_SIZE;
+use crate::scan::{ssid_from_bytes, ScanRecord, SecurityFlags};
+use crate::time::datetime_from_unix;
+
+/// Bytes each logged access point takes in flash
+pub const SLOT_LEN: usize = 64;
+/// Slots in each erase sector, the log's size is a multiple of this
+pub const SLOTS_PER_SECTOR: usize = ERASE_SIZE / SLOT_LEN;
+const SLOT_MARKER: u8 = b'W';
+const CRC_OFFSET: usize = SLOT_LEN - 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WardriveError {
+    Flash,
+}
+
+impl fmt::Display for WardriveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Flash => "flash error",
+        })
+    }
+}
+
+/// Where the best sighting of a BSSID is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexEntry {
+    bssid: Bssid,
+    rssi: i16,
+    slot: u16,
+}
+
+/// A log of `SLOTS` access points starting at `offset` in flash, `SLOTS` has to fill whole
+/// sectors and there have to be at least 2 so erasing one leaves something logged
+#[derive(Debug, Clone)]
+pub struct WardriveLog<const SLOTS: usize> {
+    offset: u32,
+    /// Sorted by BSSID
+    index: Vec<IndexEntry, SLOTS>,
+    /// Slot the next sighting is written to
+    next_slot: usize,
+    next_sequence: u32,
+}
+
+impl<const SLOTS: usize> WardriveLog<SLOTS> {
+    /// An empty log, [`WardriveLog::load`] reads what is already in flash
+    pub const fn new(offset: u32) -> Self {
+        assert!(
+            SLOTS.is_multiple_of(SLOTS_PER_SECTOR) && SLOTS >= 2 * SLOTS_PER_SECTOR,
+            "a wardrive log needs at least 2 whole sectors"
+        );
+        Self {
+            offset,
+            index: Vec::new(),
+            next_slot: 0,
+            next_sequence: 0,
+        }
+    }
+
+    /// Bytes of flash the log covers
+    pub const fn flash_len(&self) -> u32 {
+        (SLOTS * SLOT_LEN) as u32
+    }
+
+    /// Access points logged
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Rebuilds the index from the slots in flash and finds where the next write goes. Slots
+    /// that fail their CRC, like one being written when power was lost, are skipped
+    pub fn load<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), WardriveError> {
+        self.index.clear();
+        let mut newest: Option<(u32, usize)> = None;
+        for slot in 0..SLOTS {
+            let Some((sequence, record)) = self.read_slot(flash, slot)? else {
+                continue;
+            };
+            if newest.is_none_or(|(newest, _)| sequence > newest) {
+                newest = Some((sequence, slot));
+            }
+            //Only a stronger sighting is ever appended, so the strongest is the newest
+            self.index_best(record.bssid, record.rssi, slot);
+        }
+
+        let (next_slot, next_sequence) = match newest {
+            Some((sequence, slot)) => ((slot + 1) % SLOTS, sequence.wrapping_add(1)),
+            None => (0, 0),
+        };
+        self.next_slot = next_slot;
+        self.next_sequence = next_sequence;
+        //Skip a half written slot, a new sector is erased before it is used
+        while !self.next_slot.is_multiple_of(SLOTS_PER_SECTOR) && !self.is_blank(flash)? {
+            self.next_slot = (self.next_slot + 1) % SLOTS;
+        }
+        Ok(())
+    }
+
+    /// Logs `record` if its BSSID has not been logged yet or it has a stronger signal than the
+    /// logged one. Returns whether it was written
+    pub fn record<F: NorFlash>(
+        &mut self,
+        flash: &mut F,
+        record: &ScanRecord,
+    ) -> Result<bool, WardriveError> {
+        if let Ok(index) = self.search(&record.bssid) {
+            if self.index[index].rssi >= record.rssi {
+                return Ok(false);
+            }
+        }
+
+        if self.next_slot.is_multiple_of(SLOTS_PER_SECTOR) {
+            //Whatever is in this sector is the oldest in the log
+            let start = self.slot_offset(self.next_slot);
+            flash
+                .erase(start, start + ERASE_SIZE as u32)
+                .map_err(|_| WardriveError::Flash)?;
+            let first = self.next_slot;
+            self.index.retain(|entry| {
+                !(first..first + SLOTS_PER_SECTOR).contains(&(entry.slot as usize))
+            });
+        }
+        let slot = encode_slot(self.next_sequence, record);
+        flash
+            .write(self.slot_offset(self.next_slot), &slot)
+            .map_err(|_| WardriveError::Flash)?;
+        self.index_best(record.bssid, record.rssi, self.next_slot);
+        self.next_slot = (self.next_slot + 1) % SLOTS;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        Ok(true)
+    }
+
+    /// Reads the `index`th logged access point, in BSSID order. `None` past the end or if its
+    /// slot has gone bad since it was written
+    pub fn read<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        index: usize,
+    ) -> Result<Option<ScanRecord>, WardriveError> {
+        let Some(entry) = self.index.get(index) else {
+            return Ok(None);
+        };
+        Ok(self
+            .read_slot(flash, entry.slot as usize)?
+            .map(|(_, record)| record))
+    }
+
+    fn search(&self, bssid: &Bssid) -> Result<usize, usize> {
+        self.index.binary_search_by(|entry| entry.bssid.cmp(bssid))
+    }
+
+    /// Points the index at `slot` for `bssid` unless it already has a stronger sighting
+    fn index_best(&mut self, bssid: Bssid, rssi: i16, slot: usize) {
+        let entry = IndexEntry {
+            bssid,
+            rssi,
+            slot: slot as u16,
+        };
+        match self.search(&bssid) {
+            Ok(index) if self.index[index].rssi < rssi => self.index[index] = entry,
+            Ok(_) => {}
+            //There are never more BSSIDs than slots
+            Err(index) => {
+                let _ = self.index.insert(index, entry);
+            }
+        }
+    }
+
+    fn slot_offset(&self, slot: usize) -> u32 {
+        self.offset + (slot * SLOT_LEN) as u32
+    }
+
+    fn read_slot<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        slot: usize,
+    ) -> Result<Option<(u32, ScanRecord)>, WardriveError> {
+        let mut bytes = [0u8; SLOT_LEN];
+        flash
+            .read(self.slot_offset(slot), &mut bytes)
+            .map_err(|_| WardriveError::Flash)?;
+        Ok(decode_slot(&bytes))
+    }
+
+    /// True if the next slot is still erased
+    fn is_blank<F: NorFlash>(&self, flash: &mut F) -> Result<bool, WardriveError> {
+        let mut bytes = [0u8; SLOT_LEN];
+        flash
+            .read(self.slot_offset(self.next_slot), &mut bytes)
+            .map_err(|_| WardriveError::Flash)?;
+        Ok(bytes.iter().all(|byte| *byte == 0xFF))
+    }
+}
+
+fn encode_slot(sequence: u32, record: &ScanRecord) -> [u8; SLOT_LEN] {
+    let mut slot = [0u8; SLOT_LEN];
+    let ssid = record.ssid.as_bytes();
+    slot[0] = SLOT_MARKER;
+    slot[1..5].copy_from_slice(&sequence.to_le_bytes());
+    slot[5..11].copy_from_slice(&record.bssid);
+    slot[11..13].copy_from_slice(&record.rssi.to_le_bytes());
+    slot[13] = record.channel;
+    slot[14..16].copy_from_slice(&record.security.0.to_le_bytes());
+    slot[16..20].copy_from_slice(&record.first_seen.unwrap_or(0).to_le_bytes());
+    slot[20..24].copy_from_slice(&record.last_seen.unwrap_or(0).to_le_bytes());
+    slot[24] = ssid.len() as u8;
+    slot[25..25 + ssid.len()].copy_from_slice(ssid);
+    let crc = crc32(CRC_INIT, &slot[..CRC_OFFSET]) ^ CRC_INIT;
+    slot[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+    slot
+}
+
+/// The sequence number and sighting in a slot, `None` if it is erased or fails its CRC
+fn decode_slot(slot: &[u8; SLOT_LEN]) -> Option<(u32, ScanRecord)> {
+    let u16_at = |at: usize| u16::from_le_bytes([slot[at], slot[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes(slot[at..at + 4].try_into().unwrap());
+    let time_at = |at: usize| Some(u32_at(at)).filter(|time| *time != 0);
+    if slot[0] != SLOT_MARKER || crc32(CRC_INIT, &slot[..CRC_OFFSET]) ^ CRC_INIT != u32_at(60) {
+        return None;
+    }
+    let ssid_len = (slot[24] as usize).min(32);
+    let record = ScanRecord {
+        ssid: ssid_from_bytes(&slot[25..25 + ssid_len]),
+        bssid: slot[5..11].try_into().unwrap(),
+        rssi: u16_at(11) as i16,
+        channel: slot[13],
+        security: SecurityFlags(u16_at(14)),
+        first_seen: time_at(16),
+        last_seen: time_at(20),
+    };
+    Some((u32_at(1), record))
+}
+
+/// Writes the two header lines of a WiGLE CSV file
+pub fn write_wigle_header(out: &mut impl Write) -> fmt::Result {
+    let version = env!("CARGO_PKG_VERSION");
+    write!(
+        out,
+        "WigleWifi-1.4,appRelease={},model=Badger 2040 W,release={},device=badger2040w,\
+         display=uc8151,board=rp2040,brand=Pimoroni\r\n",
+        version, version
+    )?;
+    out.write_str(
+        "MAC,SSID,AuthMode,FirstSeen,Channel,RSSI,CurrentLatitude,CurrentLongitude,\
+         AltitudeMeters,AccuracyMeters,Type\r\n",
+    )
+}
+
+/// Writes `record` as a row of a WiGLE CSV file.
+///
+/// The badge has no GPS so the location is left at 0,0. The capability bits only say whether a
+/// network needs a password, not which kind, so secured networks are written as WPA2
+pub fn write_wigle_row(record: &ScanRecord, out: &mut impl Write) -> fmt::Result {
+    write!(out, "{},", format_bssid(record.bssid))?;
+    write_csv_field(&record.ssid, out)?;
+    let auth = if record.security.is_protected() {
+        "[WPA2]"
+    } else {
+        ""
+    };
+    let kind = if record.security.is_access_point() {
+        "[ESS]"
+    } else {
+        "[IBSS]"
+    };
+    let seen = datetime_from_unix(record.first_seen.unwrap_or(0) as i64);
+    write!(
+        out,
+        ",{}{},{}-{:02}-{:02} {:02}:{:02}:{:02},{},{},0.0,0.0,0,0,WIFI\r\n",
+        auth,
+        kind,
+        seen.year,
+        seen.month,
+        seen.day,
+        seen.hour,
+        seen.minute,
+        seen.second,
+        record.channel,
+        record.rssi
+    )
+}
+
+/// Quotes `field` if it has a comma, quote or line break in it, doubling any quotes
+fn write_csv_field(field: &str, out: &mut impl Write) -> fmt::Result {
+    if !field.contains([',', '"', '\r', '\n']) {
+        return out.write_str(field);
+    }
+    out.write_char('"')?;
+    for c in field.chars() {
+        if c == '"' {
+            out.write_char('"')?;
+        }
+        out.write_char(c)?;
+    }
+    out.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem_flash::MemFlash;
+    use heapless::String;
+
+    const SLOTS: usize = 2 * SLOTS_PER_SECTOR;
+    type Log = WardriveLog<SLOTS>;
+
+    fn sighting(id: u16, rssi: i16) -> ScanRecord {
+        let [a, b] = id.to_be_bytes();
+        ScanRecord::new(
+            b"RustConf",
+            [0x3c, 0x84, 0x6a, 0, a, b],
+            rssi,
+            6,
+            SecurityFlags(0x11),
+            Some(1_723_801_260),
+        )
+    }
+
+    fn all(log: &Log, flash: &mut MemFlash) -> Vec<ScanRecord, SLOTS> {
+        (0..log.len())
+            .map(|index| log.read(flash, index).unwrap().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_strongest_sighting_of_each_bssid() {
+        let mut flash = MemFlash::new();
+        let mut log = Log::new(0);
+        assert!(log.record(&mut flash, &sighting(2, -80)).unwrap());
+        assert!(log.record(&mut flash, &sighting(1, -70)).unwrap());
+        assert!(!log.record(&mut flash, &sighting(2, -85)).unwrap());
+        assert!(!log.record(&mut flash, &sighting(2, -80)).unwrap());
+        assert!(log.record(&mut flash, &sighting(2, -50)).unwrap());
+
+        let records = all(&log, &mut flash);
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].bssid[5], records[0].rssi), (1, -70));
+        assert_eq!((records[1].bssid[5], records[1].rssi), (2, -50));
+        assert_eq!(records[1].ssid, "RustConf");
+        assert_eq!(records[1].first_seen, Some(1_723_801_260));
+
+        //The same after a reboot, and writing carries on after the last slot
+        let mut reloaded = Log::new(0);
+        reloaded.load(&mut flash).unwrap();
+        assert_eq!(all(&reloaded, &mut flash), records);
+        assert_eq!(reloaded.next_slot, 3);
+        assert!(!reloaded.record(&mut flash, &sighting(1, -71)).unwrap());
+    }
+
+    #[test]
+    fn full_logs_drop_the_oldest_sector() {
+        let mut flash = MemFlash::new();
+        let mut log = Log::new(0);
+        for id in 0..SLOTS as u16 {
+            log.record(&mut flash, &sighting(id, -60)).unwrap();
+        }
+        assert_eq!(log.len(), SLOTS);
+        log.record(&mut flash, &sighting(1000, -60)).unwrap();
+        assert_eq!(log.len(), SLOTS_PER_SECTOR + 1);
+        //The first sector's networks are gone and count as new again
+        assert!(log.record(&mut flash, &sighting(0, -90)).unwrap());
+
+        let mut reloaded = Log::new(0);
+        reloaded.load(&mut flash).unwrap();
+        assert_eq!(reloaded.len(), SLOTS_PER_SECTOR + 2);
+        assert_eq!(all(&reloaded, &mut flash), all(&log, &mut flash));
+        //Nothing past the log was touched
+        let mut after = [0u8; 4];
+        embedded_storage::nor_flash::ReadNorFlash::read(&mut flash, log.flash_len(), &mut after)
+            .unwrap();
+        assert_eq!(after, [0xFF; 4]);
+    }
+
+    #[test]
+    fn power_loss_mid_write_loses_only_that_sighting() {
+        for cut_at in [1, 10, SLOT_LEN / 2, SLOT_LEN - 1] {
+            let mut flash = MemFlash::new();
+            let mut log = Log::new(0);
+            log.record(&mut flash, &sighting(1, -60)).unwrap();
+            //The first write erased the sector, the second is only writes
+            flash.cut_power_after(cut_at);
+            assert!(log.record(&mut flash, &sighting(2, -60)).is_err());
+            flash.restore_power();
+
+            let mut reloaded = Log::new(0);
+            reloaded.load(&mut flash).unwrap();
+            assert_eq!(reloaded.len(), 1, "cut at {}", cut_at);
+            assert!(reloaded.record(&mut flash, &sighting(2, -60)).unwrap());
+            assert!(reloaded.record(&mut flash, &sighting(3, -60)).unwrap());
+            let mut again = Log::new(0);
+            again.load(&mut flash).unwrap();
+            assert_eq!(again.len(), 3, "cut at {}", cut_at);
+        }
+    }
+
+    #[test]
+    fn writes_wigle_csv() {
+        let mut csv: String<512> = String::new();
+        write_wigle_header(&mut csv).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("WigleWifi-1.4,appRelease="));
+        assert_eq!(lines.next().unwrap().split(',').count(), 11);
+
+        let mut row: String<128> = String::new();
+        write_wigle_row(&sighting(1, -61), &mut row).unwrap();
+        assert_eq!(
+            row,
+            "3c:84:6a:00:00:01,RustConf,[WPA2][ESS],2024-08-16 09:41:00,6,-61,0.0,0.0,0,0,WIFI\r\n"
+        );
+
+        let mut open = ScanRecord::new(b"Cafe, \"free\"", [1; 6], -80, 11, SecurityFlags(1), None);
+        row.clear();
+        write_wigle_row(&open, &mut row).unwrap();
+        assert_eq!(
+            row,
+            "01:01:01:01:01:01,\"Cafe, \"\"free\"\"\",[ESS],1970-01-01 00:00:00,11,-80,0.0,0.0,0,0,WIFI\r\n"
+        );
+        open.ssid.clear();
+        row.clear();
+        write_wigle_row(&open, &mut row).unwrap();
+        assert!(row.starts_with("01:01:01:01:01:01,,[ESS],"));
+    }
+}
@@ -0,0 +1,252 @@
+//! The wifi networks the badge knows and which one to join.
+//!
+//! Networks are listed in priority order in the config. When joining, networks seen in a scan are
+//! tried strongest first, then the rest in priority order since hidden networks never show up in
+//! a scan. While none can be joined the badge backs off between tries, see [`Reconnect`].
+
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+/// How many networks can be saved in the config
+pub const MAX_WIFI_NETWORKS: usize = 4;
+/// Seconds to wait after the first failed join, doubled for each failure after
+const RECONNECT_INITIAL_SECONDS: u32 = 10;
+/// Longest wait between joins while none work
+const RECONNECT_MAX_SECONDS: u32 = 10 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiSecurity {
+    /// Open when there is no password, WPA2 when there is
+    Auto,
+    Open,
+    Wpa2,
+    Wpa3,
+}
+
+impl WifiSecurity {
+    /// Reads a `WIFI_SECURITY` config value, `auto`, `open`, `wpa2` or `wpa3`
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.trim() {
+            v if v.eq_ignore_ascii_case("auto") => Some(Self::Auto),
+            v if v.eq_ignore_ascii_case("open") => Some(Self::Open),
+            v if v.eq_ignore_ascii_case("wpa2") => Some(Self::Wpa2),
+            v if v.eq_ignore_ascii_case("wpa3") => Some(Self::Wpa3),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for WifiSecurity {
+    /// The `WIFI_SECURITY` config value, read back by [`WifiSecurity::from_config`]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Open => "open",
+            Self::Wpa2 => "wpa2",
+            Self::Wpa3 => "wpa3",
+        })
+    }
+}
+
+/// A saved network, an empty SSID is an unused slot
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WifiNetwork {
+    pub ssid: String<32>,
+    /// WPA passphrases are at most 63 characters
+    pub password: String<64>,
+    pub security: WifiSecurity,
+}
+
+impl WifiNetwork {
+    pub const fn unused() -> Self {
+        Self {
+            ssid: String::new(),
+            password: String::new(),
+            security: WifiSecurity::Auto,
+        }
+    }
+
+    pub fn is_used(&self) -> bool {
+        !self.ssid.is_empty()
+    }
+
+    /// The security to join with, never [`WifiSecurity::Auto`]
+    pub fn security(&self) -> WifiSecurity {
+        match self.security {
+            WifiSecurity::Auto if self.password.is_empty() => WifiSecurity::Open,
+            WifiSecurity::Auto => WifiSecurity::Wpa2,
+            security => security,
+        }
+    }
+}
+
+/// Tracks the strongest signal seen for each saved network during a scan
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScanRanking {
+    best_rssi: [Option<i16>; MAX_WIFI_NETWORKS],
+}
+
+impl ScanRanking {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a scan result, anything that is not a saved network is ignored
+    pub fn saw(&mut self, networks: &[WifiNetwork], ssid: &[u8], rssi: i16) {
+        for (best, network) in self.best_rssi.iter_mut().zip(networks) {
+            if network.is_used() && network.ssid.as_bytes() == ssid {
+                *best = Some(best.map_or(rssi, |best| best.max(rssi)));
+            }
+        }
+    }
+
+    /// Indexes of the saved networks in the order to try joining them
+    pub fn join_order(&self, networks: &[WifiNetwork]) -> Vec<usize, MAX_WIFI_NETWORKS> {
+        let mut order: Vec<usize, MAX_WIFI_NETWORKS> = networks
+            .iter()
+            .take(MAX_WIFI_NETWORKS)
+            .enumerate()
+            .filter(|(_, network)| network.is_used())
+            .map(|(index, _)| index)
+            .collect();
+        // Seen networks first, strongest first. Ties and networks that were not seen stay in
+        // priority order
+        order.sort_unstable_by_key(|index| match self.best_rssi[*index] {
+            Some(rssi) => (0, -(rssi as i32), *index),
+            None => (1, 0, *index),
+        });
+        order
+    }
+}
+
+/// When to try joining again while the wifi is down. Each try scans and tries every saved network,
+/// so a badge out of range for a while only tries every few minutes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Reconnect {
+    failures: u32,
+}
+
+impl Reconnect {
+    pub const fn new() -> Self {
+        Self { failures: 0 }
+    }
+
+    /// Seconds to wait before the next try
+    pub fn next_attempt_in(&self) -> u32 {
+        if self.failures == 0 {
+            return 0;
+        }
+        RECONNECT_INITIAL_SECONDS
+            .saturating_mul(1u32.checked_shl(self.failures - 1).unwrap_or(u32::MAX))
+            .min(RECONNECT_MAX_SECONDS)
+    }
+
+    pub fn failed(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    /// The next time the link drops is tried again straight away
+    pub fn connected(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(ssid: &str, password: &str) -> WifiNetwork {
+        WifiNetwork {
+            ssid: String::try_from(ssid).unwrap(),
+            password: String::try_from(password).unwrap(),
+            security: WifiSecurity::Auto,
+        }
+    }
+
+    fn networks() -> [WifiNetwork; MAX_WIFI_NETWORKS] {
+        [
+            network("Venue", "rustconf"),
+            network("Hotel", "room1234"),
+            WifiNetwork::unused(),
+            network("Ferris's iPhone", "crab"),
+        ]
+    }
+
+    #[test]
+    fn auto_security_depends_on_the_password() {
+        assert_eq!(network("Cafe", "").security(), WifiSecurity::Open);
+        assert_eq!(network("Venue", "rustconf").security(), WifiSecurity::Wpa2);
+        let wpa3 = WifiNetwork {
+            security: WifiSecurity::Wpa3,
+            ..network("Venue", "rustconf")
+        };
+        assert_eq!(wpa3.security(), WifiSecurity::Wpa3);
+    }
+
+    #[test]
+    fn nothing_seen_keeps_priority_order() {
+        let ranking = ScanRanking::new();
+        assert_eq!(ranking.join_order(&networks()).as_slice(), &[0, 1, 3]);
+    }
+
+    #[test]
+    fn strongest_seen_network_goes_first() {
+        let networks = networks();
+        let mut ranking = ScanRanking::new();
+        ranking.saw(&networks, b"Venue", -80);
+        ranking.saw(&networks, b"Coffee Shop", -30);
+        ranking.saw(&networks, b"Ferris's iPhone", -45);
+        ranking.saw(&networks, b"Venue", -70);
+        assert_eq!(ranking.join_order(&networks).as_slice(), &[3, 0, 1]);
+    }
+
+    #[test]
+    fn equal_signals_keep_priority_order() {
+        let networks = networks();
+        let mut ranking = ScanRanking::new();
+        ranking.saw(&networks, b"Hotel", -60);
+        ranking.saw(&networks, b"Venue", -60);
+        assert_eq!(ranking.join_order(&networks).as_slice(), &[0, 1, 3]);
+    }
+
+    #[test]
+    fn unused_slots_are_never_seen() {
+        let networks = networks();
+        let mut ranking = ScanRanking::new();
+        ranking.saw(&networks, b"", -20);
+        assert_eq!(ranking.join_order(&networks).as_slice(), &[0, 1, 3]);
+    }
+
+    #[test]
+    fn security_config_round_trips() {
+        for security in [
+            WifiSecurity::Auto,
+            WifiSecurity::Open,
+            WifiSecurity::Wpa2,
+            WifiSecurity::Wpa3,
+        ] {
+            let written = crate::helpers::easy_format::<8>(format_args!("{}", security));
+            assert_eq!(WifiSecurity::from_config(&written), Some(security));
+        }
+        assert_eq!(WifiSecurity::from_config("WEP"), None);
+    }
+
+    #[test]
+    fn reconnects_back_off_until_joined() {
+        let mut reconnect = Reconnect::new();
+        assert_eq!(reconnect.next_attempt_in(), 0);
+        let mut waits = [0; 8];
+        for wait in waits.iter_mut() {
+            reconnect.failed();
+            *wait = reconnect.next_attempt_in();
+        }
+        assert_eq!(waits, [10, 20, 40, 80, 160, 320, 600, 600]);
+        for _ in 0..100 {
+            reconnect.failed();
+        }
+        assert_eq!(reconnect.next_attempt_in(), 600);
+        reconnect.connected();
+        assert_eq!(reconnect.next_attempt_in(), 0);
+    }
+}
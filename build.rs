@@ -7,11 +7,18 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! It also parses `.env` into the badge's default config, so a typo or a
+//! missing setting fails the build instead of panicking on the badge.
 
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use badge_core::config::{Config, ConfigKey};
+use badge_core::env::parse_env;
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
@@ -33,4 +40,71 @@ fn main() {
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
     println!("cargo:rustc-link-arg-bins=-Tlink-rp.x");
     println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+
+    generate_env_config(out);
+}
+
+/// Prints why the build failed and stops it
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+/// Writes the settings from `.env` to `env_config.rs` for `src/env.rs` to include
+fn generate_env_config(out: &Path) {
+    println!("cargo:rerun-if-changed=.env");
+    let data = fs::read_to_string(".env").unwrap_or_else(|_| {
+        fail(".env not found, copy .env.save to .env and fill in your own settings")
+    });
+
+    let mut values: Vec<(String, String)> = Vec::new();
+    for entry in parse_env(&data) {
+        let entry = entry.unwrap_or_else(|e| fail(&format!(".env {}", e)));
+        let value = entry.value.to_string();
+        match values.iter_mut().find(|(key, _)| key == entry.key) {
+            Some((_, existing)) => {
+                println!(
+                    "cargo:warning=.env sets {} more than once, using line {}",
+                    entry.key, entry.line
+                );
+                *existing = value;
+            }
+            None => values.push((entry.key.to_string(), value)),
+        }
+    }
+    //Settings are looked up by their exact name below, so a name in the wrong case would be
+    //silently left out
+    for (key, _) in &values {
+        if ConfigKey::ALL.iter().any(|known| known.name() == key) {
+            continue;
+        }
+        match ConfigKey::from_name(key) {
+            Some(known) => fail(&format!(
+                ".env sets {}, setting names are case sensitive, did you mean {}?",
+                key,
+                known.name()
+            )),
+            None => println!(
+                "cargo:warning=.env sets {} which the badge does not use",
+                key
+            ),
+        }
+    }
+
+    //Check the settings the same way the badge will
+    if let Err(e) = Config::from_env(|key| {
+        values
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }) {
+        fail(&format!("{} in .env, see .env.save", e));
+    }
+
+    let mut generated = String::from("pub const ENV_VALUES: &[(&str, &str)] = &[\n");
+    for (key, value) in &values {
+        generated.push_str(&format!("    ({:?}, {:?}),\n", key, value));
+    }
+    generated.push_str("];\n");
+    fs::write(out.join("env_config.rs"), generated).unwrap();
 }
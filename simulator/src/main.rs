@@ -0,0 +1,214 @@
+//! Renders every badge screen to 296x128 PNGs without a badge or a window.
+//!
+//! `simulator [output dir]` writes the PNGs (defaults to `simulator_output`).
+//! `simulator --check <golden dir>` renders the screens and compares them to previously rendered
+//! PNGs, exiting with an error if any differ so CI can catch layout changes.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use badge_core::badge_display::display_image::DisplayImage;
+use badge_core::badge_display::framebuffer::Framebuffer;
+use badge_core::badge_display::wifi_list::WifiListView;
+use badge_core::badge_display::{draw_screen, DisplayState, Screen, HEIGHT, WIDTH};
+use badge_core::boot::{BootError, BootStatus};
+use badge_core::scan::{ScanHistory, ScanRecord, SecurityFlags, SCAN_HISTORY_LEN};
+use badge_core::stats::{DiscoveryLog, WifiStats};
+use badge_core::time::datetime_from_unix;
+use badge_core::time::format::ClockFormat;
+use badge_core::time::tz::TimeZone;
+use embedded_graphics::pixelcolor::BinaryColor;
+
+/// Renders each screen along with the file name it is saved as
+fn render_screens() -> Vec<(std::string::String, Framebuffer)> {
+    //A morning at a conference: busy channels 1, 6 and 11 and a burst of new networks at 9
+    let mut history = ScanHistory::<SCAN_HISTORY_LEN>::new();
+    let networks = [
+        ("RustConf", -48, 6, 0x11),
+        ("Hotel Guest", -61, 1, 0x01),
+        ("Ferris's iPhone", -70, 11, 0x11),
+        ("xfinitywifi", -83, 6, 0x01),
+        ("", -77, 11, 0x11),
+        ("DIRECT-printer", -88, 3, 0x11),
+        ("Speaker Room", -66, 6, 0x11),
+    ];
+    let now: u32 = 1_723_801_260;
+    for (index, (ssid, rssi, channel, capability)) in networks.into_iter().enumerate() {
+        history.record(ScanRecord::new(
+            ssid.as_bytes(),
+            [0x3c, 0x84, 0x6a, 0, 0, index as u8],
+            rssi,
+            channel,
+            SecurityFlags(capability),
+            Some(now - 3_000 + index as u32 * 300),
+        ));
+    }
+    //Seen again a few minutes later
+    history.record(ScanRecord::new(
+        b"RustConf",
+        [0x3c, 0x84, 0x6a, 0, 0, 0],
+        -52,
+        6,
+        SecurityFlags(0x11),
+        Some(now),
+    ));
+    let mut log = DiscoveryLog::new();
+    for (hours_ago, count) in [(20, 3), (9, 1), (3, 12), (2, 30), (1, 8), (0, 4)] {
+        log.counted(count, Some(now as i64 - hours_ago * 60 * 60));
+    }
+    let stats = WifiStats::new(history.iter(), 1337, &log, Some(now as i64));
+    //Local time is UTC so the sample times read the same everywhere
+    let time_zone = TimeZone::utc();
+
+    let images: Vec<DisplayImage> = DisplayImage::bundled().collect();
+    let mut state = DisplayState {
+        name_and_details: "Ferris\nRustacean",
+        temp: 72,
+        humidity: 40,
+        wifi_count: 1337,
+        time: "09:41 AM",
+        time_len: 8,
+        image: images.first(),
+        status: "",
+        history: &history,
+        list: WifiListView::new(),
+        time_zone: &time_zone,
+        stats: &stats,
+    };
+
+    let mut screens = Vec::new();
+    for (index, image) in images.iter().enumerate() {
+        state.image = Some(image);
+        let mut display = Framebuffer::new();
+        draw_screen(&mut display, Screen::Badge, &state).unwrap();
+        screens.push((format!("badge_image_{}.png", index), display));
+    }
+
+    //The longest clock format, drawn in a smaller font to stay left of the image
+    let long_format = ClockFormat::from_config("12h,seconds,weekday,ymd").unwrap();
+    let long_time = long_format.format(&datetime_from_unix(1_723_851_005));
+    let long_clock_state = DisplayState {
+        time: &long_time,
+        time_len: long_format.text_len(),
+        image: images.first(),
+        ..state
+    };
+    let mut display = Framebuffer::new();
+    draw_screen(&mut display, Screen::Badge, &long_clock_state).unwrap();
+    screens.push(("badge_long_clock.png".into(), display));
+
+    //What the badge shows when it boots without wifi
+    let mut boot_status = BootStatus::new();
+    boot_status.failed(BootError::WifiJoin);
+    boot_status.failed(BootError::TimeNoNetwork);
+    let status = boot_status.status_line();
+    let status_state = DisplayState {
+        time: "No Wifi",
+        status: &status,
+        ..state
+    };
+    let mut display = Framebuffer::new();
+    draw_screen(&mut display, Screen::Badge, &status_state).unwrap();
+    screens.push(("badge_status.png".into(), display));
+
+    let mut display = Framebuffer::new();
+    draw_screen(&mut display, Screen::WifiList, &state).unwrap();
+    screens.push(("wifi_list.png".into(), display));
+
+    //The cursor on the second page of the list, and the details of the network it is on
+    let mut list = WifiListView::new();
+    for _ in 0..5 {
+        list.down(history.len());
+    }
+    let list_state = DisplayState { list, ..state };
+    let mut display = Framebuffer::new();
+    draw_screen(&mut display, Screen::WifiList, &list_state).unwrap();
+    screens.push(("wifi_list_page_2.png".into(), display));
+    let mut display = Framebuffer::new();
+    draw_screen(&mut display, Screen::WifiDetail, &list_state).unwrap();
+    screens.push(("wifi_detail.png".into(), display));
+
+    let mut display = Framebuffer::new();
+    draw_screen(&mut display, Screen::WifiStats, &state).unwrap();
+    screens.push(("wifi_stats.png".into(), display));
+    screens
+}
+
+/// 8 bit grayscale pixels, `On` is white and `Off` is black like on the badge
+fn grayscale(display: &Framebuffer) -> Vec<u8> {
+    display
+        .rows()
+        .flatten()
+        .map(|color| match color {
+            BinaryColor::On => 0xFF,
+            BinaryColor::Off => 0x00,
+        })
+        .collect()
+}
+
+fn write_png(path: &Path, display: &Framebuffer) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&grayscale(display))?;
+    Ok(())
+}
+
+fn read_png(path: &Path) -> Result<Vec<u8>, png::DecodingError> {
+    let decoder = png::Decoder::new(File::open(path)?);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+    Ok(buf)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<std::string::String> = std::env::args().skip(1).collect();
+    let screens = render_screens();
+
+    if args.first().map(|x| x.as_str()) == Some("--check") {
+        let Some(golden_dir) = args.get(1).map(PathBuf::from) else {
+            eprintln!("Usage: simulator --check <golden dir>");
+            return ExitCode::FAILURE;
+        };
+        let mut all_match = true;
+        for (name, display) in &screens {
+            match read_png(&golden_dir.join(name)) {
+                Ok(golden) if golden == grayscale(display) => println!("{name}: ok"),
+                Ok(_) => {
+                    println!("{name}: differs from golden image");
+                    all_match = false;
+                }
+                Err(e) => {
+                    println!("{name}: could not read golden image: {e}");
+                    all_match = false;
+                }
+            }
+        }
+        return if all_match {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    let output_dir = PathBuf::from(args.first().map_or("simulator_output", |x| x.as_str()));
+    if let Err(e) = std::fs::create_dir_all(&output_dir) {
+        eprintln!("Could not create {}: {e}", output_dir.display());
+        return ExitCode::FAILURE;
+    }
+    for (name, display) in &screens {
+        let path = output_dir.join(name);
+        if let Err(e) = write_png(&path, display) {
+            eprintln!("Could not write {}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+        println!("Wrote {}", path.display());
+    }
+    ExitCode::SUCCESS
+}
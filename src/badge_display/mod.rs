@@ -1,63 +1,74 @@
-pub mod display_image;
-
-use core::{
-    cell::RefCell,
-    sync::atomic::{AtomicBool, AtomicU32, AtomicU8},
-};
+use badge_core::badge_display::compositor::Compositor;
+use badge_core::badge_display::event::{DisplayEvent, Readings};
+use badge_core::badge_display::page::{Navigator, Pages, Redraw, Refresh};
+use badge_core::badge_display::refresh::{Lut, RefreshPolicy, Update, UpdateKind};
+use badge_core::badge_display::{clear_screen, DisplayState, Screen};
+use badge_core::boot::{BootError, BootStage, BootStatus};
+use badge_core::helpers::easy_format;
+use badge_core::stats::WifiStats;
+use core::cell::RefCell;
 use defmt::*;
-use display_image::get_current_image;
 use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
+use embassy_futures::select::{select, Either};
 use embassy_rp::gpio;
 use embassy_rp::gpio::Input;
 use embassy_sync::blocking_mutex::{self, raw::CriticalSectionRawMutex};
-use embassy_time::{Delay, Duration, Timer};
-use embedded_graphics::{
-    image::Image,
-    mono_font::{ascii::*, MonoTextStyle},
-    pixelcolor::BinaryColor,
-    prelude::*,
-    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
-    text::Text,
-};
-use embedded_text::{
-    alignment::HorizontalAlignment,
-    style::{HeightMode, TextBoxStyleBuilder},
-    TextBox,
-};
+use embassy_sync::channel::Channel;
+use embassy_time::{Delay, Duration, Instant, Timer};
 use gpio::Output;
-use heapless::{String, Vec};
-use tinybmp::Bmp;
+use uc8151::asynch::Uc8151;
 use uc8151::LUT;
-use uc8151::WIDTH;
-use uc8151::{asynch::Uc8151, HEIGHT};
 use {defmt_rtt as _, panic_probe as _};
 
-use crate::{env::env_value, helpers::easy_format, Spi0Bus};
-
-pub type RecentWifiNetworksVec = Vec<String<32>, 4>;
+use crate::config::config;
+use crate::images::read_image;
+use crate::scan::{DISCOVERY_LOG, SCAN_HISTORY};
+use crate::time_sync::local_time;
+use crate::{FlashBus, Spi0Bus};
 
 //Display state
-pub static SCREEN_TO_SHOW: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<Screen>> =
-    blocking_mutex::Mutex::new(RefCell::new(Screen::Badge));
-pub static RECENT_WIFI_NETWORKS: blocking_mutex::Mutex<
-    CriticalSectionRawMutex,
-    RefCell<RecentWifiNetworksVec>,
-> = blocking_mutex::Mutex::new(RefCell::new(RecentWifiNetworksVec::new()));
+/// Which page is shown and the wifi list's cursor, moved by the buttons
+pub static NAVIGATOR: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<Navigator>> =
+    blocking_mutex::Mutex::new(RefCell::new(Navigator::new()));
+/// Everything the display is told, it sleeps until one of these or a timed refresh
+static DISPLAY_EVENTS: Channel<CriticalSectionRawMutex, DisplayEvent, 16> = Channel::new();
+/// How long without a button press before the display counts as left alone, see
+/// [`RefreshPolicy::quiet`]
+const QUIET_AFTER: Duration = Duration::from_secs(5 * 60);
+pub static BOOT_STATUS: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<BootStatus>> =
+    blocking_mutex::Mutex::new(RefCell::new(BootStatus::new()));
+
+/// Tells the display about `event`, without waiting so it can be sent from anywhere. The display
+/// takes every event waiting each time it wakes, so the queue only fills if it is stuck
+pub fn publish(event: DisplayEvent) {
+    if DISPLAY_EVENTS.try_send(event).is_err() {
+        warn!("Display busy, dropped an event");
+    }
+}
 
-pub static FORCE_SCREEN_REFRESH: AtomicBool = AtomicBool::new(true);
-pub static DISPLAY_CHANGED: AtomicBool = AtomicBool::new(false);
-pub static CURRENT_IMAGE: AtomicU8 = AtomicU8::new(0);
-pub static CHANGE_IMAGE: AtomicBool = AtomicBool::new(true);
-pub static WIFI_COUNT: AtomicU32 = AtomicU32::new(0);
-pub static RTC_TIME_STRING: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<String<8>>> =
-    blocking_mutex::Mutex::new(RefCell::new(String::<8>::new()));
-pub static TEMP: AtomicU8 = AtomicU8::new(0);
-pub static HUMIDITY: AtomicU8 = AtomicU8::new(0);
+/// Records how a boot stage went, the status line is redrawn if that changes what it shows
+pub fn report_boot_stage(stage: BootStage, result: Result<(), BootError>) {
+    if let Err(e) = result {
+        warn!("{:?} failed: {:?}", stage, e);
+    }
+    update_boot_status(|status| status.report(stage, result));
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
-pub enum Screen {
-    Badge,
-    WifiList,
+/// Takes `error` off the status line if it is still shown
+pub fn clear_boot_error(error: BootError) {
+    update_boot_status(|status| status.clear(error));
+}
+
+fn update_boot_status(update: impl FnOnce(&mut BootStatus)) {
+    let changed = BOOT_STATUS.lock(|status| {
+        let mut status = status.borrow_mut();
+        let before = *status;
+        update(&mut status);
+        *status != before
+    });
+    if changed {
+        publish(DisplayEvent::StatusChanged);
+    }
 }
 
 #[embassy_executor::task]
@@ -67,6 +78,7 @@ pub async fn run_the_display(
     dc: Output<'static>,
     busy: Input<'static>,
     reset: Output<'static>,
+    flash: &'static FlashBus,
 ) {
     let spi_dev = SpiDevice::new(&spi_bus, cs);
 
@@ -75,245 +87,143 @@ pub async fn run_the_display(
     display.reset().await;
 
     // Initialise display with speed
-    let _ = display.setup(LUT::Medium).await;
-
-    // Note we're setting the Text color to `Off`. The driver is set up to treat Off as Black so that BMPs work as expected.
-    let character_style = MonoTextStyle::new(&FONT_9X18_BOLD, BinaryColor::Off);
-    let textbox_style = TextBoxStyleBuilder::new()
-        .height_mode(HeightMode::FitToText)
-        .alignment(HorizontalAlignment::Left)
-        .paragraph_spacing(6)
-        .build();
-
-    // Bounding box for our text. Fill it with the opposite color so we can read the text.
-    let name_and_detail_bounds = Rectangle::new(Point::new(0, 40), Size::new(WIDTH - 75, 0));
-    name_and_detail_bounds
-        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
-        .draw(&mut display)
-        .unwrap();
+    let mut lut = Lut::Medium;
+    let _ = display.setup(uc8151_lut(lut)).await;
 
     // Create the text box and apply styling options.
-    let display_text = easy_format::<29>(format_args!(
-        "{}\n{}",
-        env_value("NAME"),
-        env_value("DETAILS")
-    ));
-
-    let name_and_detail_box = TextBox::with_textbox_style(
-        &display_text,
-        name_and_detail_bounds,
-        character_style,
-        textbox_style,
-    );
+    let config = config();
+    let display_text = easy_format::<97>(format_args!("{}\n{}", config.name, config.details));
+    //The time box is sized for the longest time the clock format shows
+    let time_len = config.clock_format.text_len();
+    let time_zone = config.time_zone();
+    let mut policy = RefreshPolicy::new(config.full_refresh_after);
 
     // let _ = display.update().await;
 
-    //Each cycle is half a second
-    let cycle: Duration = Duration::from_millis(500);
-
-    //New start every 120 cycles or 60 seconds
-    let cycles_to_clear_at: i32 = 120;
-    let mut cycles_since_last_clear = 0;
-    let mut current_screen = Screen::Badge;
+    //Each page remembers what it last drew, so it only redraws what changed
+    let mut pages = Pages::default();
+    //Whatever was sent before the display started, it all gets drawn the first time
+    let mut readings = Readings::default();
+    let mut redraw = Redraw::full();
+    //When the shown page's timed refresh is due, `None` if it has none
+    let mut refresh_at: Option<Instant> = None;
+    //When the display has been left alone long enough to clean up its ghosting
+    let mut quiet_at: Option<Instant> = None;
+    //The picked image, read from flash when another is picked instead of every draw
+    let mut image = None;
     loop {
-        let mut force_screen_refresh =
-            FORCE_SCREEN_REFRESH.load(core::sync::atomic::Ordering::Relaxed);
-        //Timed based display events
-        if DISPLAY_CHANGED.load(core::sync::atomic::Ordering::Relaxed) {
-            let clear_rectangle = Rectangle::new(Point::new(0, 0), Size::new(WIDTH, HEIGHT));
-            clear_rectangle
-                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
-                .draw(&mut display)
-                .unwrap();
-            let _ = display.update().await;
-            DISPLAY_CHANGED.store(false, core::sync::atomic::Ordering::Relaxed);
-            force_screen_refresh = true;
+        while let Ok(event) = DISPLAY_EVENTS.try_receive() {
+            readings.apply(event, &mut redraw);
+        }
+        if redraw.image_changed || image.is_none() {
+            image = read_image(flash, readings.image).await;
+        }
+        //Only the buffer, the full refresh for the new page shows it
+        if redraw.clear {
+            clear_screen(&mut display).unwrap();
         }
 
-        SCREEN_TO_SHOW.lock(|x| current_screen = *x.borrow());
-        // info!("Current Screen: {:?}", current_screen);
-        if current_screen == Screen::Badge {
-            if force_screen_refresh {
-                // Draw the text box.
-                name_and_detail_box.draw(&mut display).unwrap();
-            }
-
-            //Updates the top bar
-            //Runs every 60 cycles/30 seconds and first run
-            if cycles_since_last_clear % 60 == 0 || force_screen_refresh {
-                let count = WIFI_COUNT.load(core::sync::atomic::Ordering::Relaxed);
-                info!("Wifi count: {}", count);
-                let temp = TEMP.load(core::sync::atomic::Ordering::Relaxed);
-                let humidity = HUMIDITY.load(core::sync::atomic::Ordering::Relaxed);
-                let top_text: String<64> = easy_format::<64>(format_args!(
-                    "{}F {}% Wifi found: {}",
-                    temp, humidity, count
-                ));
-                let top_bounds = Rectangle::new(Point::new(0, 0), Size::new(WIDTH, 24));
-                top_bounds
-                    .into_styled(
-                        PrimitiveStyleBuilder::default()
-                            .stroke_color(BinaryColor::Off)
-                            .fill_color(BinaryColor::On)
-                            .stroke_width(1)
-                            .build(),
-                    )
-                    .draw(&mut display)
-                    .unwrap();
-
-                Text::new(top_text.as_str(), Point::new(8, 16), character_style)
-                    .draw(&mut display)
-                    .unwrap();
-
-                // Draw the text box.
-                let result = display.partial_update(top_bounds.try_into().unwrap()).await;
-                match result {
-                    Ok(_) => {}
-                    Err(_) => {
-                        info!("Error updating display");
-                    }
-                }
-            }
-
-            //Runs every 120 cycles/60 seconds and first run
-            if cycles_since_last_clear == 0 || force_screen_refresh {
-                let mut time_text: String<8> = String::<8>::new();
-
-                let time_box_rectangle_location = Point::new(0, 96);
-                RTC_TIME_STRING.lock(|x| {
-                    time_text.push_str(x.borrow().as_str()).unwrap();
-                });
-
-                //The bounds of the box for time and refresh area
-                let time_bounds = Rectangle::new(time_box_rectangle_location, Size::new(88, 24));
-                time_bounds
-                    .into_styled(
-                        PrimitiveStyleBuilder::default()
-                            .stroke_color(BinaryColor::Off)
-                            .fill_color(BinaryColor::On)
-                            .stroke_width(1)
-                            .build(),
-                    )
-                    .draw(&mut display)
-                    .unwrap();
-
-                //Adding a y offset to the box location to fit inside the box
-                Text::new(
-                    time_text.as_str(),
-                    (
-                        time_box_rectangle_location.x + 8,
-                        time_box_rectangle_location.y + 16,
-                    )
-                        .into(),
-                    character_style,
+        let navigator = NAVIGATOR.lock(|x| *x.borrow());
+        let status = BOOT_STATUS.lock(|x| x.borrow().status_line());
+        //Copied so the scan loop is not held up while drawing
+        let history = SCAN_HISTORY.lock(|x| x.borrow().clone());
+        let stats = if navigator.screen() == Screen::WifiStats {
+            let local_time = local_time();
+            DISCOVERY_LOG.lock(|log| {
+                WifiStats::new(
+                    history.iter(),
+                    readings.wifi_count,
+                    &log.borrow(),
+                    local_time,
                 )
-                .draw(&mut display)
-                .unwrap();
-
-                let result = display
-                    .partial_update(time_bounds.try_into().unwrap())
-                    .await;
-                match result {
-                    Ok(_) => {}
-                    Err(_) => {
-                        info!("Error updating display");
-                    }
-                }
+            })
+        } else {
+            WifiStats::default()
+        };
+        let state = DisplayState {
+            name_and_details: &display_text,
+            temp: readings.temp,
+            humidity: readings.humidity,
+            wifi_count: readings.wifi_count,
+            time: &readings.time,
+            time_len,
+            image: image.as_ref(),
+            status: &status,
+            history: &history,
+            list: navigator.list(),
+            time_zone: &time_zone,
+            stats: &stats,
+        };
+
+        //Everything drawn for the events is refreshed in one go
+        let mut compositor = Compositor::new();
+        pages
+            .draw(
+                navigator.screen(),
+                &mut display,
+                &state,
+                &redraw,
+                &mut compositor,
+            )
+            .unwrap();
+        let kind = UpdateKind::of(navigator.screen(), &redraw);
+        if let Some(update) = policy.plan(compositor.region(), kind, redraw.clear) {
+            //Setting the display up again only loads the waveform, what it shows is kept
+            if update.lut() != lut {
+                lut = update.lut();
+                let _ = display.setup(uc8151_lut(lut)).await;
             }
-
-            //Manually triggered display events
-
-            if CHANGE_IMAGE.load(core::sync::atomic::Ordering::Relaxed) || force_screen_refresh {
-                let current_image = get_current_image();
-                let tga: Bmp<BinaryColor> = Bmp::from_slice(&current_image.image()).unwrap();
-                let image = Image::new(&tga, current_image.image_location());
-                //clear image location by writing a white rectangle over previous image location
-                let clear_rectangle = Rectangle::new(
-                    current_image.previous().image_location(),
-                    Size::new(157, 101),
-                );
-                clear_rectangle
-                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
-                    .draw(&mut display)
-                    .unwrap();
-
-                let _ = image.draw(&mut display);
-                //TODO need to look up the reginal area display
-                let _ = display.update().await;
-                CHANGE_IMAGE.store(false, core::sync::atomic::Ordering::Relaxed);
+            let result = match update {
+                Update::Full => display.update().await,
+                Update::Partial { region, .. } => {
+                    display.partial_update(region.try_into().unwrap()).await
+                }
+            };
+            if result.is_err() {
+                info!("Error updating display");
             }
-        } else {
-            if force_screen_refresh {
-                let top_bounds = Rectangle::new(Point::new(0, 0), Size::new(WIDTH, 24));
-                top_bounds
-                    .into_styled(
-                        PrimitiveStyleBuilder::default()
-                            .stroke_color(BinaryColor::Off)
-                            .fill_color(BinaryColor::On)
-                            .stroke_width(1)
-                            .build(),
-                    )
-                    .draw(&mut display)
-                    .unwrap();
-
-                let top_text: String<64> = easy_format::<64>(format_args!(
-                    "Wifi found: {}",
-                    WIFI_COUNT.load(core::sync::atomic::Ordering::Relaxed)
-                ));
-
-                Text::new(top_text.as_str(), Point::new(8, 16), character_style)
-                    .draw(&mut display)
-                    .unwrap();
+        }
+        if redraw.input {
+            quiet_at = Some(Instant::now() + QUIET_AFTER);
+        }
 
-                let result = display.partial_update(top_bounds.try_into().unwrap()).await;
-                match result {
-                    Ok(_) => {}
-                    Err(_) => {
-                        info!("Error updating display");
-                    }
+        //A new page or a timed refresh starts the wait for the next one over
+        if redraw.clear || redraw.timed || refresh_at.is_none() {
+            refresh_at = match pages.refresh(navigator.screen()) {
+                Refresh::OnChange => None,
+                Refresh::Every(seconds) => {
+                    Some(Instant::now() + Duration::from_secs(seconds as u64))
                 }
-
-                //write the wifi list
-                let mut y_offset = 24;
-                let wifi_list = RECENT_WIFI_NETWORKS.lock(|x| x.borrow().clone());
-                for wifi in wifi_list.iter() {
-                    // let wifi_text: String<64> = easy_format::<64>(format_args!("{}", wifi));
-                    let wifi_bounds = Rectangle::new(Point::new(0, y_offset), Size::new(WIDTH, 24));
-                    wifi_bounds
-                        .into_styled(
-                            PrimitiveStyleBuilder::default()
-                                .stroke_color(BinaryColor::Off)
-                                .fill_color(BinaryColor::On)
-                                .stroke_width(1)
-                                .build(),
-                        )
-                        .draw(&mut display)
-                        .unwrap();
-
-                    Text::new(wifi.trim(), Point::new(8, y_offset + 16), character_style)
-                        .draw(&mut display)
-                        .unwrap();
-
-                    let result = display
-                        .partial_update(wifi_bounds.try_into().unwrap())
-                        .await;
-                    match result {
-                        Ok(_) => {}
-                        Err(_) => {
-                            info!("Error updating display");
-                        }
-                    }
-                    y_offset += 24;
+            };
+        }
+        redraw = Redraw::default();
+        let wake_at = [refresh_at, quiet_at].into_iter().flatten().min();
+        let event = match wake_at {
+            Some(at) => match select(DISPLAY_EVENTS.receive(), Timer::at(at)).await {
+                Either::First(event) => Some(event),
+                Either::Second(_) => None,
+            },
+            None => Some(DISPLAY_EVENTS.receive().await),
+        };
+        match event {
+            Some(event) => readings.apply(event, &mut redraw),
+            None => {
+                let now = Instant::now();
+                redraw.timed = refresh_at.is_some_and(|at| at <= now);
+                //Once per quiet stretch, the next button press starts another
+                if quiet_at.is_some_and(|at| at <= now) {
+                    quiet_at = None;
+                    policy.quiet();
                 }
             }
         }
+    }
+}
 
-        cycles_since_last_clear += 1;
-        if cycles_since_last_clear >= cycles_to_clear_at {
-            cycles_since_last_clear = 0;
-        }
-        FORCE_SCREEN_REFRESH.store(false, core::sync::atomic::Ordering::Relaxed);
-        // info!("Display Cycle: {}", cycles_since_last_clear);
-        Timer::after(cycle).await;
+fn uc8151_lut(lut: Lut) -> LUT {
+    match lut {
+        Lut::Medium => LUT::Medium,
+        Lut::Fast => LUT::Fast,
+        Lut::Ultrafast => LUT::Ultrafast,
     }
 }
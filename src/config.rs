@@ -0,0 +1,281 @@
+use core::cell::RefCell;
+use core::fmt::Write;
+use core::sync::atomic::AtomicBool;
+
+use badge_core::config::{Config, ConfigKey};
+use badge_core::console::{Command, ConsoleError};
+use badge_core::image_store::ImageUpload;
+use badge_core::wardrive::{write_wigle_header, write_wigle_row};
+use defmt::*;
+use embassy_futures::join::join;
+use embassy_rp::bind_interrupts;
+use embassy_rp::peripherals::USB;
+use embassy_rp::usb::{Driver, InterruptHandler};
+use embassy_sync::blocking_mutex::{self, raw::CriticalSectionRawMutex};
+use embassy_time::{Duration, Timer};
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::Builder;
+use heapless::{String, Vec};
+
+use crate::env::default_config;
+use crate::images::{delete_image, error_reply, list_images, save_image};
+use crate::scan::WARDRIVE_LOG;
+use crate::FlashBus;
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => InterruptHandler<USB>;
+});
+
+/// The badge's settings, loaded from flash by main before any task starts
+pub static CONFIG: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<Option<Config>>> =
+    blocking_mutex::Mutex::new(RefCell::new(None));
+/// Set by the console when the config should be written to flash, main owns the flash
+pub static CONFIG_CHANGED: AtomicBool = AtomicBool::new(false);
+/// Set by the console whenever a setting is changed, the clock task picks up a new time zone or
+/// clock format from it without a reboot
+pub static CONFIG_EDITED: AtomicBool = AtomicBool::new(false);
+
+const MAX_PACKET_SIZE: u16 = 64;
+const HELP: &str = "Commands:\r
+  list              show every setting\r
+  get KEY           show one setting\r
+  set KEY VALUE     change a setting\r
+  save              keep the changes in flash\r
+  reset             go back to the settings from .env\r
+  reboot            restart to use the saved settings\r
+  wigle             print the wardriving log as WiGLE CSV\r
+  images            list the images C steps through\r
+  upload NAME W H X Y\r
+                    add or replace an image W by H pixels at X,Y, then\r
+                    send each row as a line of hex, 1 bits are white\r
+  delete NAME       take an image out of flash\r
+";
+
+/// A copy of the current settings
+pub fn config() -> Config {
+    CONFIG.lock(|config| config.borrow().clone().unwrap())
+}
+
+/// Serial console over the USB port for changing the config without reflashing
+#[embassy_executor::task]
+pub async fn run_the_usb_console(usb: USB, flash: &'static FlashBus) {
+    let driver = Driver::new(usb, Irqs);
+
+    let mut usb_config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    usb_config.manufacturer = Some("Pimoroni");
+    usb_config.product = Some("Badger 2040 W badge");
+    usb_config.serial_number = None;
+    usb_config.max_power = 100;
+    usb_config.max_packet_size_0 = MAX_PACKET_SIZE as u8;
+    // Windows needs these to find the serial port
+    usb_config.device_class = 0xEF;
+    usb_config.device_sub_class = 0x02;
+    usb_config.device_protocol = 0x01;
+    usb_config.composite_with_iads = true;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+    let mut builder = Builder::new(
+        driver,
+        usb_config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+    let mut class = CdcAcmClass::new(&mut builder, &mut state, MAX_PACKET_SIZE);
+    let mut usb = builder.build();
+
+    let console = async {
+        loop {
+            class.wait_connection().await;
+            info!("USB console connected");
+            let _ = run_console(&mut class, flash).await;
+            info!("USB console disconnected");
+        }
+    };
+    join(usb.run(), console).await;
+}
+
+async fn run_console<'d>(
+    class: &mut CdcAcmClass<'d, Driver<'d, USB>>,
+    flash: &FlashBus,
+) -> Result<(), EndpointError> {
+    let mut packet = [0; MAX_PACKET_SIZE as usize];
+    let mut line: Vec<u8, 256> = Vec::new();
+    //Set while the rows of an image are being sent
+    let mut upload: Option<ImageUpload> = None;
+    write_text(class, "Badge config, type help for commands\r\n").await?;
+
+    loop {
+        let len = class.read_packet(&mut packet).await?;
+        for byte in &packet[..len] {
+            if *byte != b'\r' && *byte != b'\n' {
+                if line.push(*byte).is_err() {
+                    line.clear();
+                    write_text(class, "error: line too long\r\n").await?;
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(current) = upload.as_mut() {
+                let row = core::str::from_utf8(&line).unwrap_or("");
+                match current.push_row(row) {
+                    Ok(true) => {
+                        write_text(class, &save_image(flash, current.image()).await).await?;
+                        upload = None;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        write_text(class, &error_reply(e)).await?;
+                        upload = None;
+                    }
+                }
+                line.clear();
+                continue;
+            }
+
+            let command = core::str::from_utf8(&line)
+                .map_err(|_| ConsoleError::UnknownCommand)
+                .and_then(Command::parse);
+            match command {
+                Ok(Command::Reboot) => {
+                    write_text(class, "rebooting\r\n").await?;
+                    //Give the host a moment to read the reply
+                    Timer::after(Duration::from_millis(100)).await;
+                    cortex_m::peripheral::SCB::sys_reset();
+                }
+                Ok(Command::Wigle) => write_wigle(class, flash).await?,
+                Ok(Command::Images) => write_text(class, &list_images().await).await?,
+                //Whoever is plugged in can change the images, so no key is needed
+                Ok(Command::Upload {
+                    name,
+                    size,
+                    position,
+                    ..
+                }) => match ImageUpload::new(name, size, position) {
+                    Ok(started) => upload = Some(started),
+                    Err(e) => write_text(class, &error_reply(e)).await?,
+                },
+                Ok(Command::Delete(name)) => {
+                    write_text(class, &delete_image(flash, name).await).await?
+                }
+                Ok(command) => write_text(class, &run_command(command)).await?,
+                Err(e) => write_text(class, &error_reply(e)).await?,
+            }
+            line.clear();
+        }
+    }
+}
+
+fn run_command(command: Command) -> String<1024> {
+    let mut reply: String<1024> = String::new();
+    let _ = match command {
+        Command::Get(key) => CONFIG.lock(|config| {
+            let config = config.borrow();
+            write_shown_value(config.as_ref().unwrap(), key, &mut reply)
+        }),
+        Command::Set(key, value) => {
+            match CONFIG.lock(|config| config.borrow_mut().as_mut().unwrap().set(key, value)) {
+                Ok(_) => {
+                    CONFIG_EDITED.store(true, core::sync::atomic::Ordering::Relaxed);
+                    reply.write_str("ok, save to keep it")
+                }
+                Err(e) => core::write!(reply, "error: {}", e),
+            }
+        }
+        Command::List => CONFIG.lock(|config| {
+            let config = config.borrow();
+            let config = config.as_ref().unwrap();
+            for key in ConfigKey::ALL {
+                let _ = core::write!(reply, "{}=", key.name());
+                let _ = write_shown_value(config, key, &mut reply);
+                let _ = reply.write_str("\r\n");
+            }
+            Ok(())
+        }),
+        Command::Save => {
+            CONFIG_CHANGED.store(true, core::sync::atomic::Ordering::Relaxed);
+            reply.write_str("saving, reboot to use it")
+        }
+        Command::Reset => {
+            CONFIG.lock(|config| config.replace(Some(default_config())));
+            CONFIG_EDITED.store(true, core::sync::atomic::Ordering::Relaxed);
+            reply.write_str("back to the .env settings, save to keep them")
+        }
+        Command::Help
+        | Command::Reboot
+        | Command::Wigle
+        | Command::Images
+        | Command::Upload { .. }
+        | Command::Delete(_) => reply.write_str(HELP),
+    };
+    if !reply.ends_with('\n') {
+        let _ = reply.write_str("\r\n");
+    }
+    reply
+}
+
+/// Writes the value of `key` for the console, secrets can be set but never read back
+fn write_shown_value(config: &Config, key: ConfigKey, out: &mut impl Write) -> core::fmt::Result {
+    if key.is_secret() {
+        out.write_str("********")
+    } else {
+        config.write_value(key, out)
+    }
+}
+
+/// Writes every access point in the wardrive log as a WiGLE CSV file, a row at a time so the flash
+/// is not held while the host reads
+async fn write_wigle<'d>(
+    class: &mut CdcAcmClass<'d, Driver<'d, USB>>,
+    flash: &FlashBus,
+) -> Result<(), EndpointError> {
+    let mut row: String<256> = String::new();
+    let _ = write_wigle_header(&mut row);
+    write_text(class, &row).await?;
+    let len = WARDRIVE_LOG.lock(|log| log.borrow().len());
+    for index in 0..len {
+        row.clear();
+        let record = {
+            let mut flash = flash.lock().await;
+            WARDRIVE_LOG.lock(|log| log.borrow().read(&mut *flash, index))
+        };
+        match record {
+            Ok(Some(record)) => {
+                let _ = write_wigle_row(&record, &mut row);
+            }
+            //Logged past the end while printing, or a slot gone bad
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Failed to read the wardrive log: {}", e);
+                break;
+            }
+        }
+        write_text(class, &row).await?;
+    }
+    Ok(())
+}
+
+/// Writes `text` a packet at a time
+async fn write_text<'d>(
+    class: &mut CdcAcmClass<'d, Driver<'d, USB>>,
+    text: &str,
+) -> Result<(), EndpointError> {
+    let mut last_len = 0;
+    for chunk in text.as_bytes().chunks(MAX_PACKET_SIZE as usize) {
+        class.write_packet(chunk).await?;
+        last_len = chunk.len();
+    }
+    //A full last packet needs an empty one after it to end the transfer
+    if last_len == MAX_PACKET_SIZE as usize {
+        class.write_packet(&[]).await?;
+    }
+    Ok(())
+}
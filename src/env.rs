@@ -1,18 +1,15 @@
-use heapless::Vec;
+use badge_core::config::Config;
 
-const ENV_DATA: &str = include_str!("../.env");
+// `ENV_VALUES`, the settings from .env as parsed and checked by build.rs
+include!(concat!(env!("OUT_DIR"), "/env_config.rs"));
 
-pub fn env_value(key: &str) -> &'static str {
-    for line in ENV_DATA.lines() {
-        let parts: Vec<&str, 2> = line.split('=').collect();
-        if parts.len() == 2 {
-            if parts[0].trim() == key {
-                let mut value = parts[1].trim().chars();
-                value.next();
-                value.next_back();
-                return value.as_str();
-            }
-        }
-    }
-    panic!("Key: {:?} not found in .env file. May also need to provide your own .env from a copy of .env.save", key);
+/// The config from the .env file the firmware was built with, used until one is saved to flash
+pub fn default_config() -> Config {
+    Config::from_env(|key| {
+        ENV_VALUES
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| *value)
+    })
+    .expect("build.rs checks the .env")
 }
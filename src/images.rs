@@ -0,0 +1,207 @@
+use core::fmt::Write;
+
+use badge_core::badge_display::display_image::DisplayImage;
+use badge_core::badge_display::event::DisplayEvent;
+use badge_core::console::Command;
+use badge_core::image_store::{ImageStore, ImageStoreError, ImageUpload};
+use defmt::*;
+use embassy_net::tcp::TcpSocket;
+use embassy_net::Stack;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Timer};
+use embedded_io_async::Write as _;
+use embedded_storage::nor_flash::NorFlash;
+use heapless::{String, Vec};
+
+use crate::badge_display::publish;
+use crate::config::config;
+use crate::FlashBus;
+
+/// Port the badge takes image uploads on over wifi
+pub const UPLOAD_PORT: u16 = 2040;
+
+/// Wait after a wrong upload key, doubled for each wrong key after it until the right one is sent
+const WRONG_KEY_DELAY: Duration = Duration::from_secs(1);
+/// Longest wait after a wrong upload key
+const MAX_WRONG_KEY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// The images the C button steps through. Main loads it at boot. An async mutex as saving and
+/// deleting erase flash while holding it, lock the flash first when both are needed
+pub static IMAGE_STORE: Mutex<CriticalSectionRawMutex, ImageStore<{ crate::IMAGE_SLOTS }>> =
+    Mutex::new(ImageStore::new(crate::IMAGE_OFFSET));
+
+/// Finds the images in flash, putting the bundled ones there if there are none
+pub async fn load_images<F: NorFlash>(flash: &mut F) {
+    let mut store = IMAGE_STORE.lock().await;
+    let result = store.load(flash).and_then(|_| {
+        if store.is_empty() {
+            for image in DisplayImage::bundled() {
+                store.save(flash, &image)?;
+            }
+        }
+        Ok::<_, ImageStoreError>(store.len())
+    });
+    match result {
+        Ok(len) => info!("{} images in flash", len),
+        Err(e) => error!("Failed to load the images: {}", e),
+    }
+}
+
+/// The `index`th image, wrapping around as images may have been deleted since it was picked.
+/// `None` if there are none
+pub async fn read_image(flash: &FlashBus, index: usize) -> Option<DisplayImage> {
+    let mut flash = flash.lock().await;
+    let store = IMAGE_STORE.lock().await;
+    if store.is_empty() {
+        return None;
+    }
+    store
+        .read(&mut *flash, index % store.len())
+        .map_err(|e| error!("Failed to read image {}: {}", index, e))
+        .ok()
+}
+
+/// Writes an uploaded image to flash, replying with where it went
+pub async fn save_image(flash: &FlashBus, image: &DisplayImage) -> String<96> {
+    let mut reply = String::new();
+    let result = {
+        let mut flash = flash.lock().await;
+        IMAGE_STORE.lock().await.save(&mut *flash, image)
+    };
+    let _ = match result {
+        Ok(index) => {
+            publish(DisplayEvent::ImagesChanged);
+            core::write!(reply, "ok, saved {} as image {}\r\n", image.name(), index)
+        }
+        Err(e) => core::write!(reply, "error: {}\r\n", e),
+    };
+    reply
+}
+
+/// Takes the image called `name` out of flash
+pub async fn delete_image(flash: &FlashBus, name: &str) -> String<96> {
+    let mut reply = String::new();
+    let result = {
+        let mut flash = flash.lock().await;
+        IMAGE_STORE.lock().await.delete(&mut *flash, name)
+    };
+    let _ = match result {
+        Ok(()) => {
+            publish(DisplayEvent::ImagesChanged);
+            reply.write_str("ok\r\n")
+        }
+        Err(e) => core::write!(reply, "error: {}\r\n", e),
+    };
+    reply
+}
+
+/// The images' names with their index, a line each
+pub async fn list_images() -> String<512> {
+    let mut reply = String::new();
+    for (index, name) in IMAGE_STORE.lock().await.names().enumerate() {
+        let _ = core::write!(reply, "{} {}\r\n", index, name);
+    }
+    reply
+}
+
+/// Takes image uploads over wifi, one per connection on [`UPLOAD_PORT`]. It is the same
+/// `upload` line ending with the `UPLOAD_KEY` followed by a line of hex per row as on the USB
+/// console. Each wrong key in a row doubles the wait before the next upload is taken
+#[embassy_executor::task]
+pub async fn run_the_image_upload(
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    flash: &'static FlashBus,
+) {
+    //Anyone on the same wifi could change the images, so only badges with a key listen
+    let key = config().upload_key;
+    if key.is_empty() {
+        info!("Wifi image uploads are off, set UPLOAD_KEY to turn them on");
+        return;
+    }
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 128];
+    let mut wrong_keys = 0;
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        //Gives up on a sender that stops partway
+        socket.set_timeout(Some(Duration::from_secs(30)));
+        if let Err(e) = socket.accept(UPLOAD_PORT).await {
+            warn!("Image upload accept failed: {:?}", e);
+            continue;
+        }
+        info!("Image upload from {:?}", socket.remote_endpoint());
+        let reply = receive_upload(&mut socket, flash, &key, &mut wrong_keys).await;
+        let _ = socket.write_all(reply.as_bytes()).await;
+        let _ = socket.flush().await;
+        socket.close();
+    }
+}
+
+/// Reads an upload off `socket` a line at a time, returning the reply. Only saved if the upload
+/// line ends with `key`, `wrong_keys` counts the uploads in a row that did not
+async fn receive_upload(
+    socket: &mut TcpSocket<'_>,
+    flash: &FlashBus,
+    key: &str,
+    wrong_keys: &mut u32,
+) -> String<96> {
+    let mut buf = [0; 256];
+    let mut line: Vec<u8, 256> = Vec::new();
+    let mut upload: Option<ImageUpload> = None;
+    loop {
+        let len = match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => return error_reply("upload ended early"),
+            Ok(len) => len,
+        };
+        for byte in &buf[..len] {
+            if *byte != b'\n' {
+                if line.push(*byte).is_err() {
+                    return error_reply("line too long");
+                }
+                continue;
+            }
+            let text = core::str::from_utf8(&line).unwrap_or("");
+            match upload.as_mut() {
+                Some(current) => match current.push_row(text) {
+                    Ok(true) => return save_image(flash, current.image()).await,
+                    Ok(false) => {}
+                    Err(e) => return error_reply(e),
+                },
+                //The first line says what is coming
+                None => match Command::parse(text) {
+                    Ok(Command::Upload {
+                        name,
+                        size,
+                        position,
+                        key: Some(sent),
+                    }) if sent == key => {
+                        *wrong_keys = 0;
+                        match ImageUpload::new(name, size, position) {
+                            Ok(started) => upload = Some(started),
+                            Err(e) => return error_reply(e),
+                        }
+                    }
+                    Ok(Command::Upload { .. }) => {
+                        *wrong_keys = wrong_keys.saturating_add(1);
+                        warn!("Image upload with the wrong key, {} in a row", *wrong_keys);
+                        //Slows down guessing, uploads are taken one at a time
+                        let doubled = WRONG_KEY_DELAY * 2u32.pow((*wrong_keys - 1).min(16));
+                        Timer::after(doubled.min(MAX_WRONG_KEY_DELAY)).await;
+                        return error_reply("wrong upload key");
+                    }
+                    Ok(_) => return error_reply("only upload works over wifi"),
+                    Err(e) => return error_reply(e),
+                },
+            }
+            line.clear();
+        }
+    }
+}
+
+/// What the console and wifi uploads reply when `error` stops a command
+pub fn error_reply(error: impl core::fmt::Display) -> String<96> {
+    let mut reply = String::new();
+    let _ = core::write!(reply, "error: {}\r\n", error);
+    reply
+}
@@ -4,56 +4,92 @@
 
 #![no_std]
 #![no_main]
-use badge_display::display_image::DisplayImage;
-use badge_display::{
-    run_the_display, RecentWifiNetworksVec, Screen, CHANGE_IMAGE, CURRENT_IMAGE, DISPLAY_CHANGED,
-    FORCE_SCREEN_REFRESH, RECENT_WIFI_NETWORKS, RTC_TIME_STRING, SCREEN_TO_SHOW, WIFI_COUNT,
+use badge_core::badge_display::event::DisplayEvent;
+use badge_core::badge_display::page::{Button, Navigation};
+use badge_core::badge_display::Screen;
+use badge_core::boot::{BootError, BootStage};
+use badge_core::bssid::{process_bssid, WifiCounting};
+use badge_core::config::{
+    read_config_from_journal, save_config_to_journal, ConfigError, MAX_CONFIG_LEN,
 };
-use core::fmt::Write;
-use core::str::from_utf8;
+use badge_core::journal::{Journal, JournalError};
+use badge_core::save::{
+    read_from_journal, read_postcard_from_flash, save_to_journal, Save, SaveError, ERASE_SIZE,
+    MAX_SAVE_LEN,
+};
+use badge_core::wardrive::{SLOTS_PER_SECTOR, SLOT_LEN};
+use badge_display::{clear_boot_error, publish, report_boot_stage, run_the_display, NAVIGATOR};
+use config::{run_the_usb_console, CONFIG, CONFIG_CHANGED};
+use cyw43::Control;
 use cyw43_driver::setup_cyw43;
 use defmt::info;
 use defmt::*;
 use embassy_executor::Spawner;
-use embassy_net::dns::DnsSocket;
-use embassy_net::tcp::client::{TcpClient, TcpClientState};
 use embassy_net::{Stack, StackResources};
 use embassy_rp::clocks::RoscRng;
-use embassy_rp::flash::Async;
+use embassy_rp::flash::{Async, Flash};
 use embassy_rp::gpio;
 use embassy_rp::gpio::Input;
-use embassy_rp::peripherals::SPI0;
-use embassy_rp::rtc::{DateTime, DayOfWeek};
+use embassy_rp::peripherals::{FLASH, SPI0};
 use embassy_rp::spi::Spi;
 use embassy_rp::spi::{self};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_time::{Duration, Timer};
-use env::env_value;
+use env::default_config;
 use gpio::{Level, Output, Pull};
-use heapless::{String, Vec};
-use helpers::easy_format;
+use images::{load_images, run_the_image_upload, IMAGE_STORE};
 use rand::RngCore;
-use reqwless::client::{HttpClient, TlsConfig, TlsVerify};
-use reqwless::request::Method;
-use save::{read_postcard_from_flash, save_postcard_to_flash, Save};
-use serde::Deserialize;
+use rtc::BadgeRtc;
+use scan::{log_discoveries, log_wardrive, record_scan_result, SCAN_HISTORY, WARDRIVE_LOG};
 use static_cell::StaticCell;
 use temp_sensor::run_the_temp_sensor;
+use time_sync::{run_the_clock, CLOCK_DRIFT_PPM};
+use wifi::run_the_wifi;
 use {defmt_rtt as _, panic_probe as _};
 
 mod badge_display;
+mod config;
 mod cyw43_driver;
 mod env;
-mod helpers;
-mod save;
+mod images;
+mod rtc;
+mod scan;
+mod sntp;
 mod temp_sensor;
+mod time_sync;
+mod wifi;
 
 type Spi0Bus = Mutex<NoopRawMutex, Spi<'static, SPI0, spi::Async>>;
+/// Main saves to flash, the USB console exports the wardrive log from it and images are read and
+/// uploaded through it
+type FlashBus = Mutex<NoopRawMutex, Flash<'static, FLASH, Async, FLASH_SIZE>>;
+/// The wifi task joins networks with it and main scans with it
+type WifiControl = Mutex<NoopRawMutex, Control<'static>>;
 
-const BSSID_LEN: usize = 1_000;
 const ADDR_OFFSET: u32 = 0x100000;
-const SAVE_OFFSET: u32 = 0x00;
+/// Where the save was before the journal, only read to carry old counts over
+const LEGACY_SAVE_OFFSET: u32 = 0x00;
+const CONFIG_OFFSET: u32 = LEGACY_SAVE_OFFSET + ERASE_SIZE as u32;
+/// The config is appended to its own journal so losing power while saving it keeps the old one
+const CONFIG_JOURNAL: Journal = Journal::new(ADDR_OFFSET + CONFIG_OFFSET, 2);
+const _: () = assert!(CONFIG_JOURNAL.capacity() >= MAX_CONFIG_LEN);
+const SAVE_OFFSET: u32 = CONFIG_OFFSET + CONFIG_JOURNAL.flash_len();
+/// The save is appended to a ring of sectors so no one sector is erased every scan
+const SAVE_JOURNAL: Journal = Journal::new(ADDR_OFFSET + SAVE_OFFSET, 16);
+//Every BSSID counted has to fit, saves bigger than a sector are split over several
+const _: () = assert!(SAVE_JOURNAL.capacity() >= MAX_SAVE_LEN);
+/// Every access point scanned goes in a ring of sectors after the save journal
+const WARDRIVE_OFFSET: u32 = ADDR_OFFSET + SAVE_OFFSET + SAVE_JOURNAL.flash_len();
+/// 8 sectors, once full the oldest sector's access points are dropped
+const WARDRIVE_SLOTS: usize = 8 * SLOTS_PER_SECTOR;
+/// Images for the C button go after the wardrive log, a sector each
+const IMAGE_OFFSET: u32 = WARDRIVE_OFFSET + (WARDRIVE_SLOTS * SLOT_LEN) as u32;
+const IMAGE_SLOTS: usize = 16;
+const _: () = assert!(
+    IMAGE_OFFSET as usize + IMAGE_SLOTS * ERASE_SIZE <= FLASH_SIZE,
+    "the images have to fit in flash"
+);
 
 const FLASH_SIZE: usize = 2 * 1024 * 1024;
 
@@ -63,7 +99,26 @@ async fn main(spawner: Spawner) {
     let mut user_led = Output::new(p.PIN_22, Level::High);
     user_led.set_high();
 
-    let (net_device, mut control) = setup_cyw43(
+    //Boot is split into stages that each report to the status line on the badge screen instead
+    //of stopping the badge when they fail
+
+    //Config is loaded first, everything else is set up from it
+    static FLASH_BUS: StaticCell<FlashBus> = StaticCell::new();
+    let flash = FLASH_BUS.init(Mutex::new(Flash::new(p.FLASH, p.DMA_CH3)));
+    let config = match read_config_from_journal(&mut *flash.lock().await, &CONFIG_JOURNAL) {
+        Ok(config) => config,
+        //Nothing saved over USB yet
+        Err(ConfigError::NotFound) => default_config(),
+        Err(e) => {
+            info!("Using the config from .env: {:?}", e);
+            report_boot_stage(BootStage::Config, Err(BootError::ConfigLoad));
+            default_config()
+        }
+    };
+    CONFIG.lock(|x| x.replace(Some(config.clone())));
+    spawner.must_spawn(run_the_usb_console(p.USB, flash));
+
+    let (net_device, control) = setup_cyw43(
         p.PIO0, p.PIN_23, p.PIN_24, p.PIN_25, p.PIN_29, p.DMA_CH0, spawner,
     )
     .await;
@@ -111,204 +166,117 @@ async fn main(spawner: Spawner) {
     //wifi setup
     let mut rng = RoscRng;
 
-    let config = embassy_net::Config::dhcpv4(Default::default());
+    let net_config = embassy_net::Config::dhcpv4(Default::default());
     let seed = rng.next_u64();
 
     // Init network stack
     static STACK: StaticCell<Stack<cyw43::NetDriver<'static>>> = StaticCell::new();
-    static RESOURCES: StaticCell<StackResources<5>> = StaticCell::new();
+    static RESOURCES: StaticCell<StackResources<6>> = StaticCell::new();
     let stack = &*STACK.init(Stack::new(
         net_device,
-        config,
-        RESOURCES.init(StackResources::<5>::new()),
+        net_config,
+        RESOURCES.init(StackResources::<6>::new()),
         seed,
     ));
     //rtc setup
-    let mut rtc = embassy_rp::rtc::Rtc::new(p.RTC);
+    let rtc = BadgeRtc(embassy_rp::rtc::Rtc::new(p.RTC));
 
-    spawner.must_spawn(net_task(stack));
-    //Attempt to connect to wifi to get RTC time loop for 2 minutes
-    let mut wifi_connection_attempts = 0;
-    let mut connected_to_wifi = false;
-
-    let wifi_ssid = env_value("WIFI_SSID");
-    let wifi_password = env_value("WIFI_PASSWORD");
-    while wifi_connection_attempts < 30 {
-        match control.join_wpa2(wifi_ssid, &wifi_password).await {
-            Ok(_) => {
-                connected_to_wifi = true;
-                info!("join successful");
-                break;
-            }
-            Err(err) => {
-                info!("join failed with status={}", err.status);
-            }
-        }
-        Timer::after(Duration::from_secs(1)).await;
-        wifi_connection_attempts += 1;
-    }
+    static WIFI_CONTROL: StaticCell<WifiControl> = StaticCell::new();
+    let control = &*WIFI_CONTROL.init(Mutex::new(control));
 
-    let mut time_was_set = false;
-    if connected_to_wifi {
-        info!("waiting for DHCP...");
-        while !stack.is_config_up() {
-            Timer::after_millis(100).await;
+    spawner.must_spawn(net_task(stack));
+    //Set up saving
+    let mut save: Save = match read_from_journal(&mut *flash.lock().await, &SAVE_JOURNAL) {
+        Ok(save) => save,
+        //Nothing in the journal yet, carry over a save from before it if there is one
+        Err(SaveError::Journal(JournalError::Empty)) => {
+            read_postcard_from_flash(ADDR_OFFSET, &mut *flash.lock().await, LEGACY_SAVE_OFFSET)
+                .unwrap_or_default()
         }
-        info!("DHCP is now up!");
-
-        info!("waiting for link up...");
-        while !stack.is_link_up() {
-            Timer::after_millis(500).await;
-        }
-        info!("Link is up!");
-
-        info!("waiting for stack to be up...");
-        stack.wait_config_up().await;
-        info!("Stack is up!");
-
-        //RTC Web request
-        let mut rx_buffer = [0; 8192];
-        let mut tls_read_buffer = [0; 16640];
-        let mut tls_write_buffer = [0; 16640];
-        let client_state = TcpClientState::<1, 1024, 1024>::new();
-        let tcp_client = TcpClient::new(stack, &client_state);
-        let dns_client = DnsSocket::new(stack);
-        let tls_config = TlsConfig::new(
-            seed,
-            &mut tls_read_buffer,
-            &mut tls_write_buffer,
-            TlsVerify::None,
-        );
-
-        let mut http_client = HttpClient::new_with_tls(&tcp_client, &dns_client, tls_config);
-        let url = env_value("TIME_API");
-        info!("connecting to {}", &url);
-
-        let mut request = match http_client.request(Method::GET, &url).await {
-            Ok(req) => req,
-            Err(e) => {
-                error!("Failed to make HTTP request: {:?}", e);
-                return; // handle the error
-            }
-        };
-
-        let response = match request.send(&mut rx_buffer).await {
-            Ok(resp) => resp,
-            Err(_e) => {
-                error!("Failed to send HTTP request");
-                return; // handle the error;
-            }
-        };
-
-        let body = match from_utf8(response.body().read_to_end().await.unwrap()) {
-            Ok(b) => b,
-            Err(_e) => {
-                error!("Failed to read response body");
-                return; // handle the error
-            }
-        };
-        info!("Response body: {:?}", &body);
-
-        let bytes = body.as_bytes();
-        match serde_json_core::de::from_slice::<TimeApiResponse>(bytes) {
-            Ok((output, _used)) => {
-                //Deadlines am i right?
-                info!("Datetime: {:?}", output.datetime);
-                //split at T
-                let datetime = output.datetime.split('T').collect::<Vec<&str, 2>>();
-                //split at -
-                let date = datetime[0].split('-').collect::<Vec<&str, 3>>();
-                let year = date[0].parse::<u16>().unwrap();
-                let month = date[1].parse::<u8>().unwrap();
-                let day = date[2].parse::<u8>().unwrap();
-                //split at :
-                let time = datetime[1].split(':').collect::<Vec<&str, 4>>();
-                let hour = time[0].parse::<u8>().unwrap();
-                let minute = time[1].parse::<u8>().unwrap();
-                //split at .
-                let second_split = time[2].split('.').collect::<Vec<&str, 2>>();
-                let second = second_split[0].parse::<f64>().unwrap();
-                let rtc_time = DateTime {
-                    year: year,
-                    month: month,
-                    day: day,
-                    day_of_week: match output.day_of_week {
-                        0 => DayOfWeek::Sunday,
-                        1 => DayOfWeek::Monday,
-                        2 => DayOfWeek::Tuesday,
-                        3 => DayOfWeek::Wednesday,
-                        4 => DayOfWeek::Thursday,
-                        5 => DayOfWeek::Friday,
-                        6 => DayOfWeek::Saturday,
-                        _ => DayOfWeek::Sunday,
-                    },
-                    hour,
-                    minute,
-                    second: second as u8,
-                };
-                rtc.set_datetime(rtc_time).unwrap();
-                time_was_set = true;
-            }
-            Err(_e) => {
-                error!("Failed to parse response body");
-                return; // handle the error
-            }
+        Err(e) => {
+            error!("Failed to load the save: {}", e);
+            let error = if e.is_corrupt() {
+                BootError::SaveCorrupt
+            } else {
+                BootError::SaveLoad
+            };
+            report_boot_stage(BootStage::Save, Err(error));
+            Save::default()
         }
+    };
+    publish(DisplayEvent::WifiCount(save.wifi_counted));
+    let mut flash_guard = flash.lock().await;
+    if let Err(e) = WARDRIVE_LOG.lock(|log| log.borrow_mut().load(&mut *flash_guard)) {
+        error!("Failed to load the wardrive log: {}", e);
     }
-
-    //Set up saving
-    let mut flash = embassy_rp::flash::Flash::<_, Async, FLASH_SIZE>::new(p.FLASH, p.DMA_CH3);
-    let mut save: Save = read_postcard_from_flash(ADDR_OFFSET, &mut flash, SAVE_OFFSET).unwrap();
-    WIFI_COUNT.store(save.wifi_counted, core::sync::atomic::Ordering::Relaxed);
+    load_images(&mut *flash_guard).await;
+    drop(flash_guard);
+    CLOCK_DRIFT_PPM.store(save.clock_drift_ppm, core::sync::atomic::Ordering::Relaxed);
     //Task spawning
+    spawner.must_spawn(run_the_clock(stack, rtc, seed));
     spawner.must_spawn(run_the_temp_sensor(p.I2C0, p.PIN_5, p.PIN_4));
-    spawner.must_spawn(run_the_display(spi_bus, cs, dc, busy, reset));
+    spawner.must_spawn(run_the_display(spi_bus, cs, dc, busy, reset, flash));
+    spawner.must_spawn(run_the_image_upload(stack, flash));
+
+    //Joins the wifi for the clock and keeps it joined, the badge is usable while it does
+    spawner.must_spawn(run_the_wifi(control, stack));
 
     //Input loop
     let cycle = Duration::from_millis(100);
     let mut current_cycle = 0;
     let mut time_to_scan = true;
+    //Index of the image picked with the C button
+    let mut image = 0;
     //5 minutes(ish) idk it's late and my math is so bad rn
     let reset_cycle = 3_000;
     //Turn off led to signify that the badge is ready
     user_led.set_low();
 
     loop {
+        if CONFIG_CHANGED.swap(false, core::sync::atomic::Ordering::Relaxed) {
+            let config = CONFIG.lock(|x| x.borrow().clone().unwrap());
+            let result = save_config_to_journal(&mut *flash.lock().await, &CONFIG_JOURNAL, &config)
+                .map_err(|e| {
+                    error!("Failed to save the config: {:?}", e);
+                    BootError::ConfigSave
+                });
+            report_boot_stage(BootStage::Config, result);
+        }
+
         //Change Image Button
         if btn_c.is_high() {
             info!("Button C pressed");
-            let current_image = CURRENT_IMAGE.load(core::sync::atomic::Ordering::Relaxed);
-            let new_image = DisplayImage::from_u8(current_image).unwrap().next();
-            CURRENT_IMAGE.store(new_image.as_u8(), core::sync::atomic::Ordering::Relaxed);
-            CHANGE_IMAGE.store(true, core::sync::atomic::Ordering::Relaxed);
-            Timer::after(Duration::from_millis(500)).await;
-            continue;
-        }
-
-        if btn_a.is_high() {
-            println!("{:?}", current_cycle);
-            info!("Button A pressed");
-            user_led.toggle();
-            Timer::after(Duration::from_millis(500)).await;
-            continue;
-        }
-
-        if btn_down.is_high() {
-            info!("Button Down pressed");
-            SCREEN_TO_SHOW.lock(|screen| {
-                screen.replace(Screen::WifiList);
-            });
-            DISPLAY_CHANGED.store(true, core::sync::atomic::Ordering::Relaxed);
+            let images = IMAGE_STORE.lock().await.len();
+            if images > 0 {
+                image = (image + 1) % images;
+                publish(DisplayEvent::Image(image));
+            }
             Timer::after(Duration::from_millis(500)).await;
             continue;
         }
 
-        if btn_up.is_high() {
-            info!("Button Up pressed");
-            SCREEN_TO_SHOW.lock(|screen| {
-                screen.replace(Screen::Badge);
-            });
-            DISPLAY_CHANGED.store(true, core::sync::atomic::Ordering::Relaxed);
+        //Up, down and A move between the pages and around them, see `Navigator`
+        let button = if btn_a.is_high() {
+            Some(Button::A)
+        } else if btn_down.is_high() {
+            Some(Button::Down)
+        } else if btn_up.is_high() {
+            Some(Button::Up)
+        } else {
+            None
+        };
+        if let Some(button) = button {
+            info!("Button {:?} pressed", button);
+            let networks = SCAN_HISTORY.lock(|history| history.borrow().len());
+            let navigation = NAVIGATOR.lock(|x| x.borrow_mut().press(button, networks));
+            match navigation {
+                Navigation::Moved(_) => publish(DisplayEvent::ScreenChanged),
+                //Moving the cursor on the wifi list is redrawn by the display without a clear
+                Navigation::Handled => publish(DisplayEvent::CursorMoved),
+                //A toggles the LED where it does nothing else
+                Navigation::Ignored if button == Button::A => user_led.toggle(),
+                Navigation::Ignored => {}
+            }
             Timer::after(Duration::from_millis(500)).await;
             continue;
         }
@@ -316,70 +284,48 @@ async fn main(spawner: Spawner) {
         if btn_b.is_high() {
             info!("Button B pressed");
 
-            SCREEN_TO_SHOW.lock(|screen| {
-                if *screen.borrow() == Screen::Badge {
-                    //IF on badge screen and b pressed reset wifi count
-                    save.wifi_counted = 0;
-                    save.bssid.clear();
-                    WIFI_COUNT.store(0, core::sync::atomic::Ordering::Relaxed);
-                    current_cycle = 0;
-                }
-            });
-
-            let mut recent_networks = RecentWifiNetworksVec::new();
-            let mut scanner = control.scan(Default::default()).await;
-            while let Some(bss) = scanner.next().await {
-                process_bssid(bss.bssid, &mut save.wifi_counted, &mut save.bssid);
-                if recent_networks.len() < 8 {
-                    let possible_ssid = core::str::from_utf8(&bss.ssid);
-                    match possible_ssid {
-                        Ok(ssid) => {
-                            info!("ssid: {}", ssid);
-                            let ssid_string = easy_format::<32>(format_args!("{}", ssid.trim()));
-                            if recent_networks.contains(&ssid_string) {
-                                continue;
-                            }
-                            let _ = recent_networks.push(ssid_string);
-                        }
-                        Err(_) => {
-                            continue;
-                        }
-                    }
-                }
+            if NAVIGATOR.lock(|x| x.borrow().screen()) == Screen::Badge {
+                //IF on badge screen and b pressed reset wifi count
+                save.wifi_counted = 0;
+                save.bssid.clear();
+                save.sketch.clear();
+                save.forgotten.clear();
+                current_cycle = 0;
             }
-            RECENT_WIFI_NETWORKS.lock(|recent_networks_vec| {
-                recent_networks_vec.replace(recent_networks);
-            });
 
-            FORCE_SCREEN_REFRESH.store(true, core::sync::atomic::Ordering::Relaxed);
+            if scan(control, &mut save, config.wifi_counting).await {
+                log_wardrive(&mut *flash.lock().await);
+                //The scan reorders the history, start the list over at the newest network
+                NAVIGATOR.lock(|x| x.borrow_mut().reset_list());
+                publish(DisplayEvent::Scanned);
+            } else {
+                info!("Joining wifi, try scanning again in a bit");
+            }
+            publish(DisplayEvent::WifiCount(save.wifi_counted));
             Timer::after(Duration::from_millis(500)).await;
 
             continue;
         }
 
-        if time_was_set {
-            let now = rtc.now();
-            match now {
-                Ok(time) => set_display_time(time),
-                Err(_) => {
-                    info!("Error getting time");
-                }
-            }
-        } else {
-            RTC_TIME_STRING.lock(|rtc_time_string| {
-                rtc_time_string.borrow_mut().clear();
-                rtc_time_string.borrow_mut().push_str("No Wifi").unwrap();
-            });
-        }
-        if time_to_scan {
-            info!("Scanning for wifi networks");
+        //Waits for the wifi task to finish joining, trying again next cycle
+        if time_to_scan && scan(control, &mut save, config.wifi_counting).await {
             time_to_scan = false;
-            let mut scanner = control.scan(Default::default()).await;
-            while let Some(bss) = scanner.next().await {
-                process_bssid(bss.bssid, &mut save.wifi_counted, &mut save.bssid);
+            info!("Scanned for wifi networks");
+            publish(DisplayEvent::WifiCount(save.wifi_counted));
+            save.clock_drift_ppm = CLOCK_DRIFT_PPM.load(core::sync::atomic::Ordering::Relaxed);
+            let mut flash = flash.lock().await;
+            log_wardrive(&mut *flash);
+            //Saving again does not bring back a save lost at boot, so that stays on screen
+            match save_to_journal(&mut *flash, &SAVE_JOURNAL, &save) {
+                Ok(len) => {
+                    info!("Saved {} of {} bytes", len, SAVE_JOURNAL.capacity());
+                    clear_boot_error(BootError::SaveWrite);
+                }
+                Err(e) => {
+                    error!("Failed to save: {}", e);
+                    report_boot_stage(BootStage::Save, Err(BootError::SaveWrite));
+                }
             }
-            WIFI_COUNT.store(save.wifi_counted, core::sync::atomic::Ordering::Relaxed);
-            save_postcard_to_flash(ADDR_OFFSET, &mut flash, SAVE_OFFSET, &save).unwrap();
             info!("wifi_counted: {}", save.wifi_counted);
         }
         if current_cycle >= reset_cycle {
@@ -391,65 +337,35 @@ async fn main(spawner: Spawner) {
     }
 }
 
-fn set_display_time(time: DateTime) {
-    let mut am = true;
-    let twelve_hour = if time.hour > 12 {
-        am = false;
-        time.hour - 12
-    } else if time.hour == 0 {
-        12
-    } else {
-        time.hour
-    };
-
-    let am_pm = if am { "AM" } else { "PM" };
-
-    let formatted_time = easy_format::<8>(format_args!(
-        "{:02}:{:02} {}",
-        twelve_hour, time.minute, am_pm
-    ));
-
-    RTC_TIME_STRING.lock(|rtc_time_string| {
-        rtc_time_string.borrow_mut().clear();
-        rtc_time_string
-            .borrow_mut()
-            .push_str(formatted_time.as_str())
-            .unwrap();
-    });
-}
-
 #[embassy_executor::task]
 async fn net_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
     stack.run().await
 }
 
-#[derive(Deserialize)]
-struct TimeApiResponse<'a> {
-    datetime: &'a str,
-    day_of_week: u8,
-}
-
-fn process_bssid(bssid: [u8; 6], wifi_counted: &mut u32, bssids: &mut Vec<String<17>, BSSID_LEN>) {
-    let bssid_str = format_bssid(bssid);
-    if !bssids.contains(&bssid_str) {
-        *wifi_counted += 1;
-        WIFI_COUNT.store(*wifi_counted, core::sync::atomic::Ordering::Relaxed);
-        // info!("bssid: {:x}", bssid_str);
-        let result = bssids.push(bssid_str);
-        if result.is_err() {
-            info!("bssid list full");
-            bssids.clear();
-        }
+/// Counts and records every access point in range. Returns false without scanning while the wifi
+/// task is joining, as the scan would have to wait for it
+async fn scan(control: &WifiControl, save: &mut Save, counting: WifiCounting) -> bool {
+    let Ok(mut control) = control.try_lock() else {
+        return false;
+    };
+    let mut scanner = control.scan(Default::default()).await;
+    while let Some(bss) = scanner.next().await {
+        count_bssid(bss.bssid, save, counting);
+        record_scan_result(&bss);
     }
+    true
 }
 
-fn format_bssid(bssid: [u8; 6]) -> String<17> {
-    let mut s = String::new();
-    for (i, byte) in bssid.iter().enumerate() {
-        if i != 0 {
-            let _ = s.write_char(':');
-        }
-        core::fmt::write(&mut s, format_args!("{:02x}", byte)).unwrap();
+fn count_bssid(bssid: [u8; 6], save: &mut Save, counting: WifiCounting) {
+    let before = save.wifi_counted;
+    if process_bssid(
+        bssid,
+        &mut save.wifi_counted,
+        &mut save.bssid,
+        &mut save.sketch,
+        &mut save.forgotten,
+        counting,
+    ) {
+        log_discoveries(save.wifi_counted.saturating_sub(before));
     }
-    s
 }
@@ -0,0 +1,43 @@
+use badge_core::time::{Clock, DateTime, DayOfWeek};
+use embassy_rp::peripherals::RTC;
+use embassy_rp::rtc::{self, Rtc, RtcError};
+
+/// Wraps the rp2040 RTC so the time logic in `badge_core` can use it as a `Clock`
+pub struct BadgeRtc(pub Rtc<'static, RTC>);
+
+impl Clock for BadgeRtc {
+    type Error = RtcError;
+
+    fn now(&self) -> Result<DateTime, Self::Error> {
+        let time = self.0.now()?;
+        Ok(DateTime {
+            year: time.year,
+            month: time.month,
+            day: time.day,
+            day_of_week: DayOfWeek::from_u8(time.day_of_week as u8).unwrap_or(DayOfWeek::Sunday),
+            hour: time.hour,
+            minute: time.minute,
+            second: time.second,
+        })
+    }
+
+    fn set_datetime(&mut self, time: DateTime) -> Result<(), Self::Error> {
+        self.0.set_datetime(rtc::DateTime {
+            year: time.year,
+            month: time.month,
+            day: time.day,
+            day_of_week: match time.day_of_week {
+                DayOfWeek::Sunday => rtc::DayOfWeek::Sunday,
+                DayOfWeek::Monday => rtc::DayOfWeek::Monday,
+                DayOfWeek::Tuesday => rtc::DayOfWeek::Tuesday,
+                DayOfWeek::Wednesday => rtc::DayOfWeek::Wednesday,
+                DayOfWeek::Thursday => rtc::DayOfWeek::Thursday,
+                DayOfWeek::Friday => rtc::DayOfWeek::Friday,
+                DayOfWeek::Saturday => rtc::DayOfWeek::Saturday,
+            },
+            hour: time.hour,
+            minute: time.minute,
+            second: time.second,
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use core::cell::RefCell;
+
+use badge_core::scan::{ScanHistory, ScanRecord, SecurityFlags, SCAN_HISTORY_LEN};
+use badge_core::stats::DiscoveryLog;
+use badge_core::wardrive::WardriveLog;
+use cyw43::BssInfo;
+use defmt::error;
+use embassy_sync::blocking_mutex::{self, raw::CriticalSectionRawMutex};
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::time_sync::{local_time, unix_time};
+
+/// Every access point the latest scans saw, for the display and exports
+pub static SCAN_HISTORY: blocking_mutex::Mutex<
+    CriticalSectionRawMutex,
+    RefCell<ScanHistory<SCAN_HISTORY_LEN>>,
+> = blocking_mutex::Mutex::new(RefCell::new(ScanHistory::new()));
+
+/// When networks were first counted, for the statistics screen
+pub static DISCOVERY_LOG: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<DiscoveryLog>> =
+    blocking_mutex::Mutex::new(RefCell::new(DiscoveryLog::new()));
+
+/// The best sighting of every access point scanned, kept in flash for WiGLE exports. Main loads
+/// it at boot
+pub static WARDRIVE_LOG: blocking_mutex::Mutex<
+    CriticalSectionRawMutex,
+    RefCell<WardriveLog<{ crate::WARDRIVE_SLOTS }>>,
+> = blocking_mutex::Mutex::new(RefCell::new(WardriveLog::new(crate::WARDRIVE_OFFSET)));
+
+/// Adds a scan result to [`SCAN_HISTORY`]
+pub fn record_scan_result(bss: &BssInfo) {
+    //Copied out as `BssInfo` is packed
+    let ssid_len = (bss.ssid_len as usize).min(bss.ssid.len());
+    let record = ScanRecord::new(
+        &bss.ssid[..ssid_len],
+        bss.bssid,
+        bss.rssi,
+        bss.chanspec,
+        SecurityFlags(bss.capability),
+        unix_time(),
+    );
+    SCAN_HISTORY.lock(|history| history.borrow_mut().record(record));
+}
+
+/// Logs `count` networks newly counted now in [`DISCOVERY_LOG`]
+pub fn log_discoveries(count: u32) {
+    DISCOVERY_LOG.lock(|log| log.borrow_mut().counted(count, local_time()));
+}
+
+/// Writes every access point in [`SCAN_HISTORY`] to [`WARDRIVE_LOG`], only new ones and ones with
+/// a stronger signal than last time take up flash
+pub fn log_wardrive<F: NorFlash>(flash: &mut F) {
+    let history = SCAN_HISTORY.lock(|history| history.borrow().clone());
+    WARDRIVE_LOG.lock(|log| {
+        let mut log = log.borrow_mut();
+        for record in history.iter() {
+            if let Err(e) = log.record(flash, record) {
+                error!("Failed to log {} to flash: {}", record.bssid, e);
+                return;
+            }
+        }
+    });
+}
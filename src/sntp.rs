@@ -0,0 +1,70 @@
+use badge_core::time::datetime_from_unix;
+use badge_core::time::sntp::{
+    decode_response, encode_request, NtpTimestamp, SntpError, NTP_PORT, PACKET_LEN,
+};
+use badge_core::time::DateTime;
+use defmt::*;
+use embassy_net::dns::DnsQueryType;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::Stack;
+use embassy_time::{with_timeout, Duration, Instant};
+
+#[derive(Debug, defmt::Format)]
+pub enum SntpClientError {
+    /// Could not look up the server's address
+    Dns,
+    Socket,
+    /// The server did not answer in time
+    Timeout,
+    Sntp(SntpError),
+}
+
+/// Asks `server` for the time, returned in UTC
+pub async fn get_sntp_time(
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    server: &str,
+) -> Result<DateTime, SntpClientError> {
+    let addresses = stack
+        .dns_query(server, DnsQueryType::A)
+        .await
+        .map_err(|_| SntpClientError::Dns)?;
+    let address = *addresses.first().ok_or(SntpClientError::Dns)?;
+    info!("SNTP server {} is {}", server, address);
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; PACKET_LEN * 2];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; PACKET_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).map_err(|_| SntpClientError::Socket)?;
+
+    //We don't know the time yet so the time since boot is used to match the reply to the request
+    let sent_at = Instant::now();
+    let request_transmit = NtpTimestamp {
+        seconds: sent_at.as_secs() as u32,
+        fraction: sent_at.as_micros() as u32,
+    };
+    socket
+        .send_to(&encode_request(request_transmit), (address, NTP_PORT))
+        .await
+        .map_err(|_| SntpClientError::Socket)?;
+
+    let mut buf = [0; PACKET_LEN * 2];
+    let (len, _) = with_timeout(Duration::from_secs(5), socket.recv_from(&mut buf))
+        .await
+        .map_err(|_| SntpClientError::Timeout)?
+        .map_err(|_| SntpClientError::Socket)?;
+    let response = decode_response(&buf[..len], request_transmit).map_err(SntpClientError::Sntp)?;
+
+    //Assume the reply took half the round trip to get back to us
+    let half_round_trip = sent_at.elapsed().as_millis() / 2;
+    let unix_millis = response.unix_millis + half_round_trip;
+    info!("SNTP stratum {} time {} ms", response.stratum, unix_millis);
+    Ok(datetime_from_unix((unix_millis / 1_000) as i64))
+}
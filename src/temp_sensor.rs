@@ -1,3 +1,4 @@
+use badge_core::badge_display::event::DisplayEvent;
 use defmt::*;
 use embassy_rp::i2c::{I2c, SclPin, SdaPin};
 use embassy_rp::peripherals::I2C0;
@@ -5,7 +6,7 @@ use embassy_rp::{i2c, Peripheral};
 use embassy_time::Timer;
 use shtcx::{self, PowerMode};
 
-use crate::badge_display::{HUMIDITY, TEMP}; // Import the necessary items from shtcx
+use crate::badge_display::publish;
 
 #[embassy_executor::task]
 pub async fn run_the_temp_sensor(
@@ -27,11 +28,11 @@ pub async fn run_the_temp_sensor(
             fahrenheit,
             combined.humidity.as_percent()
         );
-        TEMP.store(fahrenheit as u8, core::sync::atomic::Ordering::Relaxed);
-        HUMIDITY.store(
-            combined.humidity.as_percent() as u8,
-            core::sync::atomic::Ordering::Relaxed,
-        );
+        //The display only redraws if the readings changed
+        publish(DisplayEvent::Sensors {
+            temp: fahrenheit as u8,
+            humidity: combined.humidity.as_percent() as u8,
+        });
         Timer::after_secs(30).await;
     }
 }
@@ -0,0 +1,189 @@
+use core::str::from_utf8;
+use core::sync::atomic::{AtomicI32, AtomicU32};
+
+use badge_core::badge_display::event::DisplayEvent;
+use badge_core::boot::{BootError, BootStage};
+use badge_core::config::Config;
+use badge_core::time::format::CLOCK_STRING_LEN;
+use badge_core::time::sync::{ClockSync, SyncConfig};
+use badge_core::time::{parse_time_api_response, unix_from_datetime, Clock, DateTime, TimeSource};
+use defmt::*;
+use embassy_net::dns::DnsSocket;
+use embassy_net::tcp::client::{TcpClient, TcpClientState};
+use embassy_net::Stack;
+use embassy_time::{Duration, Instant, Timer};
+use heapless::String;
+use reqwless::client::{HttpClient, TlsConfig, TlsVerify};
+use reqwless::request::Method;
+
+use crate::badge_display::{publish, report_boot_stage};
+use crate::config::{config, CONFIG_EDITED};
+use crate::rtc::BadgeRtc;
+use crate::sntp::get_sntp_time;
+
+/// Measured RTC drift, loaded from and saved to `Save::clock_drift_ppm` by the main loop
+pub static CLOCK_DRIFT_PPM: AtomicI32 = AtomicI32::new(0);
+/// Drift corrected UTC in unix seconds as of the last tick of the clock task, 0 until synced
+static UNIX_TIME: AtomicU32 = AtomicU32::new(0);
+
+/// Seconds the time zone is ahead of UTC as of the last tick of the clock task
+static UTC_OFFSET: AtomicI32 = AtomicI32::new(0);
+
+/// The time for timestamping things, `None` until the clock has been synced
+pub fn unix_time() -> Option<u32> {
+    match UNIX_TIME.load(core::sync::atomic::Ordering::Relaxed) {
+        0 => None,
+        time => Some(time),
+    }
+}
+
+/// [`unix_time`] shifted into the time zone, so whole days start at local midnight
+pub fn local_time() -> Option<i64> {
+    unix_time()
+        .map(|time| time as i64 + UTC_OFFSET.load(core::sync::atomic::Ordering::Relaxed) as i64)
+}
+
+/// Keeps the RTC synced to UTC and the local time on the display up to date. Retries with a
+/// backoff until the first sync works, then resyncs every few hours to measure and correct the
+/// RTC's drift. Time zone and clock format changes from the console show up on the next tick
+#[embassy_executor::task]
+pub async fn run_the_clock(
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    mut rtc: BadgeRtc,
+    seed: u64,
+) {
+    let mut settings = config();
+    let mut time_zone = settings.time_zone();
+    let mut clock_sync = ClockSync::new(
+        SyncConfig::default(),
+        CLOCK_DRIFT_PPM.load(core::sync::atomic::Ordering::Relaxed),
+    );
+    let mut next_sync = Instant::now();
+    //What the display was last sent
+    let mut shown_time: String<CLOCK_STRING_LEN> = String::new();
+
+    loop {
+        if CONFIG_EDITED.swap(false, core::sync::atomic::Ordering::Relaxed) {
+            settings = config();
+            time_zone = settings.time_zone();
+        }
+
+        if Instant::now() >= next_sync {
+            match fetch_time(stack, &settings, seed).await {
+                Ok(time) => {
+                    info!("Datetime: {:?}", time);
+                    let rtc_before = rtc.now().ok();
+                    match rtc.set_datetime(time) {
+                        Ok(_) => {
+                            let drift = clock_sync.synced(rtc_before, time);
+                            info!("RTC drift: {} ppm", drift);
+                            CLOCK_DRIFT_PPM.store(drift, core::sync::atomic::Ordering::Relaxed);
+                            report_boot_stage(BootStage::Clock, Ok(()));
+                        }
+                        Err(e) => {
+                            error!("Failed to set the RTC: {:?}", e);
+                            clock_sync.sync_failed();
+                            report_boot_stage(BootStage::Clock, Err(BootError::RtcSet));
+                        }
+                    }
+                }
+                Err(e) => {
+                    clock_sync.sync_failed();
+                    report_boot_stage(BootStage::Clock, Err(e));
+                }
+            }
+            let wait = clock_sync.next_attempt_in();
+            info!("Next time sync in {} seconds", wait);
+            next_sync = Instant::now() + Duration::from_secs(wait as u64);
+        }
+
+        if clock_sync.is_synced() {
+            match rtc.now() {
+                Ok(time) => {
+                    let utc = clock_sync.corrected(time);
+                    let local = time_zone.to_local(&utc);
+                    let unix = unix_from_datetime(&utc);
+                    UTC_OFFSET.store(
+                        (unix_from_datetime(&local) - unix) as i32,
+                        core::sync::atomic::Ordering::Relaxed,
+                    );
+                    UNIX_TIME.store(unix as u32, core::sync::atomic::Ordering::Relaxed);
+                    show_time(&mut shown_time, settings.clock_format.format(&local));
+                }
+                Err(_) => {
+                    info!("Error getting time");
+                }
+            }
+        } else {
+            show_time(&mut shown_time, String::try_from("No Wifi").unwrap());
+        }
+        Timer::after(Duration::from_secs(1)).await;
+    }
+}
+
+/// Sends `time` to the display if it is not what it already shows, without seconds it only
+/// changes once a minute
+fn show_time(shown_time: &mut String<CLOCK_STRING_LEN>, time: String<CLOCK_STRING_LEN>) {
+    if *shown_time != time {
+        *shown_time = time.clone();
+        publish(DisplayEvent::Time(time));
+    }
+}
+
+async fn fetch_time(
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    config: &Config,
+    seed: u64,
+) -> Result<DateTime, BootError> {
+    if !stack.is_link_up() || !stack.is_config_up() {
+        return Err(BootError::TimeNoNetwork);
+    }
+
+    if config.time_source == TimeSource::Sntp {
+        let sntp_server = config.sntp_server.as_str();
+        info!("getting time from {}", sntp_server);
+        return get_sntp_time(stack, sntp_server).await.map_err(|e| {
+            error!("Failed to get the time over SNTP: {:?}", e);
+            BootError::Sntp
+        });
+    }
+
+    //RTC Web request
+    let mut rx_buffer = [0; 8192];
+    let mut tls_read_buffer = [0; 16640];
+    let mut tls_write_buffer = [0; 16640];
+    let client_state = TcpClientState::<1, 1024, 1024>::new();
+    let tcp_client = TcpClient::new(stack, &client_state);
+    let dns_client = DnsSocket::new(stack);
+    let tls_config = TlsConfig::new(
+        seed,
+        &mut tls_read_buffer,
+        &mut tls_write_buffer,
+        TlsVerify::None,
+    );
+
+    let mut http_client = HttpClient::new_with_tls(&tcp_client, &dns_client, tls_config);
+    let url = config.time_api.as_str();
+    info!("connecting to {}", &url);
+
+    let mut request = http_client.request(Method::GET, &url).await.map_err(|e| {
+        error!("Failed to make HTTP request: {:?}", e);
+        BootError::TimeHttpRequest
+    })?;
+
+    let response = request.send(&mut rx_buffer).await.map_err(|_e| {
+        error!("Failed to send HTTP request");
+        BootError::TimeHttpSend
+    })?;
+
+    let body = response.body().read_to_end().await.map_err(|_e| {
+        error!("Failed to read response body");
+        BootError::TimeHttpBody
+    })?;
+    info!(
+        "Response body: {:?}",
+        from_utf8(body).unwrap_or("<not utf8>")
+    );
+
+    parse_time_api_response(body).map_err(BootError::TimeApi)
+}
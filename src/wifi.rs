@@ -0,0 +1,122 @@
+use badge_core::boot::{BootError, BootStage};
+use badge_core::wifi::{Reconnect, ScanRanking, WifiNetwork, WifiSecurity};
+use cyw43::Control;
+use defmt::*;
+use embassy_net::Stack;
+use embassy_time::{with_timeout, Duration, Timer};
+
+use crate::badge_display::report_boot_stage;
+use crate::config::config;
+use crate::WifiControl;
+
+/// How long DHCP gets to hand out an address after joining
+const DHCP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Times every saved network is tried at boot before giving up until the next reconnect, the same
+/// 30 tries a second apart the badge has always made
+const BOOT_JOIN_ROUNDS: u32 = 30;
+/// How often the link is checked while it is up
+const LINK_CHECK: Duration = Duration::from_secs(10);
+
+/// Joins the wifi at boot and again whenever the link drops, backing off while no saved network
+/// can be joined. Main only scans when this is not joining, so the buttons never wait on it
+#[embassy_executor::task]
+pub async fn run_the_wifi(
+    control: &'static WifiControl,
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+) {
+    let networks = config().wifi_networks;
+    let mut reconnect = Reconnect::new();
+    let mut rounds = BOOT_JOIN_ROUNDS;
+    loop {
+        if !(stack.is_link_up() && stack.is_config_up()) {
+            info!("Connecting to wifi");
+            let result = bring_up(control, stack, &networks, rounds).await;
+            match result {
+                Ok(()) => reconnect.connected(),
+                Err(_) => reconnect.failed(),
+            }
+            report_boot_stage(BootStage::Wifi, result);
+            rounds = 1;
+        }
+        let backoff = Duration::from_secs(reconnect.next_attempt_in() as u64);
+        Timer::after(backoff.max(LINK_CHECK)).await;
+    }
+}
+
+/// Joins one saved network with its security
+pub async fn join(control: &mut Control<'_>, network: &WifiNetwork) -> bool {
+    let ssid = network.ssid.as_str();
+    let password = network.password.as_str();
+    let result = match network.security() {
+        WifiSecurity::Open => control.join_open(ssid).await,
+        WifiSecurity::Wpa3 => control.join_wpa3(ssid, password).await,
+        WifiSecurity::Wpa2 | WifiSecurity::Auto => control.join_wpa2(ssid, password).await,
+    };
+    match result {
+        Ok(_) => {
+            info!("joined {}", ssid);
+            true
+        }
+        Err(err) => {
+            info!("join {} failed with status={}", ssid, err.status);
+            false
+        }
+    }
+}
+
+/// Scans and joins the strongest saved network in range, falling back to the rest in priority
+/// order. Returns the index of the joined network
+pub async fn connect(control: &mut Control<'_>, networks: &[WifiNetwork]) -> Option<usize> {
+    let mut ranking = ScanRanking::new();
+    let mut scanner = control.scan(Default::default()).await;
+    while let Some(bss) = scanner.next().await {
+        let ssid_len = (bss.ssid_len as usize).min(bss.ssid.len());
+        ranking.saw(networks, &bss.ssid[..ssid_len], bss.rssi);
+    }
+    drop(scanner);
+
+    for index in ranking.join_order(networks) {
+        if join(control, &networks[index]).await {
+            return Some(index);
+        }
+    }
+    None
+}
+
+/// Tries every saved network up to `rounds` times, then waits for DHCP. `control` is only held
+/// while joining
+async fn bring_up(
+    control: &WifiControl,
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    networks: &[WifiNetwork],
+    rounds: u32,
+) -> Result<(), BootError> {
+    let mut joined = false;
+    for round in 0..rounds {
+        if round > 0 {
+            Timer::after(Duration::from_secs(1)).await;
+        }
+        if connect(&mut *control.lock().await, networks)
+            .await
+            .is_some()
+        {
+            joined = true;
+            break;
+        }
+    }
+    if !joined {
+        return Err(BootError::WifiJoin);
+    }
+
+    info!("waiting for DHCP...");
+    with_timeout(DHCP_TIMEOUT, async {
+        stack.wait_config_up().await;
+        while !stack.is_link_up() {
+            Timer::after_millis(500).await;
+        }
+    })
+    .await
+    .map_err(|_| BootError::Dhcp)?;
+    info!("Stack is up!");
+    Ok(())
+}